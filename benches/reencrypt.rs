@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use swords::entity::{record::Record, Swd};
+
+const RECORD_COUNT: usize = 10_000;
+
+/// Builds an unlocked vault of `RECORD_COUNT` records, returning it along with its key.
+/// Blake3 keeps the setup fast, the key derivation is not what is measured.
+fn synthetic_vault() -> (Swd, Vec<u8>) {
+    let mut swd = Swd::create(b"master key", "aes256-gcm", "blake3");
+    let key = swd.header().get_key().unwrap().clone();
+    let mut records = vec![];
+    {
+        let (encrypt, _) = swd.get_key_cipher();
+        for i in 0..RECORD_COUNT {
+            let secret = format!("secret {}", i);
+            let record = Record::encrypted(
+                format!("record {}", i),
+                secret.as_bytes(),
+                encrypt,
+                &key,
+                swd.nonce_size(),
+            )
+            .unwrap();
+            records.push(record);
+        }
+    }
+    for record in records {
+        swd.get_root_mut().add_record(record);
+    }
+    (swd, key)
+}
+
+fn reencrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("reencrypt_all");
+    group.sample_size(10);
+    // Re-encrypting under the same key leaves the vault valid for the next iteration
+    let (mut swd, key) = synthetic_vault();
+
+    group.bench_function("serial", |b| {
+        b.iter(|| swd.reencrypt_all(&key, &key).unwrap())
+    });
+    #[cfg(feature = "parallel")]
+    group.bench_function("parallel", |b| {
+        b.iter(|| swd.par_reencrypt_all(&key, &key).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, reencrypt);
+criterion_main!(benches);
@@ -1,88 +1,374 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
 
 use aes_gcm::{
-    aead::{generic_array::GenericArray, Aead},
-    Aes256Gcm, KeyInit, KeySizeUser, Nonce,
+    aead::{generic_array::GenericArray, Aead, AeadCore, Payload},
+    Aes128Gcm, Aes256Gcm, KeyInit, KeySizeUser, Nonce,
 };
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
-use crate::error::CipherError;
+use crate::error::{CipherError, UnknownNameError};
 
 pub type CipherResult<T> = Result<T, CipherError>;
-pub type EncryptFn = dyn Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>>;
-pub type DecryptFn = dyn Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>>;
-pub type Cipher<'a> = (&'a Box<EncryptFn>, &'a Box<DecryptFn>);
+pub type EncryptFn =
+    dyn Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>> + Send + Sync;
+pub type DecryptFn =
+    dyn Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>> + Send + Sync;
+pub type CipherFns<'a> = (&'a Box<EncryptFn>, &'a Box<DecryptFn>);
+
+/// Symmetric cipher along with the key and nonce sizes it expects.
+/// The nonce and any other parameters are passed through `extras`,
+/// associated data to authenticate included under `aad`.
+pub trait Cipher: Send + Sync {
+    fn encrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>>;
+
+    fn decrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>>;
+
+    fn key_size(&self) -> usize;
+
+    fn nonce_size(&self) -> usize;
+
+    fn generate_nonce(&self) -> Vec<u8> {
+        random_nonce(self.nonce_size())
+    }
+}
+
+/// A registered cipher along with the key to use it with, see
+/// `CipherRegistry::bind`
+pub struct BoundCipher<'a> {
+    pub encrypt_fn: &'a Box<EncryptFn>,
+    pub decrypt_fn: &'a Box<DecryptFn>,
+    pub key: Zeroizing<Vec<u8>>,
+    pub nonce_size: usize,
+}
+
+/// A registered cipher along with its methods boxed as functions, for
+/// callers still passing around encryption and decryption functions
+struct CipherEntry {
+    cipher: Arc<dyn Cipher>,
+    encrypt_fn: Box<EncryptFn>,
+    decrypt_fn: Box<DecryptFn>,
+}
 
 pub struct CipherRegistry {
-    encrypt_functions: HashMap<String, Box<EncryptFn>>,
-    decrypt_functions: HashMap<String, Box<EncryptFn>>,
+    ciphers: HashMap<String, CipherEntry>,
 }
 
 impl CipherRegistry {
     pub fn new() -> Self {
         Self {
-            encrypt_functions: HashMap::new(),
-            decrypt_functions: HashMap::new(),
+            ciphers: HashMap::new(),
         }
     }
 
-    pub fn register(&mut self, name: &str, encrypt_fn: Box<EncryptFn>, decrypt_fn: Box<DecryptFn>) {
-        self.encrypt_functions.insert(name.to_owned(), encrypt_fn);
-        self.decrypt_functions.insert(name.to_owned(), decrypt_fn);
+    pub fn register_cipher(&mut self, name: &str, cipher: Box<dyn Cipher>) {
+        let cipher: Arc<dyn Cipher> = cipher.into();
+        let encrypt_cipher = cipher.clone();
+        let decrypt_cipher = cipher.clone();
+        let entry = CipherEntry {
+            cipher,
+            encrypt_fn: Box::new(move |data, key, extras| {
+                encrypt_cipher.encrypt(data, key, extras)
+            }),
+            decrypt_fn: Box::new(move |data, key, extras| {
+                decrypt_cipher.decrypt(data, key, extras)
+            }),
+        };
+        self.ciphers.insert(name.to_owned(), entry);
+    }
+
+    /// Registers a cipher from its encryption and decryption functions,
+    /// taking keys of exactly `key_size` bytes and nonces of exactly
+    /// `nonce_size` bytes
+    pub fn register(
+        &mut self,
+        name: &str,
+        key_size: usize,
+        nonce_size: usize,
+        encrypt_fn: Box<EncryptFn>,
+        decrypt_fn: Box<DecryptFn>,
+    ) {
+        let cipher = FnCipher {
+            key_size,
+            nonce_size,
+            encrypt_fn,
+            decrypt_fn,
+        };
+        self.register_cipher(name, Box::new(cipher));
+    }
+
+    pub fn get(&self, name: &str) -> &dyn Cipher {
+        self.ciphers.get(name).unwrap().cipher.as_ref()
+    }
+
+    pub fn get_key_size(&self, name: &str) -> usize {
+        self.get(name).key_size()
+    }
+
+    pub fn get_nonce_size(&self, name: &str) -> usize {
+        self.get(name).nonce_size()
+    }
+
+    /// Generates a random nonce of the size the cipher `name` expects
+    pub fn generate_nonce(&self, name: &str) -> Vec<u8> {
+        self.get(name).generate_nonce()
     }
 
     pub fn get_encryptor(&self, name: &str) -> &Box<EncryptFn> {
-        self.encrypt_functions.get(name).unwrap()
+        &self.ciphers.get(name).unwrap().encrypt_fn
     }
 
     pub fn get_decryptor(&self, name: &str) -> &Box<DecryptFn> {
-        self.decrypt_functions.get(name).unwrap()
+        &self.ciphers.get(name).unwrap().decrypt_fn
     }
 
     pub fn get_names(&self) -> Vec<&String> {
-        self.encrypt_functions.keys().collect()
+        self.ciphers.keys().collect()
+    }
+
+    /// Binds the cipher `name` to its key in a vault whose key cipher is
+    /// `key_cipher` and whose key is `vault_key`. The key cipher takes the
+    /// vault key as is, any other cipher a key derived from it and the
+    /// cipher name, so that no two ciphers share a key.
+    pub fn bind(
+        &self,
+        name: &str,
+        key_cipher: &str,
+        vault_key: &[u8],
+    ) -> CipherResult<BoundCipher<'_>> {
+        let entry = self
+            .ciphers
+            .get(name)
+            .ok_or_else(|| CipherError::UnknownCipher(name.to_owned()))?;
+        let key = if name == key_cipher {
+            Zeroizing::new(vault_key.to_vec())
+        } else {
+            derive_cipher_key(vault_key, name, entry.cipher.key_size())?
+        };
+        Ok(BoundCipher {
+            encrypt_fn: &entry.encrypt_fn,
+            decrypt_fn: &entry.decrypt_fn,
+            key,
+            nonce_size: entry.cipher.nonce_size(),
+        })
+    }
+
+    /// Checks that a cipher is registered as `name`
+    pub fn validate_name(&self, name: &str) -> Result<(), UnknownNameError> {
+        if self.ciphers.contains_key(name) {
+            return Ok(());
+        }
+        let mut valid_names: Vec<String> = self.get_names().into_iter().cloned().collect();
+        valid_names.sort();
+        Err(UnknownNameError {
+            name: name.to_owned(),
+            valid_names,
+        })
     }
 }
 
 impl Default for CipherRegistry {
     fn default() -> Self {
         let mut registry = CipherRegistry::new();
-        registry.register("aes256-gcm", Box::new(aes_encrypt), Box::new(aes_decrypt));
+        registry.register_cipher("aes256-gcm", Box::new(AesGcm::<Aes256Gcm>::new()));
+        registry.register_cipher("aes128-gcm", Box::new(AesGcm::<Aes128Gcm>::new()));
         registry
     }
 }
 
-fn aes_encrypt(
+/// Cipher made of separate encryption and decryption functions
+struct FnCipher {
+    key_size: usize,
+    nonce_size: usize,
+    encrypt_fn: Box<EncryptFn>,
+    decrypt_fn: Box<DecryptFn>,
+}
+
+impl Cipher for FnCipher {
+    fn encrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>> {
+        (self.encrypt_fn)(data, key, extras)
+    }
+
+    fn decrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>> {
+        (self.decrypt_fn)(data, key, extras)
+    }
+
+    fn key_size(&self) -> usize {
+        self.key_size
+    }
+
+    fn nonce_size(&self) -> usize {
+        self.nonce_size
+    }
+}
+
+/// Context of the keys derived for ciphers other than the key cipher
+const CIPHER_KEY_CONTEXT: &[u8] = b"swords cipher key";
+
+/// HMAC-SHA256 of the context and `cipher` under `vault_key`, truncated
+/// to `key_size` bytes, which cannot exceed the 32 bytes of the MAC
+fn derive_cipher_key(
+    vault_key: &[u8],
+    cipher: &str,
+    key_size: usize,
+) -> CipherResult<Zeroizing<Vec<u8>>> {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(vault_key).expect("HMAC accepts keys of any length");
+    mac.update(CIPHER_KEY_CONTEXT);
+    mac.update(cipher.as_bytes());
+    let mut key = Zeroizing::new(mac.finalize().into_bytes().to_vec());
+    if key_size > key.len() {
+        return Err(CipherError::InvalidKeyLength(key_size));
+    }
+    key.truncate(key_size);
+    Ok(key)
+}
+
+pub const AES_GCM_NONCE_LENGTH: usize = 12;
+/// Extra holding the associated data authenticated along with the ciphertext
+pub const AAD_EXTRA: &str = "aad";
+
+/// Generates `nonce_size` random bytes to use as a nonce
+pub fn random_nonce(nonce_size: usize) -> Vec<u8> {
+    let mut nonce = vec![0; nonce_size];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// AES in Galois/Counter Mode, with the key size of `C`, taking its
+/// nonce from the `nonce` extra and associated data from the `aad` extra
+pub struct AesGcm<C> {
+    _cipher: PhantomData<fn() -> C>,
+}
+
+impl<C> AesGcm<C> {
+    pub fn new() -> Self {
+        Self {
+            _cipher: PhantomData,
+        }
+    }
+}
+
+impl<C> Default for AesGcm<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Aead + KeyInit> Cipher for AesGcm<C> {
+    fn encrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        mut extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>> {
+        // Missing associated data is the same as empty for AES-GCM
+        let aad = extras.remove(AAD_EXTRA).unwrap_or_default();
+        let (cipher, nonce) = aes_gcm_cipher_and_nonce::<C>(key, extras)?;
+        let encrypted = cipher.encrypt(nonce, Payload { msg: data, aad });
+        encrypted.map_err(|_| CipherError::EncryptionError)
+    }
+
+    fn decrypt(
+        &self,
+        data: &[u8],
+        key: &[u8],
+        mut extras: HashMap<String, &[u8]>,
+    ) -> CipherResult<Vec<u8>> {
+        // Missing associated data is the same as empty for AES-GCM
+        let aad = extras.remove(AAD_EXTRA).unwrap_or_default();
+        let (cipher, nonce) = aes_gcm_cipher_and_nonce::<C>(key, extras)?;
+        let decrypted = cipher.decrypt(nonce, Payload { msg: data, aad });
+        decrypted.map_err(|_| CipherError::EncryptionError)
+    }
+
+    fn key_size(&self) -> usize {
+        C::key_size()
+    }
+
+    fn nonce_size(&self) -> usize {
+        AES_GCM_NONCE_LENGTH
+    }
+}
+
+pub fn aes_encrypt(
     data: &[u8],
     key: &[u8],
-    mut extras: HashMap<String, &[u8]>,
+    extras: HashMap<String, &[u8]>,
 ) -> CipherResult<Vec<u8>> {
-    let key = GenericArray::<u8, <Aes256Gcm as KeySizeUser>::KeySize>::from_slice(key);
-    let cipher = Aes256Gcm::new(&key);
-    let nonce = extras
-        .remove("nonce")
-        .ok_or(CipherError::MissingRequiredExtra("nonce".to_owned()))?;
-    let encrypted = cipher.encrypt(Nonce::from_slice(nonce), data);
-    encrypted.map_err(|_| CipherError::EncryptionError)
+    AesGcm::<Aes256Gcm>::new().encrypt(data, key, extras)
 }
 
-fn aes_decrypt(
+pub fn aes_decrypt(
     data: &[u8],
     key: &[u8],
-    mut extras: HashMap<String, &[u8]>,
+    extras: HashMap<String, &[u8]>,
 ) -> CipherResult<Vec<u8>> {
-    let key = GenericArray::<u8, <Aes256Gcm as KeySizeUser>::KeySize>::from_slice(key);
-    let cipher = Aes256Gcm::new(&key);
+    AesGcm::<Aes256Gcm>::new().decrypt(data, key, extras)
+}
+
+pub fn aes128_encrypt(
+    data: &[u8],
+    key: &[u8],
+    extras: HashMap<String, &[u8]>,
+) -> CipherResult<Vec<u8>> {
+    AesGcm::<Aes128Gcm>::new().encrypt(data, key, extras)
+}
+
+pub fn aes128_decrypt(
+    data: &[u8],
+    key: &[u8],
+    extras: HashMap<String, &[u8]>,
+) -> CipherResult<Vec<u8>> {
+    AesGcm::<Aes128Gcm>::new().decrypt(data, key, extras)
+}
+
+/// Checks the key and nonce lengths up front since
+/// `from_slice` panics on a length mismatch
+fn aes_gcm_cipher_and_nonce<'a, C: AeadCore + KeyInit>(
+    key: &[u8],
+    mut extras: HashMap<String, &'a [u8]>,
+) -> CipherResult<(C, &'a Nonce<C::NonceSize>)> {
+    if key.len() != C::key_size() {
+        return Err(CipherError::InvalidKeyLength(key.len()));
+    }
     let nonce = extras
         .remove("nonce")
         .ok_or(CipherError::MissingRequiredExtra("nonce".to_owned()))?;
-    let encrypted = cipher.decrypt(Nonce::from_slice(nonce), data);
-    encrypted.map_err(|_| CipherError::EncryptionError)
+    if nonce.len() != AES_GCM_NONCE_LENGTH {
+        return Err(CipherError::InvalidNonceLength(nonce.len()));
+    }
+
+    let key = GenericArray::<u8, C::KeySize>::from_slice(key);
+    Ok((C::new(key), Nonce::<C::NonceSize>::from_slice(nonce)))
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        cipher::{aes_encrypt, CipherRegistry},
+        cipher::{aes_encrypt, AesGcm, Cipher, CipherRegistry, CipherResult},
         error::CipherError,
     };
     use aes_gcm::{Aes256Gcm, KeySizeUser};
@@ -93,8 +379,8 @@ mod tests {
     #[test]
     fn aes_encrypt_ok() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
@@ -107,8 +393,8 @@ mod tests {
     #[test]
     fn aes_encrypt_missing_nonce() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
@@ -123,8 +409,8 @@ mod tests {
     #[test]
     fn aes_decrypt_ok() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
@@ -141,8 +427,8 @@ mod tests {
     #[test]
     fn aes_decrypt_missing_nonce() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
@@ -161,24 +447,50 @@ mod tests {
     #[test]
     fn registry_encrypt_ok() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
         let mut extras = HashMap::new();
         extras.insert("nonce".to_owned(), nonce);
         let registry = CipherRegistry::default();
-        let encrypt = registry.get_encryptor("aes-gcm");
+        let encrypt = registry.get_encryptor("aes256-gcm");
         let result = encrypt(data, key, extras);
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn bind_derives_a_key_per_cipher() {
+        let registry = CipherRegistry::default();
+        let vault_key = [7; 16];
+
+        let bound = registry
+            .bind("aes128-gcm", "aes128-gcm", &vault_key)
+            .unwrap();
+        assert_eq!(&bound.key[..], &vault_key);
+
+        let bound = registry
+            .bind("aes256-gcm", "aes128-gcm", &vault_key)
+            .unwrap();
+        assert_eq!(bound.key.len(), 32);
+        assert_ne!(&bound.key[..16], &vault_key);
+        let again = registry
+            .bind("aes256-gcm", "aes128-gcm", &vault_key)
+            .unwrap();
+        assert_eq!(bound.key, again.key);
+
+        assert_eq!(
+            registry.bind("des", "aes128-gcm", &vault_key).err(),
+            Some(CipherError::UnknownCipher("des".to_owned()))
+        );
+    }
+
     #[test]
     fn registry_decrypt_ok() {
         let key: &mut [u8] = &mut [0u8; 32];
-        for i in 0..32 {
-            key[i] = i as u8;
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = i as u8;
         }
         let data = b"Example dummy data";
         let nonce: &[u8] = b"dummy nonce ";
@@ -187,10 +499,159 @@ mod tests {
         let result = aes_encrypt(data, key, extras.clone());
         let encrypted = result.unwrap();
         let registry = CipherRegistry::default();
-        let decrypt = registry.get_decryptor("aes-gcm");
+        let decrypt = registry.get_decryptor("aes256-gcm");
         let result = decrypt(&encrypted, key, extras);
         assert!(result.is_ok());
         let decrypted = result.unwrap();
         assert_eq!(&decrypted, data);
     }
+
+    #[test]
+    fn aes_invalid_nonce_length() {
+        let key = [0u8; 32];
+        let nonce: &[u8] = &[0u8; 11];
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), nonce);
+        let result = aes_encrypt(b"Example dummy data", &key, extras.clone());
+        assert_eq!(result, Err(CipherError::InvalidNonceLength(11)));
+        let result = aes_decrypt(b"Example dummy data", &key, extras);
+        assert_eq!(result, Err(CipherError::InvalidNonceLength(11)));
+    }
+
+    #[test]
+    fn aes_invalid_key_length() {
+        let key = [0u8; 16];
+        let nonce: &[u8] = b"dummy nonce ";
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), nonce);
+        let result = aes_encrypt(b"Example dummy data", &key, extras.clone());
+        assert_eq!(result, Err(CipherError::InvalidKeyLength(16)));
+        let result = aes_decrypt(b"Example dummy data", &key, extras);
+        assert_eq!(result, Err(CipherError::InvalidKeyLength(16)));
+    }
+
+    #[test]
+    fn registry_aes128_roundtrip() {
+        let key = [7u8; 16];
+        let data = b"Example dummy data";
+        let nonce: &[u8] = b"dummy nonce ";
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), nonce);
+        let registry = CipherRegistry::default();
+        assert_eq!(registry.get_key_size("aes128-gcm"), 16);
+        assert_eq!(registry.get_key_size("aes256-gcm"), 32);
+        let encrypt = registry.get_encryptor("aes128-gcm");
+        let decrypt = registry.get_decryptor("aes128-gcm");
+        let encrypted = encrypt(data, &key, extras.clone()).unwrap();
+        let decrypted = decrypt(&encrypted, &key, extras.clone()).unwrap();
+        assert_eq!(&decrypted, data);
+        let result = encrypt(data, &[7u8; 32], extras);
+        assert_eq!(result, Err(CipherError::InvalidKeyLength(32)));
+    }
+
+    #[test]
+    fn registry_generates_nonces_of_cipher_size() {
+        let registry = CipherRegistry::default();
+        for name in registry.get_names() {
+            let nonce = registry.generate_nonce(name);
+            assert_eq!(nonce.len(), registry.get_nonce_size(name));
+            let key = vec![7u8; registry.get_key_size(name)];
+            let mut extras = HashMap::new();
+            extras.insert("nonce".to_owned(), &nonce[..]);
+            assert!(registry.get_encryptor(name)(b"data", &key, extras).is_ok());
+        }
+        assert_ne!(
+            registry.generate_nonce("aes256-gcm"),
+            registry.generate_nonce("aes256-gcm")
+        );
+    }
+
+    struct XorCipher;
+
+    impl Cipher for XorCipher {
+        fn encrypt(
+            &self,
+            data: &[u8],
+            key: &[u8],
+            _extras: HashMap<String, &[u8]>,
+        ) -> CipherResult<Vec<u8>> {
+            Ok(data.iter().map(|byte| byte ^ key[0]).collect())
+        }
+
+        fn decrypt(
+            &self,
+            data: &[u8],
+            key: &[u8],
+            extras: HashMap<String, &[u8]>,
+        ) -> CipherResult<Vec<u8>> {
+            self.encrypt(data, key, extras)
+        }
+
+        fn key_size(&self) -> usize {
+            1
+        }
+
+        fn nonce_size(&self) -> usize {
+            0
+        }
+    }
+
+    #[test]
+    fn aes_gcm_cipher_roundtrip() {
+        let cipher = AesGcm::<Aes256Gcm>::new();
+        assert_eq!(cipher.key_size(), Aes256Gcm::key_size());
+        let key = [7u8; 32];
+        let nonce = cipher.generate_nonce();
+        assert_eq!(nonce.len(), cipher.nonce_size());
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), &nonce[..]);
+        let encrypted = cipher.encrypt(b"data", &key, extras.clone()).unwrap();
+        assert_eq!(cipher.decrypt(&encrypted, &key, extras).unwrap(), b"data");
+    }
+
+    #[test]
+    fn registry_custom_cipher() {
+        let mut registry = CipherRegistry::new();
+        registry.register_cipher("xor", Box::new(XorCipher));
+        assert_eq!(registry.get_key_size("xor"), 1);
+        assert!(registry.generate_nonce("xor").is_empty());
+        let encrypted = registry.get_encryptor("xor")(b"data", &[1], HashMap::new()).unwrap();
+        assert_eq!(encrypted, b"e`u`");
+        let decrypted = registry
+            .get("xor")
+            .decrypt(&encrypted, &[1], HashMap::new());
+        assert_eq!(decrypted.unwrap(), b"data");
+    }
+
+    #[test]
+    fn aes_gcm_authenticates_aad() {
+        let key = [7u8; 32];
+        let nonce: &[u8] = b"dummy nonce ";
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), nonce);
+        extras.insert("aad".to_owned(), &b"label"[..]);
+        let encrypted = aes_encrypt(b"data", &key, extras.clone()).unwrap();
+        assert_eq!(
+            aes_decrypt(&encrypted, &key, extras.clone()).unwrap(),
+            b"data"
+        );
+
+        extras.insert("aad".to_owned(), &b"other label"[..]);
+        let result = aes_decrypt(&encrypted, &key, extras.clone());
+        assert_eq!(result, Err(CipherError::EncryptionError));
+        extras.remove("aad");
+        let result = aes_decrypt(&encrypted, &key, extras);
+        assert_eq!(result, Err(CipherError::EncryptionError));
+    }
+
+    #[test]
+    fn registry_validate_name() {
+        let registry = CipherRegistry::default();
+        assert!(registry.validate_name("aes256-gcm").is_ok());
+        assert!(registry.validate_name("aes128-gcm").is_ok());
+
+        let err = registry.validate_name("chacha20").unwrap_err();
+        assert_eq!(err.name, "chacha20");
+        assert_eq!(err.valid_names, vec!["aes128-gcm", "aes256-gcm"]);
+    }
 }
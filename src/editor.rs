@@ -0,0 +1,156 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+/// Environment variable naming the editor secrets are edited with
+pub const EDITOR_ENV: &str = "EDITOR";
+pub const DEFAULT_EDITOR: &str = "vi";
+
+/// Editor command from `EDITOR`, `vi` when it is unset
+pub fn editor_command() -> String {
+    env::var(EDITOR_ENV)
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_owned())
+}
+
+/// Writes `content` to a temporary file only the user can access, opens
+/// it with `editor` and returns what it holds once the editor exits, or
+/// `None` if the editor exits unsuccessfully. `editor` is split on
+/// whitespace, so it may carry arguments such as `code --wait`.
+///
+/// The file is created on tmpfs when `/dev/shm` is available and is
+/// overwritten with zeros before it is removed.
+pub fn edit_with(editor: &str, content: &[u8]) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty editor command"))?;
+
+    let file = SecretFile::create(content)?;
+    let status = Command::new(program).args(words).arg(&file.path).status()?;
+    if !status.success() {
+        return Ok(None);
+    }
+    let edited = Zeroizing::new(fs::read(&file.path)?);
+    Ok(Some(edited))
+}
+
+/// Temporary file holding a plaintext secret, wiped and removed on drop
+struct SecretFile {
+    path: PathBuf,
+}
+
+impl SecretFile {
+    fn create(content: &[u8]) -> io::Result<Self> {
+        let path = secret_dir().join(format!(
+            "swords-{}-{:016x}",
+            std::process::id(),
+            rand::thread_rng().next_u64()
+        ));
+        let mut file = restricted_options().open(&path)?;
+        let secret_file = Self { path };
+        file.write_all(content)?;
+        file.sync_all()?;
+        Ok(secret_file)
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        // The editor may have replaced the file, so wipe whatever is there now
+        if let Ok(mut file) = OpenOptions::new().write(true).open(&self.path) {
+            let length = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            let _ = file.write_all(&vec![0; length as usize]);
+            let _ = file.sync_all();
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// `/dev/shm` when it exists, so that the secret never reaches
+/// the disk, and the temporary directory otherwise
+fn secret_dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+    if shm.is_dir() {
+        return shm.to_path_buf();
+    }
+    env::temp_dir()
+}
+
+#[cfg(unix)]
+fn restricted_options() -> OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true).mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+fn restricted_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    options
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use crate::entity::{
+        record::{Record, CHECKSUM_FIELD},
+        tests::dummy_swd,
+    };
+
+    use super::edit_with;
+
+    #[test]
+    fn edit_with_returns_edited_content() {
+        let edited = edit_with("sed -i s/old/new/", b"old secret\n").unwrap();
+        assert_eq!(&edited.unwrap()[..], b"new secret\n");
+
+        let unchanged = edit_with("true", b"old secret").unwrap();
+        assert_eq!(&unchanged.unwrap()[..], b"old secret");
+    }
+
+    #[test]
+    fn edit_with_aborts_when_editor_fails() {
+        assert!(edit_with("false", b"old secret").unwrap().is_none());
+        assert!(edit_with("swords-no-such-editor", b"old secret").is_err());
+        assert!(edit_with("  ", b"old secret").is_err());
+    }
+
+    #[test]
+    fn edited_secret_is_reencrypted() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let (encrypt, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let nonce_size = swd.nonce_size();
+        let mut record =
+            Record::encrypted("bank".to_owned(), b"old pin", encrypt, key, nonce_size).unwrap();
+        let old_nonce = record.get_extra("nonce").unwrap().inner().to_vec();
+
+        let plaintext = record.decrypt_secret(decrypt, key).unwrap();
+        let edited = edit_with("sed -i s/old/new/", plaintext.as_bytes()).unwrap();
+        record
+            .replace_secret(&edited.unwrap(), encrypt, key, nonce_size)
+            .unwrap();
+
+        assert_ne!(record.get_extra("nonce").unwrap().inner(), &old_nonce[..]);
+        assert!(record.get_extra(CHECKSUM_FIELD).is_some());
+        assert_eq!(&*record.decrypt_secret(decrypt, key).unwrap(), "new pin");
+        let history: Vec<String> = record
+            .history(decrypt, key)
+            .into_iter()
+            .map(|(_, secret)| secret)
+            .collect();
+        assert_eq!(history, vec!["old pin"]);
+    }
+}
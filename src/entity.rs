@@ -1,25 +1,96 @@
-use self::{collection::Collection, value::Value};
+use self::{
+    collection::{Collection, Removed},
+    portable::PortableCollection,
+    record::{Record, Reencrypted},
+    value::Value,
+};
 use crate::{
-    cipher::{CipherRegistry, DecryptFn, EncryptFn},
-    error::ParseError,
-    hash::{HashFunction, HashFunctionRegistry},
+    cipher::{BoundCipher, CipherRegistry, CipherResult, DecryptFn, EncryptFn},
+    error::{ImportError, ParseError, RemoveError, ReservedKeyError, VerifyError, WriteError},
+    hash::{HashFunctionRegistry, PBKDF2_DEFAULT_ITERATIONS},
+    io::parser::ParseResult,
+    migrate::MigrationRegistry,
+    reserved::is_reserved_header_key,
     util::MAGIC_NUMBER,
 };
-use std::collections::HashMap;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::{self, File, Permissions},
+    io::{self, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use subtle::ConstantTimeEq;
+use zeroize::{Zeroize, Zeroizing};
 
 pub mod collection;
+pub mod diff;
+pub mod label;
+pub mod portable;
 pub mod record;
 pub mod value;
 
+/// Current version of the file format, written to the header on save
+pub const VERSION: u32 = 5;
 pub const VERSION_BYTES_LENGTH: usize = 4;
+pub const KDF_ITERATIONS_FIELD: &str = "kdf_iter";
+pub const DESCRIPTION_FIELD: &str = "desc";
+pub const LAST_OPENED_FIELD: &str = "last_opened";
+/// Header extra holding the favorite record paths as a JSON list
+pub const FAVORITES_FIELD: &str = "favorites";
+/// Nonce of the favorites, which are stored encrypted along
+/// with the labels they list when labels are encrypted
+pub const FAVORITES_NONCE_FIELD: &str = "favorites.nonce";
+/// Header extra set to 1 when record and collection labels are encrypted
+pub const LABELS_ENCRYPTED_FIELD: &str = "enc_labels";
+/// First format version whose files end with an integrity trailer
+pub const INTEGRITY_VERSION: u32 = 4;
+pub const INTEGRITY_TRAILER_LENGTH: usize = 32;
+/// Appended to the path of a vault to name the backup of its previous save
+pub const BACKUP_SUFFIX: &str = ".bak";
+const INTEGRITY_KEY_CONTEXT: &[u8] = b"swords integrity";
 
 pub type Entries = HashMap<String, Value>;
+pub type WriteResult<T> = Result<T, WriteError>;
 
+/// File layout
+/// ```text
+/// [MAGIC_NUMBER]
+/// [HEADER]
+/// [ROOT_COLLECTION]
+/// [INTEGRITY_TRAILER]
+/// ```
+///
+/// The integrity trailer is an HMAC-SHA256 over every preceding byte,
+/// keyed by an HMAC-SHA256 of a fixed context under the derived key.
+/// It is only written while the vault is unlocked, which saving
+/// requires, and files older than format version 4 have none. As the version itself is not
+/// signed, vaults parsed from such files are reported as unverified.
 pub struct Swd {
     header: Header,
     root: Collection,
     cipher_registry: CipherRegistry,
     hash_function_registry: HashFunctionRegistry,
+    integrity: Option<Integrity>,
+    migrated_from: Option<u32>,
+    read_only: bool,
+    failed_attempts: u32,
+}
+
+/// What the file a vault was parsed from offers to check its integrity
+enum Integrity {
+    /// Bytes read from the file along with the trailer signing them,
+    /// kept until the key is known to check them
+    Signed {
+        signed_bytes: Vec<u8>,
+        trailer: Option<Vec<u8>>,
+    },
+    /// The file claims a format version older than 4 and has no trailer
+    Unsigned,
 }
 
 impl Swd {
@@ -34,7 +105,81 @@ impl Swd {
             root: Collection::new(root_label),
             cipher_registry,
             hash_function_registry,
+            integrity: None,
+            migrated_from: None,
+            read_only: false,
+            failed_attempts: 0,
+        }
+    }
+
+    /// Creates an empty, unlocked vault with a root collection labelled
+    /// "root", fresh random salts and the default registries, hashing and
+    /// deriving the key from `master_key` with the hash function `kdf`.
+    /// Panics if `cipher` or `kdf` is not in the default registries.
+    pub fn create(master_key: &[u8], cipher: &str, kdf: &str) -> Self {
+        Self::create_with_hash_functions(master_key, cipher, kdf, kdf)
+    }
+
+    /// Same as `create_with_hash_functions`, reusing the cipher and hash
+    /// functions named in the header of another vault, which need not
+    /// be unlocked. Panics if they are not in the default registries.
+    pub fn create_like(master_key: &[u8], template: &Header) -> Self {
+        Self::create_with_hash_functions(
+            master_key,
+            template.key_cipher(),
+            template.master_key_hash_fn(),
+            template.key_hash_fn(),
+        )
+    }
+
+    /// Same as `create`, with separate hash functions
+    /// for the master key hash and the key derivation
+    pub fn create_with_hash_functions(
+        master_key: &[u8],
+        cipher: &str,
+        master_key_hash_fn: &str,
+        key_hash_fn: &str,
+    ) -> Self {
+        let cipher_registry = CipherRegistry::default();
+        let hash_function_registry = HashFunctionRegistry::default();
+
+        let mut rng = rand::thread_rng();
+        let mut master_key_salt = [0; 16];
+        let mut key_salt = [0; 16];
+        rng.fill_bytes(&mut master_key_salt);
+        rng.fill_bytes(&mut key_salt);
+
+        let master_key_hash = hash_function_registry.hash_salted(
+            master_key_hash_fn,
+            master_key,
+            &master_key_salt,
+            PBKDF2_DEFAULT_ITERATIONS,
+        );
+
+        let mut header = Header::new(
+            VERSION,
+            master_key_hash_fn.to_owned(),
+            key_hash_fn.to_owned(),
+            cipher.to_owned(),
+            &master_key_hash,
+            &master_key_salt,
+            &key_salt,
+            HashMap::new(),
+        );
+        if hash_function_registry.is_key_derivation(master_key_hash_fn)
+            || hash_function_registry.is_key_derivation(key_hash_fn)
+        {
+            header.set_kdf_iterations(PBKDF2_DEFAULT_ITERATIONS);
         }
+
+        let mut swd = Self::new(
+            header,
+            "root".to_owned(),
+            cipher_registry,
+            hash_function_registry,
+        );
+        swd.populate_key(master_key);
+        swd
     }
 
     pub fn from_root(
@@ -48,79 +193,800 @@ impl Swd {
             root,
             cipher_registry,
             hash_function_registry,
+            integrity: None,
+            migrated_from: None,
+            read_only: false,
+            failed_attempts: 0,
         }
     }
 
+    /// Upgrades a vault parsed from an older format version with the
+    /// default migrations, so that it is saved back in the current one
+    pub(crate) fn migrate(&mut self, from_version: u32) {
+        MigrationRegistry::default().apply(self, from_version);
+        self.header.set_version(VERSION);
+        self.migrated_from = Some(from_version);
+    }
+
+    /// Format version of the file this vault was upgraded from, if any
+    pub fn migrated_from(&self) -> Option<u32> {
+        self.migrated_from
+    }
+
+    /// Remembers the parsed bytes and trailer for `verify_integrity`
+    pub(crate) fn set_integrity(&mut self, signed_bytes: &[u8], trailer: Option<&[u8]>) {
+        self.integrity = Some(Integrity::Signed {
+            signed_bytes: signed_bytes.to_vec(),
+            trailer: trailer.map(<[u8]>::to_vec),
+        });
+    }
+
+    /// Remembers that the parsed file predates the integrity trailer
+    pub(crate) fn set_unsigned(&mut self) {
+        self.integrity = Some(Integrity::Unsigned);
+    }
+
+    /// Checks the integrity trailer of the file this vault was parsed
+    /// from, which needs the vault to be unlocked. Files claiming a format
+    /// version older than 4 cannot be checked and are reported as
+    /// unverified, since a tampered file may simply claim an older version.
+    /// Vaults that were not parsed from a file have nothing to check.
+    pub fn verify_integrity(&self) -> ParseResult<()> {
+        let (signed_bytes, trailer) = match &self.integrity {
+            None => return Ok(()),
+            Some(Integrity::Unsigned) => return Err(ParseError::IntegrityUnverified),
+            Some(Integrity::Signed {
+                signed_bytes,
+                trailer,
+            }) => (signed_bytes, trailer),
+        };
+        let (Some(trailer), Some(key)) = (trailer, self.header.get_key()) else {
+            return Err(ParseError::IntegrityCheckFailed);
+        };
+        integrity_mac(key, signed_bytes)
+            .verify_slice(trailer)
+            .map_err(|_| ParseError::IntegrityCheckFailed)
+    }
+
     pub fn unlock(&mut self, master_key: &[u8]) -> bool {
         let valid = self.validate_master_key(master_key);
         if !valid {
+            self.failed_attempts = self.failed_attempts.saturating_add(1);
             return false;
         }
+        self.failed_attempts = 0;
         self.populate_key(master_key);
+        self.unseal_labels();
         true
     }
 
+    /// Whether the vault has been unlocked and not locked since
+    pub fn is_unlocked(&self) -> bool {
+        self.header.key.is_some()
+    }
+
+    /// Key the secrets are encrypted with, or `None` while locked
+    pub fn key(&self) -> Option<&[u8]> {
+        self.header.get_key().map(Vec::as_slice)
+    }
+
+    /// Checks the derived key against the first record, returning false
+    /// when the vault is locked or the record fails to decrypt. A failure
+    /// right after `unlock` accepted the master key points at a header
+    /// that does not match the key, such as a changed key salt or KDF.
+    pub fn self_test(&self) -> bool {
+        let Some(key) = self.key() else {
+            return false;
+        };
+        let Some((_, record)) = self.root.iter_all_records().next() else {
+            return true;
+        };
+        self.bind_record_cipher(record, key)
+            .and_then(|bound| record.decrypt_secret_bytes(bound.decrypt_fn, &bound.key))
+            .is_ok()
+    }
+
+    /// Wrong master keys given to `unlock` since the last successful one,
+    /// for callers to slow down repeated guesses
+    pub fn failed_attempts(&self) -> u32 {
+        self.failed_attempts
+    }
+
     pub fn header(&self) -> &Header {
         &self.header
     }
 
-    pub fn add_extra(&mut self, key: &str, value: &[u8], is_secret: bool) {
-        self.header
-            .extras
-            .insert(key.to_owned(), Value::new(value, is_secret));
+    /// Inserts or overwrites the header extra `key`, failing if it
+    /// is reserved, see `reserved::is_reserved_header_key`
+    pub fn add_extra(&mut self, key: &str, value: &[u8], is_secret: bool) -> WriteResult<()> {
+        self.ensure_writable()?;
+        if is_reserved_header_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            }
+            .into());
+        }
+        self.header.update_extra(key, Value::new(value, is_secret));
+        Ok(())
     }
 
     pub fn get_extra(&self, key: &str) -> Option<&Value> {
-        self.header.extras.get(key)
+        self.header.get_extra(key)
+    }
+
+    /// Removes the header extra `key`, returning it, failing
+    /// if it is reserved like `add_extra`
+    pub fn remove_extra(&mut self, key: &str) -> WriteResult<Option<Value>> {
+        self.ensure_writable()?;
+        if is_reserved_header_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            }
+            .into());
+        }
+        Ok(self.header.remove_extra(key))
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.get_extra(DESCRIPTION_FIELD).and_then(Value::as_str)
+    }
+
+    /// Replaces the vault description, removing it when `description` is empty
+    pub fn set_description(&mut self, description: &str) -> WriteResult<()> {
+        self.ensure_writable()?;
+        if description.is_empty() {
+            self.header.remove_extra(DESCRIPTION_FIELD);
+        } else {
+            self.header
+                .update_extra(DESCRIPTION_FIELD, Value::new(description.as_bytes(), false));
+        }
+        Ok(())
+    }
+
+    /// When the vault was last opened, to the second
+    pub fn last_opened(&self) -> Option<SystemTime> {
+        self.get_extra(LAST_OPENED_FIELD)
+            .and_then(Value::as_timestamp)
+            .map(|timestamp| UNIX_EPOCH + Duration::from_secs(timestamp))
+    }
+
+    pub fn set_last_opened(&mut self, time: SystemTime) -> WriteResult<()> {
+        self.ensure_writable()?;
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.header
+            .update_extra(LAST_OPENED_FIELD, Value::timestamp(timestamp, false));
+        Ok(())
+    }
+
+    /// Whether record and collection labels are stored encrypted, in
+    /// which case they read as `(encrypted)` until the vault is unlocked
+    pub fn labels_encrypted(&self) -> bool {
+        self.get_extra(LABELS_ENCRYPTED_FIELD)
+            .and_then(Value::as_u64)
+            == Some(1)
+    }
+
+    /// Turns encrypting the labels on or off from the next save on.
+    /// The vault has to be unlocked, so that the labels are known and
+    /// can be encrypted. The favorites, which list labels, are then
+    /// encrypted as well.
+    pub fn set_labels_encrypted(&mut self, encrypted: bool) -> WriteResult<()> {
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        if encrypted {
+            self.header
+                .update_extra(LABELS_ENCRYPTED_FIELD, Value::u64(1, false));
+        } else {
+            self.header.remove_extra(LABELS_ENCRYPTED_FIELD);
+        }
+        Ok(())
+    }
+
+    /// Decrypts the encrypted labels if the vault is unlocked, keeping
+    /// those that fail to decrypt encrypted, and returns how many did
+    fn unseal_labels(&mut self) -> usize {
+        let Some(key) = self.header.get_key() else {
+            return 0;
+        };
+        let decrypt = self.cipher_registry.get_decryptor(self.header.key_cipher());
+        let key = Zeroizing::new(key.clone());
+        let failures = self.root.unseal_labels(decrypt, &key);
+        failures + usize::from(!self.unseal_favorites(&key))
+    }
+
+    /// Copy of the header with the favorites encrypted under
+    /// the key, which has to be known, see `FAVORITES_NONCE_FIELD`
+    fn sealed_header(&self, key: &[u8]) -> Header {
+        let mut header = self.header.clone();
+        let Some(favorites) = header
+            .get_extra(FAVORITES_FIELD)
+            .filter(|value| !value.is_secret())
+        else {
+            return header;
+        };
+        let (encrypt, _) = self.get_key_cipher();
+        let (ciphertext, nonce) =
+            Record::encrypt_secret(favorites.inner(), encrypt, key, self.nonce_size(), None)
+                .expect("BUG: this should never panic");
+        header.update_extra(FAVORITES_FIELD, Value::bytes(&ciphertext, true));
+        header.update_extra(FAVORITES_NONCE_FIELD, Value::bytes(&nonce, false));
+        header
+    }
+
+    /// Decrypts the favorites if they are encrypted, keeping them
+    /// encrypted and returning false if they fail to decrypt
+    fn unseal_favorites(&mut self, key: &[u8]) -> bool {
+        let (Some(favorites), Some(nonce)) = (
+            self.header
+                .get_extra(FAVORITES_FIELD)
+                .filter(|value| value.is_secret()),
+            self.header.get_extra(FAVORITES_NONCE_FIELD),
+        ) else {
+            return true;
+        };
+        let decrypt = self.cipher_registry.get_decryptor(self.header.key_cipher());
+        let mut decrypt_extras = HashMap::new();
+        decrypt_extras.insert("nonce".to_owned(), nonce.inner());
+        let Ok(json) = decrypt(favorites.inner(), key, decrypt_extras) else {
+            return false;
+        };
+        self.header
+            .update_extra(FAVORITES_FIELD, Value::new(&json, false));
+        self.header.remove_extra(FAVORITES_NONCE_FIELD);
+        true
+    }
+
+    /// Copy of the collection tree with every label encrypted
+    /// under the key, which has to be known
+    fn sealed_root(&self, key: &[u8]) -> Collection {
+        let (encrypt, _) = self.get_key_cipher();
+        let mut root = self.root.clone();
+        root.seal_labels(encrypt, key, self.nonce_size())
+            .expect("BUG: this should never panic");
+        root
+    }
+
+    /// Encrypts the labels again with `key` after they were decrypted
+    /// for an operation on a locked vault that then failed
+    fn reseal_labels_if_locked(&mut self, key: &[u8]) {
+        if self.labels_encrypted() && !self.is_unlocked() {
+            self.root = self.sealed_root(key);
+            self.header = self.sealed_header(key);
+        }
+    }
+
+    /// Paths of the favorite records, each the labels of the collections
+    /// below the root leading to the record followed by its own label.
+    /// Encrypted favorites are only known once the vault is unlocked.
+    pub fn favorites(&self) -> Vec<Vec<String>> {
+        self.get_extra(FAVORITES_FIELD)
+            .filter(|value| !value.is_secret())
+            .and_then(|value| serde_json::from_slice(value.inner()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Fails while the favorites are encrypted, which would
+    /// otherwise be replaced by the ones edited without them
+    fn ensure_favorites_known(&self) -> WriteResult<()> {
+        match self.get_extra(FAVORITES_FIELD) {
+            Some(value) if value.is_secret() => Err(WriteError::Locked),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds the record at `path` to the favorites unless it already is one
+    pub fn add_favorite(&mut self, path: &[String]) -> WriteResult<()> {
+        self.ensure_writable()?;
+        self.ensure_favorites_known()?;
+        let mut favorites = self.favorites();
+        if !favorites.iter().any(|favorite| favorite == path) {
+            favorites.push(path.to_vec());
+            self.set_favorites(&favorites);
+        }
+        Ok(())
+    }
+
+    /// Removes `path` from the favorites, returning whether it was one
+    pub fn remove_favorite(&mut self, path: &[String]) -> WriteResult<bool> {
+        self.ensure_writable()?;
+        self.ensure_favorites_known()?;
+        let mut favorites = self.favorites();
+        let count = favorites.len();
+        favorites.retain(|favorite| favorite != path);
+        if favorites.len() == count {
+            return Ok(false);
+        }
+        self.set_favorites(&favorites);
+        Ok(true)
+    }
+
+    /// Favorites no longer leading to a record, such as
+    /// those of records since deleted, moved or relabeled
+    pub fn stale_favorites(&self) -> Vec<Vec<String>> {
+        self.favorites()
+            .into_iter()
+            .filter(|favorite| {
+                let path: Vec<&str> = favorite.iter().map(String::as_str).collect();
+                self.root.record_address(&path).is_none()
+            })
+            .collect()
+    }
+
+    fn set_favorites(&mut self, favorites: &[Vec<String>]) {
+        if favorites.is_empty() {
+            self.header.remove_extra(FAVORITES_FIELD);
+            return;
+        }
+        let json = serde_json::to_vec(favorites).expect("BUG: this should never panic");
+        self.header
+            .update_extra(FAVORITES_FIELD, Value::new(&json, false));
     }
 
     pub fn get_root(&self) -> &Collection {
         &self.root
     }
 
+    /// Labels leading to every collection, starting with the root's own
+    /// label, depth-first, see `Collection::collection_paths`
+    pub fn collection_paths(&self) -> Vec<Vec<String>> {
+        self.root.collection_paths()
+    }
+
+    /// Mutable access to the root for navigation and edits,
+    /// which ignores read-only mode, see `try_get_root_mut`
     pub fn get_root_mut(&mut self) -> &mut Collection {
         &mut self.root
     }
 
+    /// Same as `get_root_mut`, failing when the vault is read-only
+    pub fn try_get_root_mut(&mut self) -> WriteResult<&mut Collection> {
+        self.ensure_writable()?;
+        Ok(&mut self.root)
+    }
+
+    /// Follows child labels down from the root, see `Collection::get_by_path`.
+    /// The path may start with the root label, which is skipped unless the
+    /// root has a child labelled the same that the whole path leads through.
+    pub fn get_collection_by_path(&self, path: &[&str]) -> Option<&Collection> {
+        self.root.get_by_path(self.path_below_root(path))
+    }
+
+    /// Same as `get_collection_by_path` for edits, which
+    /// ignores read-only mode like `get_root_mut`
+    pub fn get_collection_mut_by_path(&mut self, path: &[&str]) -> Option<&mut Collection> {
+        let path = self.path_below_root(path);
+        self.root.get_by_path_mut(path)
+    }
+
+    fn path_below_root<'a>(&self, path: &'a [&'a str]) -> &'a [&'a str] {
+        match path.split_first() {
+            Some((first, rest))
+                if *first == self.root.label() && self.root.get_by_path(path).is_none() =>
+            {
+                rest
+            }
+            _ => path,
+        }
+    }
+
+    /// Removes the record or collection at `path`, see
+    /// `Collection::take_by_path` and `get_collection_by_path`
+    pub fn remove_by_path(&mut self, path: &[&str]) -> Result<Removed, RemoveError> {
+        if self.read_only {
+            return Err(RemoveError::ReadOnly);
+        }
+        let path = self.path_below_root(path);
+        if path.is_empty() {
+            return Err(RemoveError::Root);
+        }
+        self.root
+            .take_by_path(path)
+            .ok_or_else(|| RemoveError::NotFound(path.join("/")))
+    }
+
+    /// Makes the vault refuse edits through its guarded methods
+    /// and refuse to be saved
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Writes the vault to `path`, unless it is read-only or locked, after
+    /// copying the file already there to `<path>.bak`, replacing any older
+    /// backup. A locked vault cannot sign the file with an integrity trailer.
+    pub fn save_to_path(&self, path: &str) -> WriteResult<()> {
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        if Path::new(path).is_file() {
+            fs::copy(path, format!("{}{}", path, BACKUP_SUFFIX))?;
+        }
+        self.write_atomically(path)
+    }
+
+    /// Same as `save_to_path` without keeping a backup
+    pub fn save_to_path_without_backup(&self, path: &str) -> WriteResult<()> {
+        self.ensure_writable()?;
+        self.ensure_unlocked()?;
+        self.write_atomically(path)
+    }
+
+    /// Writes to a temporary file next to `path` first, flushed to disk,
+    /// and then renames it over `path`, so a crash midway leaves the old
+    /// file intact. The file keeps the permissions of the one it replaces,
+    /// a new vault being only accessible to the user on unix.
+    fn write_atomically(&self, path: &str) -> WriteResult<()> {
+        let temporary_path = format!("{}.tmp", path);
+        let result = write_synced(&temporary_path, &self.to_bytes(), vault_permissions(path))
+            .and_then(|()| fs::rename(&temporary_path, path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&temporary_path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn ensure_writable(&self) -> WriteResult<()> {
+        if self.read_only {
+            return Err(WriteError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn ensure_unlocked(&self) -> WriteResult<()> {
+        if !self.is_unlocked() {
+            return Err(WriteError::Locked);
+        }
+        Ok(())
+    }
+
     pub fn cipher_registry(&self) -> &CipherRegistry {
         &self.cipher_registry
     }
 
+    /// Exports the whole collection tree as JSON, with every record
+    /// secret decrypted
+    pub fn export_json(&self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> CipherResult<String> {
+        let portable = PortableCollection::from_collection_with(
+            &self.root,
+            &|record| match record.cipher() {
+                None => record.decrypt_secret(decrypt_fn, key),
+                Some(_) => self.decrypt_record_secret(record, key),
+            },
+        )?;
+        Ok(portable.to_json())
+    }
+
+    /// Plaintext copy of `collection`, which belongs to this vault, with
+    /// each record decrypted with its own cipher under the vault key `key`
+    pub fn to_portable(
+        &self,
+        collection: &Collection,
+        key: &[u8],
+    ) -> CipherResult<PortableCollection> {
+        collection.to_portable(&|record| self.decrypt_record_secret(record, key))
+    }
+
+    fn decrypt_record_secret(
+        &self,
+        record: &Record,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<String>> {
+        let bound = self.bind_record_cipher(record, key)?;
+        record.decrypt_secret(bound.decrypt_fn, &bound.key)
+    }
+
+    /// Tries to decrypt every record secret field without revealing it,
+    /// collecting a failure for each field that does not decrypt
+    pub fn verify(&self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> Vec<VerifyError> {
+        self.root
+            .records_with_paths()
+            .into_iter()
+            .flat_map(|(path, record)| {
+                record.secret_fields().into_iter().filter_map(move |field| {
+                    let result = match record.cipher() {
+                        None => record.decrypt_field(field, decrypt_fn, key),
+                        Some(_) => self.bind_record_cipher(record, key).and_then(|bound| {
+                            record.decrypt_field(field, bound.decrypt_fn, &bound.key)
+                        }),
+                    };
+                    let error = result.err()?;
+                    Some(VerifyError {
+                        path: path.clone(),
+                        label: record.label().clone(),
+                        field: field.to_owned(),
+                        error,
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Builds a vault from a JSON tree as produced by `export_json`,
+    /// encrypting every secret with `encrypt_fn` and `key`
+    pub fn import_json(
+        header: Header,
+        json: &str,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+    ) -> Result<Swd, ImportError> {
+        let portable = PortableCollection::from_json(json)?;
+        Self::import_portable(header, portable.label.clone(), portable, encrypt_fn, key)
+    }
+
+    /// Builds a vault from CSV with `path`, `label` and `secret` columns,
+    /// encrypting every secret with `encrypt_fn` and `key`
+    pub fn import_csv(
+        header: Header,
+        root_label: String,
+        csv: &str,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+    ) -> Result<Swd, ImportError> {
+        let portable = PortableCollection::from_csv(csv)?;
+        Self::import_portable(header, root_label, portable, encrypt_fn, key)
+    }
+
+    fn import_portable(
+        header: Header,
+        root_label: String,
+        portable: PortableCollection,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+    ) -> Result<Swd, ImportError> {
+        let cipher_registry = CipherRegistry::default();
+        let nonce_size = cipher_registry.get_nonce_size(header.key_cipher());
+        let mut root = Collection::new(root_label);
+        portable.import_into(&mut root, encrypt_fn, key, nonce_size)?;
+
+        Ok(Self::from_root(
+            header,
+            root,
+            cipher_registry,
+            HashFunctionRegistry::default(),
+        ))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.extend_from_slice(&MAGIC_NUMBER);
-        bytes.extend_from_slice(&self.header.to_bytes());
-        bytes.extend_from_slice(&self.root.to_bytes());
+        match self.header.get_key() {
+            // Labels and favorites are kept decrypted while unlocked and only
+            // encrypted on their way out. A locked vault has them encrypted
+            // already. The collection tree is always written in the current
+            // format.
+            Some(key) if self.labels_encrypted() => {
+                bytes.extend_from_slice(&self.sealed_header(key).to_bytes_with_version(VERSION));
+                bytes.extend_from_slice(&self.sealed_root(key).to_bytes())
+            }
+            _ => {
+                bytes.extend_from_slice(&self.header.to_bytes_with_version(VERSION));
+                bytes.extend_from_slice(&self.root.to_bytes())
+            }
+        }
+        if let Some(key) = self.header.get_key() {
+            let trailer = integrity_mac(key, &bytes).finalize().into_bytes();
+            bytes.extend_from_slice(&trailer);
+        }
         bytes
     }
 
+    /// Replaces the master key, re-encrypting every record secret under
+    /// fresh salts and nonces. Returns false without changing anything if
+    /// `old_master_key` is wrong, a secret fails to decrypt or the vault
+    /// is read-only.
+    pub fn change_master_key(&mut self, old_master_key: &[u8], new_master_key: &[u8]) -> bool {
+        if self.read_only || !self.validate_master_key(old_master_key) {
+            return false;
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut master_key_salt = [0; 16];
+        let mut key_salt = [0; 16];
+        rng.fill_bytes(&mut master_key_salt);
+        rng.fill_bytes(&mut key_salt);
+
+        let old_key = self.derive_key(old_master_key, self.header.key_salt());
+        let new_key = self.derive_key(new_master_key, &key_salt);
+        // Secrets are bound to the labels, which a locked vault has encrypted
+        let decrypt = self.cipher_registry.get_decryptor(self.header.key_cipher());
+        if self.root.unseal_labels(decrypt, &old_key) > 0 || !self.unseal_favorites(&old_key) {
+            self.reseal_labels_if_locked(&old_key);
+            return false;
+        }
+        let master_key_hash = self.hash_master_key(new_master_key, &master_key_salt);
+
+        #[cfg(feature = "parallel")]
+        let result = self.par_reencrypt_all(&old_key, &new_key);
+        #[cfg(not(feature = "parallel"))]
+        let result = self.reencrypt_all(&old_key, &new_key);
+        if result.is_err() {
+            self.reseal_labels_if_locked(&old_key);
+            return false;
+        }
+
+        self.header.master_key_salt = master_key_salt.to_vec();
+        self.header.key_salt = key_salt.to_vec();
+        self.header.master_key_hash = master_key_hash;
+        self.header.key = Some(new_key);
+        true
+    }
+
+    /// Zeroes and forgets the derived key along with every revealed
+    /// secret and, if labels are encrypted, every label, so that the
+    /// vault has to be unlocked again
+    pub fn lock(&mut self) {
+        if let Some(key) = self.header.get_key().filter(|_| self.labels_encrypted()) {
+            let header = self.sealed_header(key);
+            self.root = self.sealed_root(key);
+            self.header = header;
+        }
+        self.header.key.zeroize();
+        for record in self.root.all_records_mut() {
+            record.conceal();
+        }
+    }
+
     fn validate_master_key(&self, master_key: &[u8]) -> bool {
-        let hash = self.get_master_key_hash_fn();
-        let mut master_key = master_key.to_vec();
-        master_key.extend_from_slice(self.header.master_key_salt());
-        let master_key_hash = hash(&master_key);
+        let master_key_hash = self.hash_master_key(master_key, self.header.master_key_salt());
         let stored_master_key_hash = self.header.master_key_hash();
-        &master_key_hash == stored_master_key_hash
+        // Slices of different lengths compare unequal right away,
+        // which only reveals the length of the stored hash
+        master_key_hash.ct_eq(stored_master_key_hash).into()
     }
 
     fn populate_key(&mut self, master_key: &[u8]) {
-        let hash = self.get_key_hash_fn();
-        let mut master_key = master_key.to_vec();
-        master_key.extend_from_slice(self.header.key_salt());
-        let key = hash(&master_key);
-        self.header.set_key(key);
+        let key = self.derive_key(master_key, self.header.key_salt());
+        self.header.key = Some(key);
+    }
+
+    fn hash_master_key(&self, master_key: &[u8], salt: &[u8]) -> Vec<u8> {
+        self.hash_function_registry.hash_salted(
+            self.header.master_key_hash_fn(),
+            master_key,
+            salt,
+            self.header.kdf_iterations(),
+        )
+    }
+
+    /// Derives the key from the master key, truncated to
+    /// the key size of the key cipher
+    fn derive_key(&self, master_key: &[u8], salt: &[u8]) -> Zeroizing<Vec<u8>> {
+        let mut key = Zeroizing::new(self.hash_function_registry.hash_salted(
+            self.header.key_hash_fn(),
+            master_key,
+            salt,
+            self.header.kdf_iterations(),
+        ));
+        let key_size = self.cipher_registry.get_key_size(self.header.key_cipher());
+        key.truncate(key_size);
+        key
+    }
+
+    /// Decrypts every secret field of every record with `old_key` and
+    /// encrypts it again with `new_key` under a fresh nonce, binding it
+    /// to the record label. Each record keeps its cipher, see
+    /// `record_cipher`. Nothing is changed if any field fails to decrypt.
+    pub fn reencrypt_all(&mut self, old_key: &[u8], new_key: &[u8]) -> CipherResult<()> {
+        let reencrypted = self
+            .root
+            .all_records()
+            .into_iter()
+            .map(|record| self.reencrypt_record(record, old_key, new_key))
+            .collect::<CipherResult<Vec<_>>>()?;
+        self.set_reencrypted(reencrypted);
+        Ok(())
+    }
+
+    /// Same as `reencrypt_all`, spreading the records over a thread pool
+    #[cfg(feature = "parallel")]
+    pub fn par_reencrypt_all(&mut self, old_key: &[u8], new_key: &[u8]) -> CipherResult<()> {
+        use rayon::prelude::*;
+
+        let registry = &self.cipher_registry;
+        let key_cipher = self.header.key_cipher();
+        let reencrypted = self
+            .root
+            .all_records()
+            .into_par_iter()
+            .map(|record| {
+                let old = bind_cipher_of(record, registry, key_cipher, old_key)?;
+                let new = bind_cipher_of(record, registry, key_cipher, new_key)?;
+                record.reencrypt(
+                    record.label(),
+                    new.encrypt_fn,
+                    old.decrypt_fn,
+                    &old.key,
+                    &new.key,
+                    new.nonce_size,
+                )
+            })
+            .collect::<CipherResult<Vec<_>>>()?;
+        self.set_reencrypted(reencrypted);
+        Ok(())
+    }
+
+    /// Encrypts every secret field of every record again with `cipher`
+    /// and `key` under a fresh random nonce, so that saving unchanged
+    /// secrets does not keep their ciphertext. Records with a cipher of
+    /// their own keep it. The key and plaintexts stay the same. Nothing
+    /// is changed if any field fails to decrypt or a cipher is not
+    /// registered.
+    pub fn rotate_nonces(&mut self, cipher: &str, key: &[u8]) -> CipherResult<()> {
+        let reencrypted = self
+            .root
+            .all_records()
+            .into_iter()
+            .map(|record| {
+                let bound = bind_cipher_of(record, &self.cipher_registry, cipher, key)?;
+                record.reencrypt(
+                    record.label(),
+                    bound.encrypt_fn,
+                    bound.decrypt_fn,
+                    &bound.key,
+                    &bound.key,
+                    bound.nonce_size,
+                )
+            })
+            .collect::<CipherResult<Vec<_>>>()?;
+        self.set_reencrypted(reencrypted);
+        Ok(())
+    }
+
+    /// Re-encrypts `record` from `old_key` to `new_key` with its own cipher
+    fn reencrypt_record(
+        &self,
+        record: &Record,
+        old_key: &[u8],
+        new_key: &[u8],
+    ) -> CipherResult<Reencrypted> {
+        let old = self.bind_record_cipher(record, old_key)?;
+        let new = self.bind_record_cipher(record, new_key)?;
+        record.reencrypt(
+            record.label(),
+            new.encrypt_fn,
+            old.decrypt_fn,
+            &old.key,
+            &new.key,
+            new.nonce_size,
+        )
+    }
+
+    /// Name of the cipher the secrets of `record` are encrypted
+    /// with, its own if it has one and the key cipher otherwise
+    pub fn record_cipher<'a>(&'a self, record: &'a Record) -> &'a str {
+        record.cipher().unwrap_or(self.header.key_cipher())
+    }
+
+    /// Cipher of `record` bound to its key under the vault key `key`,
+    /// see `CipherRegistry::bind`
+    pub fn bind_record_cipher(&self, record: &Record, key: &[u8]) -> CipherResult<BoundCipher<'_>> {
+        bind_cipher_of(record, &self.cipher_registry, self.header.key_cipher(), key)
+    }
+
+    /// Stores the output of `Record::reencrypt` for every record, in order
+    fn set_reencrypted(&mut self, reencrypted: Vec<Reencrypted>) {
+        let records = self.root.all_records_mut();
+        for (record, reencrypted) in records.into_iter().zip(reencrypted) {
+            record.set_reencrypted(reencrypted);
+        }
     }
 
-    fn get_master_key_hash_fn(&self) -> &Box<HashFunction> {
-        let master_key_hash_fn = self.header.master_key_hash_fn();
-        let hash_fn = self.hash_function_registry.get_function(master_key_hash_fn);
-        hash_fn
+    /// Size of the nonces the key cipher expects
+    pub fn nonce_size(&self) -> usize {
+        self.cipher_registry
+            .get_nonce_size(self.header.key_cipher())
     }
 
-    fn get_key_hash_fn(&self) -> &Box<HashFunction> {
-        let key_hash_fn = self.header.key_hash_fn();
-        let hash_fn = self.hash_function_registry.get_function(key_hash_fn);
-        hash_fn
+    /// Generates a random nonce for the key cipher
+    pub fn generate_nonce(&self) -> Vec<u8> {
+        self.cipher_registry
+            .generate_nonce(self.header.key_cipher())
     }
 
     pub fn get_key_cipher(&self) -> (&Box<EncryptFn>, &Box<DecryptFn>) {
@@ -131,6 +997,79 @@ impl Swd {
     }
 }
 
+/// Cipher of `record`, falling back to `key_cipher`, bound to its key
+fn bind_cipher_of<'a>(
+    record: &Record,
+    registry: &'a CipherRegistry,
+    key_cipher: &str,
+    key: &[u8],
+) -> CipherResult<BoundCipher<'a>> {
+    registry.bind(record.cipher().unwrap_or(key_cipher), key_cipher, key)
+}
+
+/// Serializes `entries` sorted by key, so that the same entries
+/// always serialize to the same bytes
+pub(crate) fn entries_to_bytes(entries: &Entries) -> Vec<u8> {
+    let mut keys: Vec<&String> = entries.keys().collect();
+    keys.sort();
+    let mut bytes = vec![];
+    for key in keys {
+        bytes.extend_from_slice(&Value::str_to_bytes(key, false));
+        bytes.extend_from_slice(&entries[key].to_bytes());
+    }
+    bytes
+}
+
+/// Writes `bytes` to `path` with `permissions`, set before anything
+/// is written, and waits until they reach the disk
+fn write_synced(path: &str, bytes: &[u8], permissions: Option<Permissions>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    if let Some(permissions) = permissions {
+        file.set_permissions(permissions)?;
+    }
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+/// Permissions of the vault at `path`, or those of a new vault if
+/// there is none yet
+fn vault_permissions(path: &str) -> Option<Permissions> {
+    match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(_) => new_vault_permissions(),
+    }
+}
+
+#[cfg(unix)]
+fn new_vault_permissions() -> Option<Permissions> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(Permissions::from_mode(0o600))
+}
+
+#[cfg(not(unix))]
+fn new_vault_permissions() -> Option<Permissions> {
+    None
+}
+
+fn integrity_mac(key: &[u8], bytes: &[u8]) -> Hmac<Sha256> {
+    let mut key_mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    key_mac.update(INTEGRITY_KEY_CONTEXT);
+    let integrity_key = Zeroizing::new(key_mac.finalize().into_bytes().to_vec());
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(&integrity_key).expect("HMAC accepts keys of any length");
+    mac.update(bytes);
+    mac
+}
+
+impl Drop for Swd {
+    fn drop(&mut self) {
+        self.lock();
+    }
+}
+
+#[derive(Clone)]
 pub struct Header {
     version: u32,
     master_key_hash_fn: String,
@@ -139,10 +1078,44 @@ pub struct Header {
     key_cipher: String,
     master_key_salt: Vec<u8>,
     key_salt: Vec<u8>,
-    key: Option<Vec<u8>>,
+    key: Option<Zeroizing<Vec<u8>>>,
     extras: Entries,
 }
 
+/// Headers are equal when their stored fields are,
+/// regardless of whether either is unlocked
+impl PartialEq for Header {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.master_key_hash_fn == other.master_key_hash_fn
+            && self.key_hash_fn == other.key_hash_fn
+            && self.master_key_hash == other.master_key_hash
+            && self.key_cipher == other.key_cipher
+            && self.master_key_salt == other.master_key_salt
+            && self.key_salt == other.key_salt
+            && self.extras == other.extras
+    }
+}
+
+impl Eq for Header {}
+
+/// Leaves out the key, which must never end up in logs
+impl Debug for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Header")
+            .field("version", &self.version)
+            .field("master_key_hash_fn", &self.master_key_hash_fn)
+            .field("key_hash_fn", &self.key_hash_fn)
+            .field("master_key_hash", &self.master_key_hash)
+            .field("key_cipher", &self.key_cipher)
+            .field("master_key_salt", &self.master_key_salt)
+            .field("key_salt", &self.key_salt)
+            .field("is_unlocked", &self.key.is_some())
+            .field("extras", &self.extras)
+            .finish()
+    }
+}
+
 pub const REQUIRED_HEADER_FIELDS: [&str; 7] = ["v", "mkhf", "khf", "mks", "ks", "mkh", "kc"];
 
 impl Header {
@@ -169,7 +1142,15 @@ impl Header {
         }
     }
 
-    pub fn master_key_hash_fn(&self) -> &String {
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn set_version(&mut self, version: u32) {
+        self.version = version;
+    }
+
+    pub fn master_key_hash_fn(&self) -> &str {
         &self.master_key_hash_fn
     }
 
@@ -181,7 +1162,7 @@ impl Header {
         &self.master_key_salt
     }
 
-    pub fn key_hash_fn(&self) -> &String {
+    pub fn key_hash_fn(&self) -> &str {
         &self.key_hash_fn
     }
 
@@ -189,46 +1170,88 @@ impl Header {
         &self.key_salt
     }
 
-    pub fn key_cipher(&self) -> &String {
+    pub fn key_cipher(&self) -> &str {
         &self.key_cipher
     }
 
+    /// Iteration count of the key derivation functions, stored in
+    /// the `kdf_iter` extra and ignored by plain hash functions
+    pub fn kdf_iterations(&self) -> u32 {
+        self.extras
+            .get(KDF_ITERATIONS_FIELD)
+            .and_then(Value::as_u64)
+            .and_then(|iterations| iterations.try_into().ok())
+            .unwrap_or(PBKDF2_DEFAULT_ITERATIONS)
+    }
+
+    pub fn set_kdf_iterations(&mut self, iterations: u32) {
+        self.extras.insert(
+            KDF_ITERATIONS_FIELD.to_owned(),
+            Value::u64(iterations as u64, false),
+        );
+    }
+
+    pub fn get_extra(&self, key: &str) -> Option<&Value> {
+        self.extras.get(key)
+    }
+
+    /// Every extra, required fields excluded, sorted by key
+    pub fn extras_iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        let mut extras: Vec<(&String, &Value)> = self.extras.iter().collect();
+        extras.sort_by_key(|(key, _)| *key);
+        extras.into_iter()
+    }
+
+    /// Inserts or overwrites the extra `key`, returning false without
+    /// changing anything if `key` names one of the required fields,
+    /// which are not extras
+    pub fn update_extra(&mut self, key: &str, value: Value) -> bool {
+        if REQUIRED_HEADER_FIELDS.contains(&key) {
+            return false;
+        }
+        self.extras.insert(key.to_owned(), value);
+        true
+    }
+
+    /// Removes the extra `key`, returning it. Required fields are not
+    /// extras, so they are never removed.
+    pub fn remove_extra(&mut self, key: &str) -> Option<Value> {
+        self.extras.remove(key)
+    }
+
     pub fn set_key(&mut self, key: Vec<u8>) {
-        self.key = Some(key);
+        self.key = Some(Zeroizing::new(key));
     }
 
     pub fn get_key(&self) -> Option<&Vec<u8>> {
-        self.key.as_ref()
+        self.key.as_deref()
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with_version(self.version)
+    }
+
+    fn to_bytes_with_version(&self, version: u32) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.extend_from_slice(&Value::str_to_bytes("v", false));
-        bytes.extend_from_slice(&Value::new(&self.version_bytes(), false).to_bytes());
+        bytes.extend_from_slice(&Value::bytes(&version.to_be_bytes(), false).to_bytes());
         bytes.extend_from_slice(&Value::str_to_bytes("mkhf", false));
-        bytes.extend_from_slice(&Value::str_to_bytes(&self.master_key_hash_fn(), false));
+        bytes.extend_from_slice(&Value::str_to_bytes(self.master_key_hash_fn(), false));
         bytes.extend_from_slice(&Value::str_to_bytes("khf", false));
-        bytes.extend_from_slice(&Value::str_to_bytes(&self.key_hash_fn(), false));
+        bytes.extend_from_slice(&Value::str_to_bytes(self.key_hash_fn(), false));
         bytes.extend_from_slice(&Value::str_to_bytes("kc", false));
         bytes.extend_from_slice(&Value::str_to_bytes(self.key_cipher(), false));
         bytes.extend_from_slice(&Value::str_to_bytes("mks", false));
-        bytes.extend_from_slice(&Value::new(self.master_key_salt(), false).to_bytes());
+        bytes.extend_from_slice(&Value::bytes(self.master_key_salt(), false).to_bytes());
         bytes.extend_from_slice(&Value::str_to_bytes("ks", false));
-        bytes.extend_from_slice(&Value::new(self.key_salt(), false).to_bytes());
+        bytes.extend_from_slice(&Value::bytes(self.key_salt(), false).to_bytes());
         bytes.extend_from_slice(&Value::str_to_bytes("mkh", false));
-        bytes.extend_from_slice(&Value::new(self.master_key_hash(), false).to_bytes());
+        bytes.extend_from_slice(&Value::bytes(self.master_key_hash(), false).to_bytes());
 
-        for (key, value) in self.extras.iter() {
-            bytes.extend_from_slice(&Value::str_to_bytes(key, false));
-            bytes.extend_from_slice(&value.to_bytes());
-        }
+        bytes.extend_from_slice(&entries_to_bytes(&self.extras));
 
         bytes
     }
-
-    fn version_bytes(&self) -> [u8; 4] {
-        self.version.to_be_bytes()
-    }
 }
 
 impl TryFrom<Entries> for Header {
@@ -257,7 +1280,7 @@ impl TryFrom<Entries> for Header {
         let master_key_hash = raw_header.remove("mkh").unwrap().take();
 
         Ok(Self::new(
-            0,
+            version,
             master_key_hash_fn,
             key_hash_fn,
             key_cipher,
@@ -268,3 +1291,1631 @@ impl TryFrom<Entries> for Header {
         ))
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        env, fs, process,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use zeroize::Zeroizing;
+
+    use crate::{
+        cipher::CipherRegistry,
+        error::{CipherError, ImportError, ParseError, RemoveError, ReservedKeyError, WriteError},
+        hash::{HashFunctionRegistry, PBKDF2_DEFAULT_ITERATIONS},
+        io::parser::Parser,
+        util::MAGIC_NUMBER,
+    };
+
+    use super::{
+        collection::{Collection, Removed},
+        label::ENCRYPTED_LABEL_PLACEHOLDER,
+        record::{
+            parse_tags, Record, RecordBuilder, CHECKSUM_FIELD, CHECKSUM_LENGTH, CIPHER_FIELD,
+            HISTORY_LIMIT,
+        },
+        value::Value,
+        Header, Swd, BACKUP_SUFFIX, DESCRIPTION_FIELD, FAVORITES_FIELD, FAVORITES_NONCE_FIELD,
+        INTEGRITY_TRAILER_LENGTH, KDF_ITERATIONS_FIELD, LAST_OPENED_FIELD, REQUIRED_HEADER_FIELDS,
+        VERSION,
+    };
+
+    #[test]
+    fn unlock_populates_zeroizing_key() {
+        let mut swd = dummy_swd(b"master key");
+        assert!(swd.header().get_key().is_none());
+        assert!(swd.unlock(b"master key"));
+        let key: Option<&Zeroizing<Vec<u8>>> = swd.header.key.as_ref();
+        assert!(key.is_some());
+    }
+
+    #[test]
+    fn is_unlocked_flips_on_unlock_and_lock() {
+        let mut swd = dummy_swd(b"master key");
+        assert!(!swd.is_unlocked());
+        assert!(swd.key().is_none());
+        assert!(!swd.unlock(b"wrong key"));
+        assert!(!swd.is_unlocked());
+
+        assert!(swd.unlock(b"master key"));
+        assert!(swd.is_unlocked());
+        assert_eq!(swd.key(), swd.header().get_key().map(Vec::as_slice));
+
+        swd.lock();
+        assert!(!swd.is_unlocked());
+        assert!(swd.key().is_none());
+    }
+
+    #[test]
+    fn self_test_detects_mismatched_key_salt() {
+        let mut swd = dummy_swd(b"master key");
+        assert!(!swd.self_test());
+        swd.unlock(b"master key");
+        assert!(swd.self_test());
+        add_dummy_record(&mut swd, "abc", "top secret");
+        assert!(swd.self_test());
+
+        swd.lock();
+        swd.header.key_salt = vec![0; 16];
+        assert!(swd.unlock(b"master key"));
+        assert!(!swd.self_test());
+    }
+
+    #[test]
+    fn unlock_wrong_master_key() {
+        let mut swd = dummy_swd(b"master key");
+        assert!(!swd.unlock(b"wrong key"));
+        assert!(swd.header().get_key().is_none());
+    }
+
+    #[test]
+    fn lock_forgets_key() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        assert!(record
+            .reveal(decrypt, swd.header().get_key().unwrap())
+            .is_ok());
+        swd.get_root_mut().add_record(record);
+
+        swd.lock();
+        assert!(swd.header().get_key().is_none());
+        let record = swd.get_root().get_record(0).unwrap();
+        assert!(record.revealed_secret().is_none());
+
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd
+            .header()
+            .get_key()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        assert!(record.reveal(decrypt, key).is_err());
+        assert!(swd.unlock(b"master key"));
+    }
+
+    #[test]
+    fn reveal_rejects_invalid_utf8() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let (encrypt, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let (secret, nonce) =
+            Record::encrypt_secret(&[0xff, 0xfe], encrypt, key, swd.nonce_size(), None).unwrap();
+        let mut record = Record::new("abc".to_owned(), secret);
+        record.insert_extra("nonce", Value::new(&nonce, false));
+
+        assert_eq!(record.reveal(decrypt, key), Err(CipherError::InvalidUtf8));
+        assert!(record.revealed_secret().is_none());
+    }
+
+    #[test]
+    fn change_master_key_reencrypts() {
+        let mut swd = dummy_swd(b"old master key");
+        swd.unlock(b"old master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut child = Collection::new("child".to_owned());
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let (secret, nonce) =
+            Record::encrypt_secret(b"nested secret", encrypt, key, swd.nonce_size(), None).unwrap();
+        let mut record = Record::new("def".to_owned(), secret);
+        record.insert_extra("nonce", Value::new(&nonce, false));
+        child.add_record(record);
+        swd.get_root_mut().add_child(child);
+        let old_salt = swd.header().key_salt().clone();
+
+        assert!(!swd.change_master_key(b"wrong master key", b"new master key"));
+        assert!(swd.change_master_key(b"old master key", b"new master key"));
+        assert_ne!(swd.header().key_salt(), &old_salt);
+
+        let bytes = swd.to_bytes();
+        let mut swd = Parser::new().parse(&bytes).unwrap();
+        assert!(!swd.unlock(b"old master key"));
+        assert!(swd.unlock(b"new master key"));
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let root = swd.get_root();
+        let secret = root.get_record(0).unwrap().decrypt_secret(decrypt, key);
+        assert_eq!(secret.unwrap().as_str(), "top secret");
+        let nested = root.get_child(0).unwrap().get_record(0).unwrap();
+        let secret = nested.decrypt_secret(decrypt, key);
+        assert_eq!(secret.unwrap().as_str(), "nested secret");
+    }
+
+    #[test]
+    fn rotate_nonces_keeps_plaintext() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let record = swd.get_root().get_record(0).unwrap();
+        let old_secret = record.secret().clone();
+        let old_nonce = record.get_extra("nonce").unwrap().inner().to_vec();
+
+        let key = swd.key().unwrap().to_vec();
+        let cipher = swd.header().key_cipher().to_owned();
+        assert!(swd.rotate_nonces(&cipher, b"wrong key").is_err());
+        swd.rotate_nonces(&cipher, &key).unwrap();
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let record = swd.get_root().get_record(0).unwrap();
+        assert_ne!(record.secret(), &old_secret);
+        assert_ne!(record.get_extra("nonce").unwrap().inner(), &old_nonce[..]);
+        let secret = record.decrypt_secret(decrypt, &key);
+        assert_eq!(secret.unwrap().as_str(), "top secret");
+    }
+
+    #[test]
+    fn export_json_decrypts_secrets() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let mut child = Collection::new("child".to_owned());
+        child.add_extra("icon", b"mail", false).unwrap();
+        swd.get_root_mut().add_child(child);
+        add_dummy_record(&mut swd, "abc", "top secret");
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let exported = swd.export_json(decrypt, key).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&exported).unwrap();
+
+        assert_eq!(json["label"], "root");
+        assert_eq!(json["collections"][0]["label"], "child");
+        assert_eq!(json["collections"][0]["extras"]["icon"], "mail");
+        assert_eq!(json["records"][0]["label"], "abc");
+        assert_eq!(json["records"][0]["secret"], "top secret");
+        assert!(json["records"][0]["extras"].get("nonce").is_none());
+    }
+
+    #[test]
+    fn export_subtree_json() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "Gmail", "root secret");
+        add_dummy_record(&mut swd, "VPN", "work secret");
+        add_dummy_record(&mut swd, "Old MAIL", "legacy secret");
+        let mut legacy = Collection::new("Legacy".to_owned());
+        legacy.add_record(swd.get_root_mut().take_record(2).unwrap());
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(swd.get_root_mut().take_record(1).unwrap());
+        work.add_child(legacy);
+        swd.get_root_mut().add_child(work);
+
+        let key = swd.header().get_key().unwrap();
+        let work = swd.get_root().get_by_path(&["Work"]).unwrap();
+        let exported = swd.to_portable(work, key).unwrap().to_json();
+        let json: serde_json::Value = serde_json::from_str(&exported).unwrap();
+
+        assert_eq!(json["label"], "Work");
+        assert_eq!(json["records"].as_array().unwrap().len(), 1);
+        assert_eq!(json["records"][0]["label"], "VPN");
+        assert_eq!(json["records"][0]["secret"], "work secret");
+        let legacy = &json["collections"][0];
+        assert_eq!(legacy["label"], "Legacy");
+        assert_eq!(legacy["records"][0]["secret"], "legacy secret");
+        assert!(legacy["collections"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn current_totp_requires_flag_and_valid_seed() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "totp", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        add_dummy_record(&mut swd, "invalid", "not base32!");
+        add_dummy_record(&mut swd, "plain", "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        let root = swd.get_root_mut();
+        root.get_record_mut(0).unwrap().set_totp(true);
+        root.get_record_mut(1).unwrap().set_totp(true);
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let root = swd.get_root();
+        let code = root.get_record(0).unwrap().current_totp(decrypt, key);
+        assert!(matches!(code, Some(code) if code.len() == 6));
+        assert!(root
+            .get_record(1)
+            .unwrap()
+            .current_totp(decrypt, key)
+            .is_none());
+        assert!(!root.get_record(2).unwrap().is_totp());
+        assert!(root
+            .get_record(2)
+            .unwrap()
+            .current_totp(decrypt, key)
+            .is_none());
+    }
+
+    #[test]
+    fn verify_collects_every_failure() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "healthy", "top secret");
+        add_dummy_record(&mut swd, "bad nonce", "top secret");
+        add_dummy_record(&mut swd, "bit rot", "top secret");
+        let mut child = Collection::new("child".to_owned());
+        child.add_record(Record::new("garbage".to_owned(), Box::new([1, 2, 3])));
+        swd.get_root_mut().add_child(child);
+
+        let root = swd.get_root_mut();
+        let record = root.get_record_mut(1).unwrap();
+        record.insert_extra("nonce", Value::new(&[3u8; 11], false));
+        let record = root.get_record_mut(2).unwrap();
+        let mut secret = record.secret().clone();
+        secret[0] ^= 0xff;
+        let nonce = record.get_extra("nonce").unwrap().inner().to_vec();
+        record.set_encrypted_secret(secret, &nonce);
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let failures = swd.verify(decrypt, key);
+        let failures: Vec<(String, CipherError)> = failures
+            .into_iter()
+            .map(|failure| (failure.path.join("/") + "/" + &failure.label, failure.error))
+            .collect();
+        assert_eq!(
+            failures,
+            vec![
+                (
+                    "root/bad nonce".to_owned(),
+                    CipherError::InvalidNonceLength(11)
+                ),
+                ("root/bit rot".to_owned(), CipherError::EncryptionError),
+                (
+                    "root/child/garbage".to_owned(),
+                    CipherError::MissingRequiredExtra("nonce".to_owned())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn secret_fields_reveal_and_reencrypt() {
+        let mut swd = dummy_swd(b"old master key");
+        swd.unlock(b"old master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        record
+            .set_secret_field("username", b"alice", encrypt, key, swd.nonce_size())
+            .unwrap();
+        record
+            .set_secret_field("pin", b"1234", encrypt, key, swd.nonce_size())
+            .unwrap();
+        record.add_extra("icon", b"mail", false).unwrap();
+        assert_eq!(record.secret_fields(), vec!["secret", "pin", "username"]);
+        swd.get_root_mut().add_record(record);
+
+        assert!(swd.change_master_key(b"old master key", b"new master key"));
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.unlock(b"new master key"));
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        assert!(swd.verify(decrypt, key).is_empty());
+
+        let revealed = record.reveal_all(decrypt, key);
+        assert_eq!(revealed.len(), 3);
+        assert_eq!(revealed["secret"], "top secret");
+        assert_eq!(revealed["username"], "alice");
+        assert_eq!(revealed["pin"], "1234");
+        assert_eq!(record.revealed_secret().unwrap(), "top secret");
+        assert_eq!(
+            record.decrypt_field("icon", decrypt, key),
+            Err(CipherError::MissingRequiredExtra("icon".to_owned()))
+        );
+    }
+
+    fn set_dummy_secret(swd: &Swd, record: &mut Record, secret: &str) {
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let (secret, nonce) =
+            Record::encrypt_secret(secret.as_bytes(), encrypt, key, swd.nonce_size(), None)
+                .unwrap();
+        record.set_secret(secret, &nonce);
+    }
+
+    #[test]
+    fn changing_secret_invalidates_reveal() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "first");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        assert_eq!(record.reveal(decrypt, key).unwrap(), "first");
+
+        set_dummy_secret(&swd, &mut record, "second");
+        assert!(record.revealed_secret().is_none());
+        assert_eq!(record.reveal(decrypt, key).unwrap(), "second");
+
+        record.set_label("def");
+        assert!(record.revealed_secret().is_none());
+    }
+
+    #[test]
+    fn set_secret_rotates_history() {
+        let mut swd = dummy_swd(b"old master key");
+        swd.unlock(b"old master key");
+        add_dummy_record(&mut swd, "abc", "first");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        set_dummy_secret(&swd, &mut record, "second");
+        set_dummy_secret(&swd, &mut record, "third");
+        swd.get_root_mut().add_record(record);
+
+        assert!(swd.change_master_key(b"old master key", b"new master key"));
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.unlock(b"new master key"));
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record = &swd.get_root().records()[0];
+        assert_eq!(&*record.decrypt_secret(decrypt, key).unwrap(), "third");
+        let history: Vec<String> = record
+            .history(decrypt, key)
+            .into_iter()
+            .map(|(replaced_at, secret)| {
+                assert!(replaced_at > 0);
+                secret
+            })
+            .collect();
+        assert_eq!(history, vec!["second", "first"]);
+    }
+
+    #[test]
+    fn history_is_capped() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "0");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        for secret in 1..=HISTORY_LIMIT + 2 {
+            set_dummy_secret(&swd, &mut record, &secret.to_string());
+        }
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let history: Vec<String> = record
+            .history(decrypt, key)
+            .into_iter()
+            .map(|(_, secret)| secret)
+            .collect();
+        let expected: Vec<String> = (2..=HISTORY_LIMIT + 1)
+            .rev()
+            .map(|secret| secret.to_string())
+            .collect();
+        assert_eq!(history, expected);
+        assert!(record
+            .get_extra(&format!("hist{}", HISTORY_LIMIT))
+            .is_none());
+    }
+
+    fn path(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    #[test]
+    fn add_and_remove_favorites() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "Gmail", "secret");
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(Record::new("VPN".to_owned(), Box::new([0])));
+        swd.get_root_mut().add_child(work);
+
+        assert!(swd.favorites().is_empty());
+        swd.add_favorite(&path(&["Work", "VPN"])).unwrap();
+        swd.add_favorite(&path(&["Gmail"])).unwrap();
+        swd.add_favorite(&path(&["Work", "VPN"])).unwrap();
+        let swd_bytes = swd.to_bytes();
+        let mut swd = Parser::new().parse(&swd_bytes).unwrap();
+        assert_eq!(
+            swd.favorites(),
+            vec![path(&["Work", "VPN"]), path(&["Gmail"])]
+        );
+
+        assert!(swd.remove_favorite(&path(&["Work", "VPN"])).unwrap());
+        assert!(!swd.remove_favorite(&path(&["Work", "VPN"])).unwrap());
+        assert_eq!(swd.favorites(), vec![path(&["Gmail"])]);
+        assert!(swd.remove_favorite(&path(&["Gmail"])).unwrap());
+        assert!(swd.get_extra(FAVORITES_FIELD).is_none());
+
+        swd.set_read_only(true);
+        assert!(matches!(
+            swd.add_favorite(&path(&["Gmail"])),
+            Err(WriteError::ReadOnly)
+        ));
+    }
+
+    #[test]
+    fn stale_favorites_detected() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "Gmail", "secret");
+        add_dummy_record(&mut swd, "Bank", "secret");
+        add_dummy_record(&mut swd, "Forum", "secret");
+        for label in ["Gmail", "Bank", "Forum"] {
+            swd.add_favorite(&path(&[label])).unwrap();
+        }
+        swd.add_favorite(&path(&["Work", "VPN"])).unwrap();
+        assert_eq!(swd.stale_favorites(), vec![path(&["Work", "VPN"])]);
+
+        swd.get_root_mut().take_record(1).unwrap();
+        swd.get_root_mut()
+            .get_record_mut(1)
+            .unwrap()
+            .set_label("Old Forum");
+        assert_eq!(
+            swd.stale_favorites(),
+            vec![path(&["Bank"]), path(&["Forum"]), path(&["Work", "VPN"])]
+        );
+    }
+
+    #[test]
+    fn built_record_reveals_plaintext() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let cipher = swd.cipher_registry().get(swd.header().key_cipher());
+        let key = swd.header().get_key().unwrap();
+        let mut record = RecordBuilder::new()
+            .label("Gmail")
+            .secret_plaintext(b"hunter2")
+            .notes("personal account")
+            .extra("icon", Value::new(b"mail", false))
+            .unwrap()
+            .encrypt_with(cipher, key)
+            .unwrap();
+
+        assert_eq!(record.label(), "Gmail");
+        assert!(record.is_bound());
+        assert!(!record.is_totp());
+        assert_eq!(record.notes(), Some("personal account"));
+        assert_eq!(record.get_extra("icon").unwrap().as_str(), Some("mail"));
+        assert_eq!(
+            record.get_extra("nonce").unwrap().inner().len(),
+            swd.nonce_size()
+        );
+        let (_, decrypt) = swd.get_key_cipher();
+        assert_eq!(record.reveal(decrypt, key).unwrap(), "hunter2");
+    }
+
+    #[test]
+    fn parse_tags_trims_and_drops_empty() {
+        assert_eq!(parse_tags("work, mail"), vec!["work", "mail"]);
+        assert_eq!(parse_tags("  work ,\tmail  ,"), vec!["work", "mail"]);
+        assert_eq!(parse_tags(",, ,work,,"), vec!["work"]);
+        assert_eq!(parse_tags("two words"), vec!["two words"]);
+        assert!(parse_tags("").is_empty());
+        assert!(parse_tags(" , ").is_empty());
+    }
+
+    #[test]
+    fn record_tags() {
+        let mut record = Record::new("abc".to_owned(), Box::new([1]));
+        assert!(record.tags().is_empty());
+        assert!(!record.has_tag("work"));
+
+        record.set_tags(&[" Work ", "", "mail, work", "2fa "]);
+        assert_eq!(record.tags(), vec!["Work", "mail", "2fa"]);
+        assert!(record.has_tag("work"));
+        assert!(record.has_tag(" MAIL "));
+        assert!(!record.has_tag(""));
+        assert!(!record.has_tag("wor"));
+
+        record.set_tags(&[" ", ","]);
+        assert!(record.tags().is_empty());
+        assert!(record.get_extra("tags").is_none());
+    }
+
+    #[test]
+    fn binary_record_reveals_bytes() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let cipher = swd.cipher_registry().get(swd.header().key_cipher());
+        let key = swd.header().get_key().unwrap();
+        let blob: Vec<u8> = (0..70000).map(|i| (i % 7) as u8 | 0x80).collect();
+        let record = RecordBuilder::new()
+            .label("recovery.key")
+            .secret_plaintext(&blob)
+            .binary(true)
+            .encrypt_with(cipher, key)
+            .unwrap();
+        assert!(record.is_binary());
+        swd.get_root_mut().add_record(record);
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.unlock(b"master key"));
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        assert!(record.is_binary());
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        assert_eq!(record.reveal_bytes(decrypt, key).unwrap(), blob);
+        assert_eq!(record.reveal(decrypt, key), Err(CipherError::InvalidUtf8));
+    }
+
+    #[test]
+    fn duplicate_record_decrypts_to_same_plaintext() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (encrypt, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        record
+            .set_secret_field("username", b"alice", encrypt, key, swd.nonce_size())
+            .unwrap();
+        record.set_notes("some notes");
+        assert!(record.reveal(decrypt, key).is_ok());
+
+        let mut copy = record
+            .duplicate(encrypt, decrypt, key, swd.nonce_size())
+            .unwrap();
+        assert_eq!(copy.label(), "abc (copy)");
+        assert!(copy.is_bound());
+        assert_eq!(copy.notes(), Some("some notes"));
+        assert!(copy.revealed_secret().is_none());
+        let revealed = copy.reveal_all(decrypt, key);
+        assert_eq!(revealed["secret"], "top secret");
+        assert_eq!(revealed["username"], "alice");
+    }
+
+    #[test]
+    fn bound_record_fails_after_relabel() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let (encrypt, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let nonce_size = swd.nonce_size();
+        let mut bank =
+            Record::encrypted("bank".to_owned(), b"1234", encrypt, key, nonce_size).unwrap();
+        let mut forum =
+            Record::encrypted("forum".to_owned(), b"hunter2", encrypt, key, nonce_size).unwrap();
+        assert!(bank.is_bound());
+        assert_eq!(bank.reveal(decrypt, key), Ok("1234"));
+
+        bank.set_label("savings");
+        assert_eq!(bank.reveal(decrypt, key), Err(CipherError::EncryptionError));
+        bank.set_label("bank");
+        assert_eq!(bank.reveal(decrypt, key), Ok("1234"));
+
+        let nonce = bank.get_extra("nonce").unwrap().inner().to_vec();
+        forum.set_encrypted_secret(bank.secret().clone(), &nonce);
+        assert_eq!(
+            forum.reveal(decrypt, key),
+            Err(CipherError::EncryptionError)
+        );
+    }
+
+    #[test]
+    fn checksum_survives_reencryption() {
+        let mut swd = dummy_swd(b"old master key");
+        swd.unlock(b"old master key");
+        let (encrypt, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record =
+            Record::encrypted("bank".to_owned(), b"1234", encrypt, key, swd.nonce_size()).unwrap();
+        let checksum = record.get_extra(CHECKSUM_FIELD).unwrap().inner().to_vec();
+        assert_eq!(checksum.len(), CHECKSUM_LENGTH);
+        assert_eq!(
+            record.decrypt_secret(decrypt, key).unwrap().as_str(),
+            "1234"
+        );
+        swd.get_root_mut().add_record(record);
+
+        assert!(swd.change_master_key(b"old master key", b"new master key"));
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record = swd.get_root().get_record(0).unwrap();
+        assert_ne!(
+            record.get_extra(CHECKSUM_FIELD).unwrap().inner(),
+            &checksum[..]
+        );
+        assert_eq!(
+            record.decrypt_secret(decrypt, key).unwrap().as_str(),
+            "1234"
+        );
+        assert!(swd.verify(decrypt, key).is_empty());
+    }
+
+    #[test]
+    fn corrupted_checksum_is_rejected() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let mut record =
+            Record::encrypted("bank".to_owned(), b"1234", encrypt, key, swd.nonce_size()).unwrap();
+        let mut checksum = record.get_extra(CHECKSUM_FIELD).unwrap().inner().to_vec();
+        checksum[0] ^= 1;
+        record.insert_extra(CHECKSUM_FIELD, Value::new(&checksum, false));
+        swd.get_root_mut().add_record(record);
+
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record = swd.get_root().get_record(0).unwrap();
+        assert_eq!(
+            record.decrypt_secret(decrypt, key),
+            Err(CipherError::ChecksumMismatch)
+        );
+        let failures = swd.verify(decrypt, key);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].error, CipherError::ChecksumMismatch);
+    }
+
+    #[test]
+    fn change_master_key_binds_records() {
+        let mut swd = dummy_swd(b"old master key");
+        swd.unlock(b"old master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        assert!(!swd.get_root().get_record(0).unwrap().is_bound());
+
+        assert!(swd.change_master_key(b"old master key", b"new master key"));
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        assert!(record.is_bound());
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        assert_eq!(record.reveal(decrypt, key), Ok("top secret"));
+    }
+
+    #[test]
+    fn validate_master_key_compares_whole_hash() {
+        let swd = dummy_swd(b"master key");
+        assert!(swd.validate_master_key(b"master key"));
+        assert!(!swd.validate_master_key(b"master kez"));
+        assert!(!swd.validate_master_key(b""));
+
+        let mut header = dummy_header(b"master key");
+        header.master_key_hash.pop();
+        let swd = Swd::new(
+            header,
+            "root".to_owned(),
+            CipherRegistry::default(),
+            HashFunctionRegistry::default(),
+        );
+        assert!(!swd.validate_master_key(b"master key"));
+    }
+
+    #[test]
+    fn header_extras_update_and_remove() {
+        let mut header = dummy_header(b"master key");
+        assert!(header.update_extra("icon", Value::new(b"vault", false)));
+        assert!(header.update_extra("icon", Value::new(b"safe", false)));
+        assert_eq!(header.get_extra("icon").unwrap().as_str(), Some("safe"));
+
+        header.set_kdf_iterations(1000);
+        assert_eq!(header.kdf_iterations(), 1000);
+        assert!(header.remove_extra(KDF_ITERATIONS_FIELD).is_some());
+        assert_eq!(header.kdf_iterations(), PBKDF2_DEFAULT_ITERATIONS);
+
+        assert_eq!(header.remove_extra("icon").unwrap().as_str(), Some("safe"));
+        assert!(header.remove_extra("icon").is_none());
+        assert!(header.get_extra("icon").is_none());
+    }
+
+    #[test]
+    fn header_extras_keep_required_fields() {
+        let mut header = dummy_header(b"master key");
+        assert!(!header.update_extra("v", Value::u64(2, false)));
+        assert!(!header.update_extra("kc", Value::new(b"aes128-gcm", false)));
+        assert!(header.remove_extra("kc").is_none());
+        assert_eq!(header.key_cipher(), "aes256-gcm");
+
+        header.set_version(VERSION);
+        let mut bytes = MAGIC_NUMBER.to_vec();
+        bytes.append(&mut header.to_bytes());
+        bytes.append(&mut Collection::new("root".to_owned()).to_bytes());
+        let swd = Parser::new().parse(&bytes).unwrap();
+        assert_eq!(swd.header().key_cipher(), "aes256-gcm");
+        assert!(swd.get_extra("kc").is_none());
+    }
+
+    #[test]
+    fn add_extra_rejects_reserved_keys() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let record = swd.get_root_mut().get_record_mut(0).unwrap();
+        for key in ["nonce", "cksum", "tags", "pin.nonce", "hist0"] {
+            assert_eq!(
+                record.add_extra(key, b"clobbered", false),
+                Err(ReservedKeyError {
+                    key: key.to_owned()
+                })
+            );
+        }
+        assert_eq!(record.get_extra("nonce").unwrap().inner(), &[3u8; 12]);
+        assert!(record.add_extra("icon", b"mail", false).is_ok());
+        assert!(record
+            .add_extra_value("url", Value::new(b"example.com", false))
+            .is_ok());
+
+        let root = swd.get_root_mut();
+        assert!(root.add_extra("label", b"clobbered", false).is_err());
+        assert!(root.add_extra("icon", b"vault", false).is_ok());
+
+        assert!(matches!(
+            swd.add_extra("kc", b"aes128-gcm", false),
+            Err(WriteError::ReservedKey(_))
+        ));
+        assert!(matches!(
+            swd.add_extra("favorites", b"[]", false),
+            Err(WriteError::ReservedKey(_))
+        ));
+        assert!(swd.add_extra("icon", b"vault", false).is_ok());
+        assert_eq!(swd.header().key_cipher(), "aes256-gcm");
+    }
+
+    #[test]
+    fn remove_extra_rejects_reserved_keys() {
+        let mut swd = dummy_swd(b"master key");
+        swd.header
+            .update_extra(KDF_ITERATIONS_FIELD, Value::u64(1000, false));
+        swd.header
+            .update_extra(FAVORITES_NONCE_FIELD, Value::new(&[7; 12], false));
+        swd.add_extra("icon", b"vault", false).unwrap();
+
+        for key in [KDF_ITERATIONS_FIELD, FAVORITES_NONCE_FIELD, "kc"] {
+            assert!(matches!(
+                swd.remove_extra(key),
+                Err(WriteError::ReservedKey(ReservedKeyError { key: reserved })) if reserved == key
+            ));
+        }
+        assert!(swd.get_extra(KDF_ITERATIONS_FIELD).is_some());
+        assert!(swd.get_extra(FAVORITES_NONCE_FIELD).is_some());
+        assert!(swd.remove_extra("icon").unwrap().is_some());
+    }
+
+    #[test]
+    fn collection_mut_by_path_edits_persist() {
+        let mut swd = dummy_swd(b"master key");
+        let mut work = Collection::new("Work".to_owned());
+        work.add_child(Collection::new("Email".to_owned()));
+        swd.get_root_mut().add_child(work);
+
+        let email = swd.get_collection_mut_by_path(&["Work", "Email"]).unwrap();
+        email.add_record(Record::new("Gmail".to_owned(), Box::new([1])));
+        let work = swd.get_collection_mut_by_path(&["root", "Work"]).unwrap();
+        work.set_label("Office");
+
+        let office = swd.get_root().get_by_path(&["Office"]).unwrap();
+        let email = office.get_by_path(&["Email"]).unwrap();
+        assert_eq!(email.records()[0].label(), "Gmail");
+        assert!(swd.get_collection_mut_by_path(&["Work"]).is_none());
+        assert_eq!(swd.get_collection_by_path(&[]).unwrap().label(), "root");
+        assert_eq!(
+            swd.get_collection_by_path(&["root"]).unwrap().label(),
+            "root"
+        );
+    }
+
+    #[test]
+    fn collection_by_path_prefers_child_labelled_like_root() {
+        let mut swd = dummy_swd(b"master key");
+        swd.get_root_mut()
+            .add_child(Collection::new("root".to_owned()));
+        let child = swd.get_collection_mut_by_path(&["root"]).unwrap();
+        child.add_child(Collection::new("nested".to_owned()));
+
+        assert_eq!(swd.get_root().children().len(), 1);
+        assert!(swd.get_collection_by_path(&["root", "nested"]).is_some());
+    }
+
+    fn dummy_work_tree() -> Swd {
+        let mut swd = dummy_swd(b"master key");
+        let mut legacy = Collection::new("Legacy".to_owned());
+        legacy.add_record(Record::new("Old MAIL".to_owned(), Box::new([1])));
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(Record::new("VPN".to_owned(), Box::new([1])));
+        work.add_child(legacy);
+        let root = swd.get_root_mut();
+        root.add_record(Record::new("Gmail".to_owned(), Box::new([1])));
+        root.add_child(work);
+        swd
+    }
+
+    #[test]
+    fn archived_flag_roundtrips() {
+        let mut swd = dummy_work_tree();
+        swd.get_root_mut()
+            .get_record_mut(0)
+            .unwrap()
+            .set_archived(true);
+
+        let swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.get_root().get_record(0).unwrap().is_archived());
+        let work = swd.get_collection_by_path(&["Work"]).unwrap();
+        assert!(!work.get_record(0).unwrap().is_archived());
+    }
+
+    fn contains(haystack: &[u8], needle: &str) -> bool {
+        haystack
+            .windows(needle.len())
+            .any(|window| window == needle.as_bytes())
+    }
+
+    #[test]
+    fn encrypted_labels_are_hidden_while_locked() {
+        let mut swd = dummy_work_tree();
+        swd.unlock(b"master key");
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record =
+            Record::encrypted("Bank".to_owned(), b"1234", encrypt, key, swd.nonce_size()).unwrap();
+        swd.get_root_mut().add_record(record);
+        swd.set_labels_encrypted(true).unwrap();
+        let favorite = vec!["Work".to_owned(), "VPN".to_owned()];
+        swd.add_favorite(&favorite).unwrap();
+
+        let labels = ["root", "Gmail", "Work", "VPN", "Legacy", "Old MAIL", "Bank"];
+        let unlocked_bytes = swd.to_bytes();
+        assert_eq!(swd.get_root().get_record(1).unwrap().label(), "Bank");
+        swd.lock();
+        let locked_bytes = swd.to_bytes();
+        for label in labels {
+            assert!(!contains(&unlocked_bytes, label), "{}", label);
+            assert!(!contains(&locked_bytes, label), "{}", label);
+        }
+
+        let mut swd = Parser::new().parse(&locked_bytes).unwrap();
+        let root = swd.get_root();
+        assert!(root.is_label_sealed());
+        assert_eq!(root.label(), ENCRYPTED_LABEL_PLACEHOLDER);
+        assert_eq!(
+            root.get_record(0).unwrap().label(),
+            ENCRYPTED_LABEL_PLACEHOLDER
+        );
+        assert!(swd.favorites().is_empty());
+        assert!(matches!(
+            swd.remove_favorite(&favorite),
+            Err(WriteError::Locked)
+        ));
+
+        assert!(swd.unlock(b"master key"));
+        assert_eq!(swd.favorites(), vec![favorite]);
+        assert_eq!(swd.get_root().label(), "root");
+        assert!(swd.get_collection_by_path(&["Work", "Legacy"]).is_some());
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record = swd.get_root().get_record(1).unwrap();
+        assert_eq!(record.label(), "Bank");
+        assert_eq!(&*record.decrypt_secret(decrypt, key).unwrap(), "1234");
+    }
+
+    #[test]
+    fn labels_encrypted_requires_unlock() {
+        let mut swd = dummy_work_tree();
+        assert!(matches!(
+            swd.set_labels_encrypted(true),
+            Err(WriteError::Locked)
+        ));
+        assert!(!swd.labels_encrypted());
+        assert!(contains(&swd.to_bytes(), "Gmail"));
+    }
+
+    #[test]
+    fn change_master_key_keeps_encrypted_labels() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record =
+            Record::encrypted("Gmail".to_owned(), b"1234", encrypt, key, swd.nonce_size()).unwrap();
+        swd.get_root_mut().add_record(record);
+        swd.set_labels_encrypted(true).unwrap();
+        swd.lock();
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+
+        assert!(swd.change_master_key(b"master key", b"new master key"));
+        assert_eq!(swd.get_root().get_record(0).unwrap().label(), "Gmail");
+        swd.lock();
+        assert!(!contains(&swd.to_bytes(), "Gmail"));
+        assert!(swd.unlock(b"new master key"));
+        assert_eq!(swd.get_root().get_record(0).unwrap().label(), "Gmail");
+    }
+
+    /// Vault whose record "kept" is encrypted with the key cipher,
+    /// aes256-gcm, and whose record "upgraded" with aes128-gcm
+    pub(crate) fn mixed_cipher_swd() -> Swd {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let key = swd.header().get_key().unwrap().clone();
+        let (encrypt, _) = swd.get_key_cipher();
+        let records: Vec<Record> = [("kept", "first secret"), ("upgraded", "second secret")]
+            .into_iter()
+            .map(|(label, secret)| {
+                Record::encrypted(
+                    label.to_owned(),
+                    secret.as_bytes(),
+                    encrypt,
+                    &key,
+                    swd.nonce_size(),
+                )
+                .unwrap()
+            })
+            .collect();
+        for record in records {
+            swd.get_root_mut().add_record(record);
+        }
+
+        let mut record = swd.get_root_mut().take_record(1).unwrap();
+        let old = swd.bind_record_cipher(&record, &key).unwrap();
+        let new = swd
+            .cipher_registry()
+            .bind("aes128-gcm", "aes256-gcm", &key)
+            .unwrap();
+        record
+            .reencrypt_with(
+                old.decrypt_fn,
+                &old.key,
+                new.encrypt_fn,
+                &new.key,
+                new.nonce_size,
+                "aes128-gcm",
+            )
+            .unwrap();
+        swd.get_root_mut().add_record(record);
+        swd
+    }
+
+    fn decrypt_all(swd: &Swd) -> Vec<String> {
+        let key = swd.key().unwrap();
+        swd.get_root()
+            .records()
+            .iter()
+            .map(|record| {
+                let bound = swd.bind_record_cipher(record, key).unwrap();
+                record
+                    .decrypt_secret(bound.decrypt_fn, &bound.key)
+                    .unwrap()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reencrypt_with_switches_record_cipher() {
+        let swd = mixed_cipher_swd();
+        let root = swd.get_root();
+        assert_eq!(root.get_record(0).unwrap().cipher(), None);
+        assert_eq!(swd.record_cipher(root.get_record(0).unwrap()), "aes256-gcm");
+        let upgraded = root.get_record(1).unwrap();
+        assert_eq!(upgraded.cipher(), Some("aes128-gcm"));
+        assert_eq!(swd.record_cipher(upgraded), "aes128-gcm");
+
+        let (_, decrypt) = swd.get_key_cipher();
+        assert!(upgraded
+            .decrypt_secret(decrypt, swd.key().unwrap())
+            .is_err());
+        assert_eq!(decrypt_all(&swd), vec!["first secret", "second secret"]);
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.unlock(b"master key"));
+        assert!(swd.self_test());
+        let (_, decrypt) = swd.get_key_cipher();
+        assert!(swd.verify(decrypt, swd.key().unwrap()).is_empty());
+        assert_eq!(decrypt_all(&swd), vec!["first secret", "second secret"]);
+    }
+
+    #[test]
+    fn export_decrypts_mixed_cipher_records() {
+        let swd = mixed_cipher_swd();
+        let key = swd.key().unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        let exported = swd.export_json(decrypt, key).unwrap();
+        assert!(exported.contains("second secret"));
+        assert!(!exported.contains("aes128-gcm"));
+
+        let portable = swd.to_portable(swd.get_root(), key).unwrap();
+        let secrets: Vec<&str> = portable
+            .records
+            .iter()
+            .map(|record| record.secret.as_str())
+            .collect();
+        assert_eq!(secrets, vec!["first secret", "second secret"]);
+    }
+
+    #[test]
+    fn mixed_cipher_records_survive_rekeying() {
+        let mut swd = mixed_cipher_swd();
+        assert!(swd.change_master_key(b"master key", b"new master key"));
+        assert_eq!(decrypt_all(&swd), vec!["first secret", "second secret"]);
+
+        let key = swd.key().unwrap().to_vec();
+        swd.rotate_nonces("aes256-gcm", &key).unwrap();
+        assert_eq!(decrypt_all(&swd), vec!["first secret", "second secret"]);
+        assert_eq!(
+            swd.get_root().get_record(1).unwrap().cipher(),
+            Some("aes128-gcm")
+        );
+    }
+
+    #[test]
+    fn unknown_record_cipher_fails_to_decrypt() {
+        let mut swd = mixed_cipher_swd();
+        let record = swd.get_root_mut().get_record_mut(1).unwrap();
+        record.insert_extra(CIPHER_FIELD, Value::new(b"des", false));
+        let record = swd.get_root().get_record(1).unwrap();
+        assert_eq!(
+            swd.bind_record_cipher(record, swd.key().unwrap()).err(),
+            Some(CipherError::UnknownCipher("des".to_owned()))
+        );
+        assert!(!swd.change_master_key(b"master key", b"new master key"));
+    }
+
+    #[test]
+    fn remove_by_path_takes_leaf_record() {
+        let mut swd = dummy_work_tree();
+        let removed = swd.remove_by_path(&["Work", "Legacy", "Old MAIL"]);
+        assert!(matches!(removed, Ok(Removed::Record(record)) if record.label() == "Old MAIL"));
+        let legacy = swd.get_collection_by_path(&["Work", "Legacy"]).unwrap();
+        assert_eq!(legacy.record_count(), 0);
+        assert_eq!(swd.get_root().total_record_count(), 2);
+
+        let removed = swd.remove_by_path(&["root", "Gmail"]);
+        assert!(matches!(removed, Ok(Removed::Record(_))));
+        assert_eq!(swd.get_root().record_count(), 0);
+    }
+
+    #[test]
+    fn remove_by_path_takes_whole_collection() {
+        let mut swd = dummy_work_tree();
+        let Ok(Removed::Collection(work)) = swd.remove_by_path(&["Work"]) else {
+            panic!("Work should be removed as a collection");
+        };
+        assert_eq!(work.total_record_count(), 2);
+        assert!(swd.get_root().children().is_empty());
+        assert_eq!(swd.get_root().total_record_count(), 1);
+    }
+
+    #[test]
+    fn remove_by_path_refuses_root_and_missing_paths() {
+        let mut swd = dummy_work_tree();
+        assert!(matches!(swd.remove_by_path(&[]), Err(RemoveError::Root)));
+        assert!(matches!(
+            swd.remove_by_path(&["root"]),
+            Err(RemoveError::Root)
+        ));
+        assert!(matches!(
+            swd.remove_by_path(&["Work", "Email"]),
+            Err(RemoveError::NotFound(path)) if path == "Work/Email"
+        ));
+        swd.set_read_only(true);
+        assert!(matches!(
+            swd.remove_by_path(&["Gmail"]),
+            Err(RemoveError::ReadOnly)
+        ));
+        assert_eq!(swd.get_root().total_record_count(), 3);
+    }
+
+    #[test]
+    fn create_unlocks_with_same_key() {
+        let swd = Swd::create(b"master key", "aes128-gcm", "blake3");
+        assert_eq!(swd.get_root().label(), "root");
+        assert_eq!(swd.header().key_cipher(), "aes128-gcm");
+        assert_eq!(swd.header().key_hash_fn(), "blake3");
+        assert!(swd.get_extra(KDF_ITERATIONS_FIELD).is_none());
+        assert_eq!(swd.header().get_key().unwrap().len(), 16);
+        let key = swd.header().get_key().unwrap().clone();
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(!swd.unlock(b"wrong master key"));
+        assert!(swd.unlock(b"master key"));
+        assert_eq!(swd.header().get_key().unwrap(), &key);
+        assert_eq!(swd.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn create_like_reuses_template_settings() {
+        let template =
+            Swd::create_with_hash_functions(b"template key", "aes128-gcm", "sha3-256", "blake3");
+        let template = Parser::new().parse(&template.to_bytes()).unwrap();
+
+        let mut swd = Swd::create_like(b"master key", template.header());
+        assert_eq!(swd.header().key_cipher(), "aes128-gcm");
+        assert_eq!(swd.header().master_key_hash_fn(), "sha3-256");
+        assert_eq!(swd.header().key_hash_fn(), "blake3");
+        assert_ne!(swd.header().key_salt(), template.header().key_salt());
+        swd.lock();
+        assert!(swd.unlock(b"master key"));
+    }
+
+    #[test]
+    fn description_and_last_opened_roundtrip() {
+        let mut swd = dummy_swd(b"master key");
+        assert!(swd.description().is_none());
+        assert!(swd.last_opened().is_none());
+        let opened = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        swd.set_description("Personal vault").unwrap();
+        swd.set_last_opened(opened).unwrap();
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert_eq!(swd.description(), Some("Personal vault"));
+        assert_eq!(swd.last_opened(), Some(opened));
+        assert!(!REQUIRED_HEADER_FIELDS.contains(&DESCRIPTION_FIELD));
+        assert!(!REQUIRED_HEADER_FIELDS.contains(&LAST_OPENED_FIELD));
+
+        swd.set_description("").unwrap();
+        assert!(swd.description().is_none());
+        swd.set_read_only(true);
+        assert!(matches!(
+            swd.set_description("Shared vault"),
+            Err(WriteError::ReadOnly)
+        ));
+        assert!(matches!(
+            swd.set_last_opened(SystemTime::now()),
+            Err(WriteError::ReadOnly)
+        ));
+        assert_eq!(swd.last_opened(), Some(opened));
+    }
+
+    #[test]
+    fn failed_attempts_count_until_unlocked() {
+        let mut swd = dummy_swd(b"master key");
+        assert_eq!(swd.failed_attempts(), 0);
+        assert!(!swd.unlock(b"wrong master key"));
+        assert!(!swd.unlock(b"master"));
+        assert_eq!(swd.failed_attempts(), 2);
+        assert!(swd.unlock(b"master key"));
+        assert_eq!(swd.failed_attempts(), 0);
+        assert!(!swd.unlock(b"wrong master key"));
+        assert_eq!(swd.failed_attempts(), 1);
+    }
+
+    #[test]
+    fn read_only_refuses_writes() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        swd.set_read_only(true);
+        assert!(swd.is_read_only());
+
+        assert!(matches!(
+            swd.add_extra("icon", b"vault", false),
+            Err(WriteError::ReadOnly)
+        ));
+        assert!(swd.get_extra("icon").is_none());
+        assert!(matches!(swd.try_get_root_mut(), Err(WriteError::ReadOnly)));
+        assert!(!swd.change_master_key(b"master key", b"new master key"));
+        assert!(swd.unlock(b"master key"));
+
+        let path = env::temp_dir().join(format!("swords-read-only-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+        assert!(matches!(swd.save_to_path(path), Err(WriteError::ReadOnly)));
+        assert!(fs::metadata(path).is_err());
+
+        swd.set_read_only(false);
+        assert!(swd.add_extra("icon", b"vault", false).is_ok());
+        assert!(swd.try_get_root_mut().is_ok());
+        assert!(swd.save_to_path(path).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn save_keeps_backup_of_previous_file() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let path = env::temp_dir().join(format!("swords-backup-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+        let backup_path = format!("{}{}", path, BACKUP_SUFFIX);
+
+        swd.save_to_path(path).unwrap();
+        assert!(fs::metadata(&backup_path).is_err());
+        let first_save = fs::read(path).unwrap();
+
+        add_dummy_record(&mut swd, "abc", "top secret");
+        swd.save_to_path(path).unwrap();
+        assert_eq!(fs::read(&backup_path).unwrap(), first_save);
+        let second_save = fs::read(path).unwrap();
+        assert_eq!(second_save, swd.to_bytes());
+
+        swd.save_to_path(path).unwrap();
+        assert_eq!(fs::read(&backup_path).unwrap(), second_save);
+
+        fs::remove_file(&backup_path).unwrap();
+        swd.save_to_path_without_backup(path).unwrap();
+        assert!(fs::metadata(&backup_path).is_err());
+        assert!(fs::metadata(format!("{}.tmp", path)).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn save_rejects_locked_vault() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        swd.lock();
+        let path = env::temp_dir().join(format!("swords-locked-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+
+        assert!(matches!(swd.save_to_path(path), Err(WriteError::Locked)));
+        assert!(matches!(
+            swd.save_to_path_without_backup(path),
+            Err(WriteError::Locked)
+        ));
+        assert!(fs::metadata(path).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn save_keeps_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let path = env::temp_dir().join(format!("swords-mode-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+        let mode = |path: &str| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        swd.save_to_path_without_backup(path).unwrap();
+        assert_eq!(mode(path), 0o600);
+
+        fs::set_permissions(path, fs::Permissions::from_mode(0o640)).unwrap();
+        swd.save_to_path_without_backup(path).unwrap();
+        assert_eq!(mode(path), 0o640);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn aes128_vault_roundtrip() {
+        let dummy = dummy_header(b"master key");
+        let header = Header::new(
+            VERSION,
+            dummy.master_key_hash_fn().to_owned(),
+            dummy.key_hash_fn().to_owned(),
+            "aes128-gcm".to_owned(),
+            dummy.master_key_hash(),
+            dummy.master_key_salt(),
+            dummy.key_salt(),
+            HashMap::new(),
+        );
+        let mut swd = Swd::new(
+            header,
+            "root".to_owned(),
+            CipherRegistry::default(),
+            HashFunctionRegistry::default(),
+        );
+        assert!(swd.unlock(b"master key"));
+        assert_eq!(swd.header().get_key().unwrap().len(), 16);
+        add_dummy_record(&mut swd, "abc", "top secret");
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert!(swd.unlock(b"master key"));
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let record = swd.get_root().get_record(0).unwrap();
+        let secret = record.decrypt_secret(decrypt, key).unwrap();
+        assert_eq!(secret.as_str(), "top secret");
+    }
+
+    #[test]
+    fn integrity_trailer_roundtrip() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let bytes = swd.to_bytes();
+
+        let mut swd = Parser::new().parse(&bytes).unwrap();
+        assert_eq!(
+            swd.verify_integrity(),
+            Err(ParseError::IntegrityCheckFailed)
+        );
+        swd.unlock(b"master key");
+        assert_eq!(swd.verify_integrity(), Ok(()));
+    }
+
+    #[test]
+    fn integrity_detects_tampering() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut bytes = swd.to_bytes();
+        let label = bytes
+            .windows(3)
+            .rposition(|window| window == b"abc")
+            .unwrap();
+        bytes[label] = b'x';
+
+        let mut swd = Parser::new().parse(&bytes).unwrap();
+        swd.unlock(b"master key");
+        assert_eq!(swd.get_root().get_record(0).unwrap().label(), "xbc");
+        assert_eq!(
+            swd.verify_integrity(),
+            Err(ParseError::IntegrityCheckFailed)
+        );
+    }
+
+    #[test]
+    fn integrity_detects_stripped_trailer() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let mut bytes = swd.to_bytes();
+        bytes.truncate(bytes.len() - INTEGRITY_TRAILER_LENGTH);
+
+        let mut swd = Parser::new().parse(&bytes).unwrap();
+        swd.unlock(b"master key");
+        assert_eq!(
+            swd.verify_integrity(),
+            Err(ParseError::IntegrityCheckFailed)
+        );
+
+        bytes.push(0);
+        let result = Parser::new().parse(&bytes);
+        assert!(matches!(result, Err(ParseError::IntegrityCheckFailed)));
+    }
+
+    #[test]
+    fn integrity_detects_downgraded_version() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let mut bytes = swd.to_bytes();
+        bytes.truncate(bytes.len() - INTEGRITY_TRAILER_LENGTH);
+        let version = bytes
+            .windows(4)
+            .position(|window| window == VERSION.to_be_bytes())
+            .unwrap();
+        bytes[version + 3] = 3;
+
+        let mut swd = Parser::new().parse(&bytes).unwrap();
+        assert_eq!(swd.migrated_from(), Some(3));
+        swd.unlock(b"master key");
+        assert_eq!(swd.verify_integrity(), Err(ParseError::IntegrityUnverified));
+    }
+
+    pub(crate) fn add_dummy_record(swd: &mut Swd, label: &str, secret: &str) {
+        let nonce = [3u8; 12];
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), &nonce[..]);
+        let (encrypt, _) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let encrypted = encrypt(secret.as_bytes(), key, extras).unwrap();
+        let mut record = Record::new(label.to_owned(), encrypted.into_boxed_slice());
+        record.insert_extra("nonce", Value::new(&nonce, false));
+        swd.get_root_mut().add_record(record);
+    }
+
+    #[test]
+    fn import_json_roundtrip() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        let mut child = Collection::new("child".to_owned());
+        child.add_extra("icon", b"mail", false).unwrap();
+        swd.get_root_mut().add_child(child);
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        let exported = swd.export_json(decrypt, key).unwrap();
+
+        let registry = CipherRegistry::default();
+        let encrypt = registry.get_encryptor("aes256-gcm");
+        let decrypt = registry.get_decryptor("aes256-gcm");
+        let key = [7u8; 32];
+        let imported =
+            Swd::import_json(dummy_header(b"master key"), &exported, encrypt, &key).unwrap();
+
+        let root = imported.get_root();
+        assert_eq!(root.label(), "root");
+        assert_eq!(root.children()[0].label(), "child");
+        assert_eq!(
+            root.children()[0].get_extra("icon").unwrap().inner(),
+            b"mail"
+        );
+        let record = root.get_record(0).unwrap();
+        assert_eq!(record.label(), "abc");
+        assert_eq!(record.get_extra("nonce").unwrap().inner().len(), 12);
+        let secret = record.decrypt_secret(decrypt, &key).unwrap();
+        assert_eq!(secret.as_str(), "top secret");
+    }
+
+    #[test]
+    fn import_csv_creates_collections() {
+        let csv = "path,label,secret\n\
+                   ,Gmail,one\n\
+                   Work/Email,Outlook,two\n\
+                   Work,VPN,three\n\
+                   /Work/Email/,Outlook,four\n";
+        let registry = CipherRegistry::default();
+        let encrypt = registry.get_encryptor("aes256-gcm");
+        let decrypt = registry.get_decryptor("aes256-gcm");
+        let key = [7u8; 32];
+        let imported = Swd::import_csv(
+            dummy_header(b"master key"),
+            "root".to_owned(),
+            csv,
+            encrypt,
+            &key,
+        )
+        .unwrap();
+
+        let root = imported.get_root();
+        assert_eq!(root.label(), "root");
+        assert_eq!(root.records().len(), 1);
+        assert_eq!(root.children().len(), 1);
+        let work = root.get_child(0).unwrap();
+        assert_eq!(work.label(), "Work");
+        assert_eq!(work.get_record(0).unwrap().label(), "VPN");
+        let email = work.get_child(0).unwrap();
+        assert_eq!(email.label(), "Email");
+        assert_eq!(email.records().len(), 2);
+        let secret = email.get_record(1).unwrap().decrypt_secret(decrypt, &key);
+        assert_eq!(secret.unwrap().as_str(), "four");
+        assert_eq!(
+            root.duplicate_labels(),
+            vec![vec!["root", "Work", "Email", "Outlook"]]
+        );
+    }
+
+    #[test]
+    fn import_json_invalid() {
+        let registry = CipherRegistry::default();
+        let encrypt = registry.get_encryptor("aes256-gcm");
+        let result = Swd::import_json(dummy_header(b"master key"), "{", encrypt, &[7u8; 32]);
+        assert!(matches!(result, Err(ImportError::Json(_))));
+    }
+
+    #[test]
+    fn unlock_with_pbkdf2() {
+        let hash_registry = HashFunctionRegistry::default();
+        let master_key_salt = [1u8; 16];
+        let master_key_hash =
+            hash_registry.hash_salted("pbkdf2-sha256", b"master key", &master_key_salt, 2);
+        let mut header = Header::new(
+            VERSION,
+            "pbkdf2-sha256".to_owned(),
+            "pbkdf2-sha256".to_owned(),
+            "aes256-gcm".to_owned(),
+            &master_key_hash,
+            &master_key_salt,
+            &[2u8; 16],
+            HashMap::new(),
+        );
+        header.set_kdf_iterations(2);
+        let mut swd = Swd::new(
+            header,
+            "root".to_owned(),
+            CipherRegistry::default(),
+            hash_registry,
+        );
+
+        assert!(!swd.unlock(b"wrong key"));
+        assert!(swd.unlock(b"master key"));
+        assert_eq!(swd.header().get_key().unwrap().len(), 32);
+        add_dummy_record(&mut swd, "abc", "top secret");
+
+        let mut swd = Parser::new().parse(&swd.to_bytes()).unwrap();
+        assert_eq!(swd.header().kdf_iterations(), 2);
+        assert!(swd.unlock(b"master key"));
+    }
+
+    #[test]
+    fn header_algorithm_accessors() {
+        let header = Header::new(
+            VERSION,
+            "pbkdf2-sha256".to_owned(),
+            "sha3-256".to_owned(),
+            "aes128-gcm".to_owned(),
+            &[1, 2, 3],
+            &[4, 5, 6],
+            &[7, 8, 9],
+            HashMap::new(),
+        );
+        let names: [&str; 3] = [
+            header.master_key_hash_fn(),
+            header.key_hash_fn(),
+            header.key_cipher(),
+        ];
+        assert_eq!(names, ["pbkdf2-sha256", "sha3-256", "aes128-gcm"]);
+        assert_eq!(header.master_key_hash(), &[1, 2, 3]);
+        assert_eq!(header.master_key_salt(), &[4, 5, 6]);
+        assert_eq!(header.key_salt(), &[7, 8, 9]);
+    }
+
+    #[test]
+    fn header_extras_iter_sorted_by_key() {
+        let mut header = dummy_header(b"master key");
+        assert_eq!(header.extras_iter().count(), 0);
+        header.update_extra(DESCRIPTION_FIELD, Value::new(b"Personal", false));
+        header.update_extra(KDF_ITERATIONS_FIELD, Value::u64(1000, false));
+        header.update_extra("api", Value::new(b"hidden", true));
+
+        let extras: Vec<(&str, String)> = header
+            .extras_iter()
+            .map(|(key, value)| (key.as_str(), value.describe()))
+            .collect();
+        assert_eq!(
+            extras,
+            vec![
+                ("api", "(secret)".to_owned()),
+                ("desc", "Personal".to_owned()),
+                ("kdf_iter", "1000".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn extras_serialize_in_key_order() {
+        let keys = ["desc", "kdf_iter", "favorites", "a", "zz", "last_opened"];
+        let mut header = dummy_header(b"master key");
+        let mut reversed = dummy_header(b"master key");
+        for key in keys {
+            header.update_extra(key, Value::new(key.as_bytes(), false));
+        }
+        for key in keys.iter().rev() {
+            reversed.update_extra(key, Value::new(key.as_bytes(), false));
+        }
+        assert_eq!(header.to_bytes(), reversed.to_bytes());
+
+        let mut record = Record::new("abc".to_owned(), Box::new([1]));
+        let mut reversed = record.clone();
+        for key in keys {
+            record.add_extra(key, b"value", false).unwrap();
+        }
+        for key in keys.iter().rev() {
+            reversed.add_extra(key, b"value", false).unwrap();
+        }
+        assert_eq!(record.to_bytes(), reversed.to_bytes());
+
+        let bytes = header.to_bytes();
+        let position = |key: &str| {
+            let key = Value::str_to_bytes(key, false);
+            bytes
+                .windows(key.len())
+                .position(|window| window == key)
+                .unwrap()
+        };
+        assert!(position("a") < position("desc"));
+        assert!(position("desc") < position("favorites"));
+        assert!(position("last_opened") < position("zz"));
+    }
+
+    pub(crate) fn dummy_header(master_key: &[u8]) -> Header {
+        let hash_registry = HashFunctionRegistry::default();
+        let master_key_salt = [1u8; 16];
+        let key_salt = [2u8; 16];
+        let mut salted_master_key = master_key.to_vec();
+        salted_master_key.extend_from_slice(&master_key_salt);
+        let hash = hash_registry.get_function("sha3-256");
+        let master_key_hash = hash(&salted_master_key);
+
+        Header::new(
+            1,
+            "sha3-256".to_owned(),
+            "sha3-256".to_owned(),
+            "aes256-gcm".to_owned(),
+            &master_key_hash,
+            &master_key_salt,
+            &key_salt,
+            HashMap::new(),
+        )
+    }
+
+    pub(crate) fn dummy_swd(master_key: &[u8]) -> Swd {
+        Swd::new(
+            dummy_header(master_key),
+            "root".to_owned(),
+            CipherRegistry::default(),
+            HashFunctionRegistry::default(),
+        )
+    }
+}
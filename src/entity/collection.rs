@@ -1,16 +1,56 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
-use crate::error::ParseError;
+use zeroize::Zeroizing;
 
-use super::{record::Record, value::Value, Entries};
+use crate::{
+    cipher::{CipherResult, DecryptFn, EncryptFn},
+    error::{IndexError, ParseError, ReservedKeyError},
+    reserved::{is_reserved_collection_key, is_reserved_record_key},
+};
+
+use super::{
+    entries_to_bytes,
+    label::{SealedLabel, ENCRYPTED_LABEL_PLACEHOLDER, LABEL_FIELD},
+    portable::PortableCollection,
+    record::Record,
+    value::Value,
+    Entries,
+};
 
 pub const COLLECTION_STARTER_BYTE: u8 = 0x03;
 pub const COLLECTION_ENDER_BYTE: u8 = 0x04;
+pub const COLLECTION_LENGTH_BYTES_LENGTH: usize = 4;
+/// First format version whose collections carry a length prefix
+pub const COLLECTION_LENGTH_VERSION: u32 = 2;
 
 pub const REQUIRED_COLLECTION_FIELDS: [&str; 1] = ["label"];
 
+/// How `Collection::merge` resolves a record whose label is taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Drops the incoming record
+    KeepExisting,
+    /// Replaces the existing record in place
+    Overwrite,
+    /// Adds the incoming record next to the existing one. Both keep their
+    /// label, as relabeling would break secrets bound to it, and show up
+    /// in `Collection::duplicate_labels`.
+    KeepBoth,
+}
+
+/// Record or collection detached by `Collection::take_by_path`
+#[derive(Debug)]
+pub enum Removed {
+    Record(Record),
+    /// Collection along with everything below it
+    Collection(Collection),
+}
+
 /// Collection structure
-/// ```
+/// ```text
 /// [STARTER_BYTE]
 /// [LENGTH]
 /// [METADATA]:
@@ -23,13 +63,21 @@ pub const REQUIRED_COLLECTION_FIELDS: [&str; 1] = ["label"];
 /// [RECORD]
 /// ...
 /// [RECORD]
+/// [ENDER_BYTE]
 /// ```
 ///
 /// Length consist of 4 byte ordered in big endian ordering
-/// Length is required to determine where does the collection end
-#[derive(Debug)]
+/// Length counts the bytes between itself and the ender byte,
+/// so that a collection can be skipped without parsing it.
+/// Files older than format version 2 have no length.
+///
+/// In vaults with encrypted labels, the label is stored sealed as
+/// described in `SealedLabel`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Collection {
     label: String,
+    /// Stored label while it is encrypted, `label` then being a placeholder
+    sealed_label: Option<SealedLabel>,
     children: Vec<Collection>,
     records: Vec<Record>,
     extras: Entries,
@@ -39,6 +87,7 @@ impl Collection {
     pub fn new(label: String) -> Self {
         Self {
             label,
+            sealed_label: None,
             children: vec![],
             records: vec![],
             extras: HashMap::new(),
@@ -73,50 +122,643 @@ impl Collection {
         self.children.get_mut(index)
     }
 
+    /// Follows child indices down from this collection
+    pub fn get_descendant(&self, indices: &[usize]) -> Option<&Collection> {
+        indices
+            .iter()
+            .try_fold(self, |collection, &index| collection.get_child(index))
+    }
+
+    pub fn get_descendant_mut(&mut self, indices: &[usize]) -> Option<&mut Collection> {
+        indices
+            .iter()
+            .try_fold(self, |collection, &index| collection.get_child_mut(index))
+    }
+
+    /// Follows child labels down from this collection, taking the first
+    /// child with a matching label at each step
+    pub fn get_by_path(&self, path: &[&str]) -> Option<&Collection> {
+        path.iter().try_fold(self, |collection, &label| {
+            collection
+                .children
+                .iter()
+                .find(|child| child.label == label)
+        })
+    }
+
+    pub fn get_by_path_mut(&mut self, path: &[&str]) -> Option<&mut Collection> {
+        path.iter().try_fold(self, |collection, &label| {
+            collection
+                .children
+                .iter_mut()
+                .find(|child| child.label == label)
+        })
+    }
+
+    /// Like `get_by_path_mut`, but creates the collections
+    /// missing along the path
+    pub fn get_or_create_by_path(&mut self, path: &[&str]) -> &mut Collection {
+        path.iter().fold(self, |collection, &label| {
+            let index = match collection
+                .children
+                .iter()
+                .position(|child| child.label == label)
+            {
+                Some(index) => index,
+                None => {
+                    collection.add_child(Collection::new(label.to_owned()));
+                    collection.children.len() - 1
+                }
+            };
+            &mut collection.children[index]
+        })
+    }
+
+    /// Locates the record at `path`, the labels of the collections leading
+    /// to it followed by its own label, by the child indices leading to its
+    /// collection and its index there. The first match is taken at each step.
+    pub fn record_address(&self, path: &[&str]) -> Option<(Vec<usize>, usize)> {
+        let (label, path) = path.split_last()?;
+        let mut location = vec![];
+        let mut collection = self;
+        for child_label in path {
+            let index = collection
+                .children
+                .iter()
+                .position(|child| child.label == *child_label)?;
+            location.push(index);
+            collection = &collection.children[index];
+        }
+        let index = collection
+            .records
+            .iter()
+            .position(|record| record.label() == label)?;
+        Some((location, index))
+    }
+
+    /// Plaintext copy of this collection and its descendants, with
+    /// every record secret decrypted by `decrypt_secret`, which picks
+    /// the cipher of each record, see `Swd::to_portable`
+    pub fn to_portable(
+        &self,
+        decrypt_secret: &dyn Fn(&Record) -> CipherResult<Zeroizing<String>>,
+    ) -> CipherResult<PortableCollection> {
+        PortableCollection::from_collection_with(self, decrypt_secret)
+    }
+
     pub fn set_label(&mut self, label: &str) {
         self.label = label.to_owned();
+        self.sealed_label = None;
+    }
+
+    /// Whether the label is encrypted and not decrypted yet,
+    /// `label` returning a placeholder until it is
+    pub fn is_label_sealed(&self) -> bool {
+        self.sealed_label.is_some()
+    }
+
+    /// Encrypts the labels of this collection and of everything below
+    /// it that are not encrypted yet, replacing them with a placeholder
+    pub(crate) fn seal_labels(
+        &mut self,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<()> {
+        if self.sealed_label.is_none() {
+            self.sealed_label = Some(SealedLabel::seal(&self.label, encrypt_fn, key, nonce_size)?);
+            self.label = ENCRYPTED_LABEL_PLACEHOLDER.to_owned();
+        }
+        for record in self.records.iter_mut() {
+            record.seal_label(encrypt_fn, key, nonce_size)?;
+        }
+        for child in self.children.iter_mut() {
+            child.seal_labels(encrypt_fn, key, nonce_size)?;
+        }
+        Ok(())
+    }
+
+    /// Decrypts the encrypted labels of this collection and of everything
+    /// below it, returning how many failed to decrypt. Those are kept
+    /// encrypted, so that they are written back as they were read.
+    pub(crate) fn unseal_labels(&mut self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> usize {
+        let mut failures = 0;
+        if let Some(sealed_label) = &self.sealed_label {
+            match sealed_label.open(decrypt_fn, key) {
+                Ok(label) => {
+                    self.label = label;
+                    self.sealed_label = None;
+                }
+                Err(_) => failures += 1,
+            }
+        }
+        for record in self.records.iter_mut() {
+            if record.unseal_label(decrypt_fn, key).is_err() {
+                failures += 1;
+            }
+        }
+        for child in self.children.iter_mut() {
+            failures += child.unseal_labels(decrypt_fn, key);
+        }
+        failures
     }
 
-    pub fn add_extra(&mut self, key: &str, value: &[u8], is_secret: bool) {
+    /// Inserts or overwrites the extra `key`, failing if it is
+    /// reserved, see `reserved::is_reserved_collection_key`
+    pub fn add_extra(
+        &mut self,
+        key: &str,
+        value: &[u8],
+        is_secret: bool,
+    ) -> Result<(), ReservedKeyError> {
+        if is_reserved_collection_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            });
+        }
         self.extras
             .insert(key.to_owned(), Value::new(value, is_secret));
+        Ok(())
     }
 
     pub fn get_extra(&self, key: &str) -> Option<&Value> {
         self.extras.get(key)
     }
 
+    pub fn extras(&self) -> &Entries {
+        &self.extras
+    }
+
+    /// Replaces `from` with `to` in the non-secret text extra `key` of this
+    /// collection, its descendants and all their records. Secret values are
+    /// never touched. Returns how many collections and records changed, or
+    /// fails without changing anything if `key` is reserved for either.
+    pub fn replace_extra(
+        &mut self,
+        key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<usize, ReservedKeyError> {
+        if is_reserved_collection_key(key) || is_reserved_record_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            });
+        }
+        let mut updated = self.replace_collection_extra(key, from, to);
+        for (_, record) in self.iter_all_records_mut() {
+            if record.replace_extra(key, from, to)? {
+                updated += 1;
+            }
+        }
+        Ok(updated)
+    }
+
+    fn replace_collection_extra(&mut self, key: &str, from: &str, to: &str) -> usize {
+        let mut updated = 0;
+        if let Some(value) = self
+            .extras
+            .get(key)
+            .and_then(|value| value.replace_text(from, to))
+        {
+            self.extras.insert(key.to_owned(), value);
+            updated += 1;
+        }
+        for child in self.children.iter_mut() {
+            updated += child.replace_collection_extra(key, from, to);
+        }
+        updated
+    }
+
     pub fn add_record(&mut self, record: Record) {
         self.records.push(record);
     }
 
+    /// Detaches the record at `index`, shifting the following records back
+    pub fn take_record(&mut self, index: usize) -> Option<Record> {
+        if index >= self.records.len() {
+            return None;
+        }
+        Some(self.records.remove(index))
+    }
+
     pub fn add_child(&mut self, child: Collection) {
         self.children.push(child);
     }
 
+    /// Detaches the child at `index`, shifting the following children back
+    pub fn take_child(&mut self, index: usize) -> Option<Collection> {
+        if index >= self.children.len() {
+            return None;
+        }
+        Some(self.children.remove(index))
+    }
+
+    /// Detaches the record or child collection at `path`, the labels of
+    /// the collections leading to it followed by its own label. A record
+    /// is taken over a collection labelled the same.
+    pub fn take_by_path(&mut self, path: &[&str]) -> Option<Removed> {
+        let (label, path) = path.split_last()?;
+        let parent = self.get_by_path_mut(path)?;
+        if let Some(index) = parent
+            .records
+            .iter()
+            .position(|record| record.label() == label)
+        {
+            return parent.take_record(index).map(Removed::Record);
+        }
+        let index = parent
+            .children
+            .iter()
+            .position(|child| child.label == *label)?;
+        parent.take_child(index).map(Removed::Collection)
+    }
+
+    /// Moves the records and children of `other` into this collection.
+    /// Children labeled the same as one of ours are merged into it,
+    /// records labeled the same as one of ours are resolved by
+    /// `on_conflict`. Extras of `other` are only added where missing,
+    /// its label is dropped.
+    pub fn merge(&mut self, other: Collection, on_conflict: ConflictPolicy) {
+        for (key, value) in other.extras {
+            self.extras.entry(key).or_insert(value);
+        }
+
+        for child in other.children {
+            match self
+                .children
+                .iter_mut()
+                .find(|own| own.label == child.label)
+            {
+                Some(own) => own.merge(child, on_conflict),
+                None => self.children.push(child),
+            }
+        }
+
+        for record in other.records {
+            let Some(index) = self
+                .records
+                .iter()
+                .position(|own| own.label() == record.label())
+            else {
+                self.records.push(record);
+                continue;
+            };
+            match on_conflict {
+                ConflictPolicy::KeepExisting => {}
+                ConflictPolicy::Overwrite => self.records[index] = record,
+                ConflictPolicy::KeepBoth => self.records.push(record),
+            }
+        }
+    }
+
+    /// Moves the record at `from` to `to`, shifting the records in between
+    pub fn move_record(&mut self, from: usize, to: usize) -> Result<(), IndexError> {
+        move_entry(&mut self.records, from, to)
+    }
+
+    /// Moves the child at `from` to `to`, shifting the children in between
+    pub fn move_child(&mut self, from: usize, to: usize) -> Result<(), IndexError> {
+        move_entry(&mut self.children, from, to)
+    }
+
+    /// Sorts the records by label ignoring case,
+    /// keeping records with equal labels in their current order
+    pub fn sort_records_by_label(&mut self) {
+        self.records
+            .sort_by_cached_key(|record| record.label().to_lowercase());
+    }
+
+    /// Sorts the child collections the same way as `sort_records_by_label`
+    pub fn sort_children_by_label(&mut self) {
+        self.children
+            .sort_by_cached_key(|child| child.label().to_lowercase());
+    }
+
+    /// Number of records directly in this collection
+    pub fn record_count(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Number of records in this collection and all its descendants
+    pub fn total_record_count(&self) -> usize {
+        self.records.len()
+            + self
+                .children
+                .iter()
+                .map(Collection::total_record_count)
+                .sum::<usize>()
+    }
+
+    /// Number of collections directly in this collection
+    pub fn child_count(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Removes the descendant collections without any record in them or
+    /// below them, returning how many were removed. This collection is
+    /// kept even if it ends up empty.
+    pub fn prune_empty(&mut self) -> usize {
+        let mut removed: usize = self.children.iter_mut().map(Collection::prune_empty).sum();
+        let count = self.children.len();
+        self.children
+            .retain(|child| !child.records.is_empty() || !child.children.is_empty());
+        removed += count - self.children.len();
+        removed
+    }
+
+    pub fn has_child_label(&self, label: &str) -> bool {
+        self.children.iter().any(|child| child.label() == label)
+    }
+
+    /// Indices of the records to list, leaving out
+    /// archived ones unless `include_archived` is set
+    pub fn listed_record_indices(&self, include_archived: bool) -> Vec<usize> {
+        self.records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| include_archived || !record.is_archived())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn has_record_label(&self, label: &str) -> bool {
+        self.records.iter().any(|record| record.label() == label)
+    }
+
+    /// Searches this collection and its descendants depth-first for records
+    /// whose label contains `query`, ignoring case. Each hit is paired with
+    /// the labels of the collections leading to it, starting from this one.
+    pub fn find_records(&self, query: &str) -> Vec<(Vec<String>, &Record)> {
+        let query = query.to_lowercase();
+        let mut hits = vec![];
+        self.collect_records(&query, &mut vec![], &mut hits);
+        hits
+    }
+
+    /// Every record along with the path of its collection,
+    /// in the same order as `find_records`
+    pub fn records_with_paths(&self) -> Vec<(Vec<String>, &Record)> {
+        self.iter_all_records().collect()
+    }
+
+    /// Walks every record of this collection and its descendants without
+    /// collecting them, in the same order as `find_records`. Each record is
+    /// paired with the labels of the collections leading to it.
+    pub fn iter_all_records(&self) -> RecordsIter<'_> {
+        RecordsIter {
+            collections: self.iter_all_collections(),
+            current: None,
+        }
+    }
+
+    /// Walks this collection and all of its descendants, this one first, in
+    /// the order `iter_all_records` visits them. Each collection is paired
+    /// with the labels leading to it, its own label included.
+    pub fn iter_all_collections(&self) -> CollectionsIter<'_> {
+        CollectionsIter {
+            stack: vec![(vec![self.label.clone()], self)],
+        }
+    }
+
+    /// Lines drawing this collection and its descendants as a tree, this
+    /// collection first, records listed before the child collections of
+    /// their collection. Entries deeper than `max_depth` are left out, the
+    /// direct children of this collection being at depth 1.
+    pub fn tree_lines(&self, max_depth: Option<usize>) -> Vec<TreeLine> {
+        let within = |depth: usize| max_depth.is_none_or(|max_depth| depth <= max_depth);
+        let mut entries = vec![];
+        for (path, collection) in self.iter_all_collections() {
+            let depth = path.len() - 1;
+            if !within(depth) {
+                continue;
+            }
+            entries.push((depth, collection.label.as_str(), true));
+            if within(depth + 1) {
+                entries.extend(
+                    (collection.records.iter())
+                        .map(|record| (depth + 1, record.label().as_str(), false)),
+                );
+            }
+        }
+
+        // An entry is the last of its siblings when no entry at the same
+        // depth follows it before the walk climbs back above that depth
+        let mut is_last = vec![false; entries.len()];
+        let mut seen = vec![];
+        for (index, &(depth, ..)) in entries.iter().enumerate().rev() {
+            seen.resize(depth + 1, false);
+            is_last[index] = !seen[depth];
+            seen[depth] = true;
+        }
+
+        let mut guides: Vec<&str> = vec![];
+        let mut lines = vec![];
+        for ((depth, label, is_collection), is_last) in entries.into_iter().zip(is_last) {
+            let mut prefix = String::new();
+            if depth > 0 {
+                guides.truncate(depth - 1);
+                prefix = guides.concat();
+                prefix.push_str(if is_last { "└── " } else { "├── " });
+                guides.push(if is_last { "    " } else { "│   " });
+            }
+            lines.push(TreeLine {
+                prefix,
+                label: label.to_owned(),
+                is_collection,
+            });
+        }
+        lines
+    }
+
+    /// Same as `iter_all_records`, in the same order
+    pub fn iter_all_records_mut(&mut self) -> RecordsIterMut<'_> {
+        RecordsIterMut {
+            stack: vec![(vec![self.label.clone()], self)],
+            current: None,
+        }
+    }
+
+    /// Same search as `find_records`, in the same order, locating each hit by
+    /// the child indices leading to its collection and its index there
+    pub fn find_record_addresses(&self, query: &str) -> Vec<(Vec<usize>, usize)> {
+        let query = query.to_lowercase();
+        let is_hit = |record: &Record| record.label().to_lowercase().contains(&query);
+        let mut hits = vec![];
+        self.collect_record_addresses(&is_hit, &mut vec![], &mut hits);
+        hits
+    }
+
+    /// Records of this collection and its descendants tagged `tag`, see
+    /// `Record::has_tag`, in the same order and form as `find_records`
+    pub fn find_by_tag(&self, tag: &str) -> Vec<(Vec<String>, &Record)> {
+        self.iter_all_records()
+            .filter(|(_, record)| record.has_tag(tag))
+            .collect()
+    }
+
+    /// Same search as `find_by_tag`, in the same order, locating each hit
+    /// like `find_record_addresses`
+    pub fn find_record_addresses_by_tag(&self, tag: &str) -> Vec<(Vec<usize>, usize)> {
+        let is_hit = |record: &Record| record.has_tag(tag);
+        let mut hits = vec![];
+        self.collect_record_addresses(&is_hit, &mut vec![], &mut hits);
+        hits
+    }
+
+    pub fn find_records_mut(&mut self, query: &str) -> Vec<(Vec<String>, &mut Record)> {
+        let query = query.to_lowercase();
+        let mut hits = vec![];
+        self.collect_records_mut(&query, &mut vec![], &mut hits);
+        hits
+    }
+
+    /// Collects every record of this collection and its descendants,
+    /// depth-first with a collection's own records before its children's
+    pub(crate) fn all_records(&self) -> Vec<&Record> {
+        self.iter_all_records().map(|(_, record)| record).collect()
+    }
+
+    /// Same as `all_records`, in the same order
+    pub(crate) fn all_records_mut(&mut self) -> Vec<&mut Record> {
+        self.iter_all_records_mut()
+            .map(|(_, record)| record)
+            .collect()
+    }
+
+    /// Lists the labels leading to this collection and to each of its
+    /// descendants, depth-first with a collection before its children
+    pub fn collection_paths(&self) -> Vec<Vec<String>> {
+        let mut paths = vec![];
+        self.collect_collection_paths(&mut vec![], &mut paths);
+        paths
+    }
+
+    fn collect_collection_paths(&self, path: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+        path.push(self.label.clone());
+        paths.push(path.clone());
+        for child in self.children.iter() {
+            child.collect_collection_paths(path, paths);
+        }
+        path.pop();
+    }
+
+    /// Lists the paths of collections and records that share their label
+    /// with an earlier sibling of the same kind, starting from this one
+    pub fn duplicate_labels(&self) -> Vec<Vec<String>> {
+        let mut duplicates = vec![];
+        self.collect_duplicate_labels(&mut vec![], &mut duplicates);
+        duplicates
+    }
+
+    fn collect_duplicate_labels(&self, path: &mut Vec<String>, duplicates: &mut Vec<Vec<String>>) {
+        path.push(self.label.clone());
+
+        let mut seen = HashSet::new();
+        for child in self.children.iter() {
+            if !seen.insert(child.label()) {
+                let mut duplicate = path.clone();
+                duplicate.push(child.label().clone());
+                duplicates.push(duplicate);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for record in self.records.iter() {
+            if !seen.insert(record.label()) {
+                let mut duplicate = path.clone();
+                duplicate.push(record.label().clone());
+                duplicates.push(duplicate);
+            }
+        }
+
+        for child in self.children.iter() {
+            child.collect_duplicate_labels(path, duplicates);
+        }
+        path.pop();
+    }
+
+    fn collect_records<'a>(
+        &'a self,
+        query: &str,
+        path: &mut Vec<String>,
+        hits: &mut Vec<(Vec<String>, &'a Record)>,
+    ) {
+        path.push(self.label.clone());
+        for record in self.records.iter() {
+            if record.label().to_lowercase().contains(query) {
+                hits.push((path.clone(), record));
+            }
+        }
+        for child in self.children.iter() {
+            child.collect_records(query, path, hits);
+        }
+        path.pop();
+    }
+
+    fn collect_record_addresses(
+        &self,
+        is_hit: &dyn Fn(&Record) -> bool,
+        location: &mut Vec<usize>,
+        hits: &mut Vec<(Vec<usize>, usize)>,
+    ) {
+        for (index, record) in self.records.iter().enumerate() {
+            if is_hit(record) {
+                hits.push((location.clone(), index));
+            }
+        }
+        for (index, child) in self.children.iter().enumerate() {
+            location.push(index);
+            child.collect_record_addresses(is_hit, location, hits);
+            location.pop();
+        }
+    }
+
+    fn collect_records_mut<'a>(
+        &'a mut self,
+        query: &str,
+        path: &mut Vec<String>,
+        hits: &mut Vec<(Vec<String>, &'a mut Record)>,
+    ) {
+        path.push(self.label.clone());
+        for record in self.records.iter_mut() {
+            if record.label().to_lowercase().contains(query) {
+                hits.push((path.clone(), record));
+            }
+        }
+        for child in self.children.iter_mut() {
+            child.collect_records_mut(query, path, hits);
+        }
+        path.pop();
+    }
+
     fn label_bytes() -> Vec<u8> {
         Value::new(b"label", false).to_bytes()
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![];
-        bytes.push(COLLECTION_STARTER_BYTE);
-        bytes.extend_from_slice(&Self::label_bytes());
-        bytes.extend_from_slice(&Value::str_to_bytes(&self.label, false));
-
-        for (key, value) in self.extras.iter() {
-            bytes.extend_from_slice(&Value::str_to_bytes(key, false));
-            bytes.extend_from_slice(&value.to_bytes());
+        let mut body = vec![];
+        match &self.sealed_label {
+            Some(sealed_label) => body.extend_from_slice(&sealed_label.to_bytes()),
+            None => {
+                body.extend_from_slice(&Self::label_bytes());
+                body.extend_from_slice(&Value::str_to_bytes(&self.label, false));
+            }
         }
 
+        body.extend_from_slice(&entries_to_bytes(&self.extras));
+
         for collection in self.children.iter() {
-            bytes.extend_from_slice(&collection.to_bytes());
+            body.extend_from_slice(&collection.to_bytes());
         }
 
         for record in self.records.iter() {
-            bytes.extend_from_slice(&record.to_bytes());
+            body.extend_from_slice(&record.to_bytes());
         }
 
+        let mut bytes = Vec::with_capacity(body.len() + COLLECTION_LENGTH_BYTES_LENGTH + 2);
+        bytes.push(COLLECTION_STARTER_BYTE);
+        bytes.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        bytes.append(&mut body);
         bytes.push(COLLECTION_ENDER_BYTE);
         bytes
     }
@@ -134,18 +776,752 @@ impl TryFrom<(Vec<Collection>, Vec<Record>, Entries)> for Collection {
                 return Err(ParseError::MissingRequiredField(required_field.to_owned()));
             }
 
-            if extras.get(required_field).unwrap().is_secret() {
+            // The label is only secret when encrypted, which `take_label` checks
+            if required_field != LABEL_FIELD && extras.get(required_field).unwrap().is_secret() {
                 return Err(ParseError::ForbiddenSecretField(required_field.to_owned()));
             }
         }
 
-        let label = extras.remove("label").unwrap().parse_string()?;
+        let (label, sealed_label) = SealedLabel::take_label(&mut extras)?;
 
         Ok(Self {
             label,
+            sealed_label,
             children,
             records,
             extras,
         })
     }
 }
+
+/// Depth-first iterator over the records of a collection tree,
+/// see `Collection::iter_all_records`.
+///
+/// Pending collections are kept on an explicit stack rather than
+/// recursing, so deeply nested trees cannot overflow the call stack.
+fn move_entry<T>(entries: &mut Vec<T>, from: usize, to: usize) -> Result<(), IndexError> {
+    let len = entries.len();
+    if let Some(index) = [from, to].into_iter().find(|&index| index >= len) {
+        return Err(IndexError { index, len });
+    }
+    let entry = entries.remove(from);
+    entries.insert(to, entry);
+    Ok(())
+}
+
+/// A line of `Collection::tree_lines`, displayed as its prefix followed by
+/// its label, with a trailing slash for collections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeLine {
+    /// Guides and connector drawn before the label
+    pub prefix: String,
+    pub label: String,
+    pub is_collection: bool,
+}
+
+impl Display for TreeLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.prefix, self.label)?;
+        if self.is_collection {
+            write!(f, "/")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct CollectionsIter<'a> {
+    stack: Vec<(Vec<String>, &'a Collection)>,
+}
+
+impl<'a> Iterator for CollectionsIter<'a> {
+    type Item = (Vec<String>, &'a Collection);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, collection) = self.stack.pop()?;
+        for child in collection.children.iter().rev() {
+            let mut child_path = path.clone();
+            child_path.push(child.label.clone());
+            self.stack.push((child_path, child));
+        }
+        Some((path, collection))
+    }
+}
+
+pub struct RecordsIter<'a> {
+    collections: CollectionsIter<'a>,
+    current: Option<(Vec<String>, std::slice::Iter<'a, Record>)>,
+}
+
+impl<'a> Iterator for RecordsIter<'a> {
+    type Item = (Vec<String>, &'a Record);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((path, records)) = self.current.as_mut() {
+                if let Some(record) = records.next() {
+                    return Some((path.clone(), record));
+                }
+            }
+
+            let (path, collection) = self.collections.next()?;
+            self.current = Some((path, collection.records.iter()));
+        }
+    }
+}
+
+/// Mutable counterpart of `RecordsIter`,
+/// see `Collection::iter_all_records_mut`
+pub struct RecordsIterMut<'a> {
+    stack: Vec<(Vec<String>, &'a mut Collection)>,
+    current: Option<(Vec<String>, std::slice::IterMut<'a, Record>)>,
+}
+
+impl<'a> Iterator for RecordsIterMut<'a> {
+    type Item = (Vec<String>, &'a mut Record);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((path, records)) = self.current.as_mut() {
+                if let Some(record) = records.next() {
+                    return Some((path.clone(), record));
+                }
+            }
+
+            let (path, collection) = self.stack.pop()?;
+            let Collection {
+                children, records, ..
+            } = collection;
+            for child in children.iter_mut().rev() {
+                let mut child_path = path.clone();
+                child_path.push(child.label.clone());
+                self.stack.push((child_path, child));
+            }
+            self.current = Some((path, records.iter_mut()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::{record::Record, value::Value},
+        error::{IndexError, ReservedKeyError},
+    };
+
+    use super::{Collection, ConflictPolicy};
+
+    #[test]
+    fn find_records_case_insensitive() {
+        let root = dummy_tree();
+        let hits = root.find_records("mail");
+        let found: Vec<(String, &str)> = hits
+            .iter()
+            .map(|(path, record)| (path.join("/"), record.label().as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("root".to_owned(), "Gmail"),
+                ("root/Work".to_owned(), "Work Email"),
+                ("root/Work/Legacy".to_owned(), "Old MAIL"),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_records_no_match() {
+        let root = dummy_tree();
+        assert!(root.find_records("nothing").is_empty());
+    }
+
+    #[test]
+    fn find_records_mut_matches_find_records() {
+        let mut root = dummy_tree();
+        let paths: Vec<Vec<String>> = root
+            .find_records("e")
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        let paths_mut: Vec<Vec<String>> = root
+            .find_records_mut("e")
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, paths_mut);
+    }
+
+    #[test]
+    fn find_record_addresses_matches_find_records() {
+        let root = dummy_tree();
+        let addresses = root.find_record_addresses("e");
+        let labels: Vec<&String> = addresses
+            .iter()
+            .map(|(location, index)| {
+                let collection = root.get_descendant(location).unwrap();
+                collection.get_record(*index).unwrap().label()
+            })
+            .collect();
+        let expected: Vec<&String> = root
+            .find_records("e")
+            .into_iter()
+            .map(|(_, record)| record.label())
+            .collect();
+        assert_eq!(labels, expected);
+        assert_eq!(addresses[0], (vec![0], 0));
+    }
+
+    #[test]
+    fn get_descendant_follows_indices() {
+        let mut root = dummy_tree();
+        assert_eq!(root.get_descendant(&[]).unwrap().label(), "root");
+        assert_eq!(root.get_descendant(&[0, 0]).unwrap().label(), "Legacy");
+        assert!(root.get_descendant(&[0, 1]).is_none());
+        assert!(root.get_descendant(&[1]).is_none());
+        let legacy = root.get_descendant_mut(&[0, 0]).unwrap();
+        legacy.set_label("Archive");
+        assert_eq!(
+            root.get_child(0).unwrap().get_child(0).unwrap().label(),
+            "Archive"
+        );
+    }
+
+    #[test]
+    fn get_by_path_follows_labels() {
+        let mut root = dummy_tree();
+        assert_eq!(root.get_by_path(&[]).unwrap().label(), "root");
+        assert_eq!(root.get_by_path(&["Work"]).unwrap().label(), "Work");
+        assert_eq!(
+            root.get_by_path(&["Work", "Legacy"]).unwrap().records()[0].label(),
+            "Old MAIL"
+        );
+        assert!(root.get_by_path(&["Legacy"]).is_none());
+        assert!(root.get_by_path(&["work"]).is_none());
+        assert!(root.get_by_path(&["Work", "Legacy", "Missing"]).is_none());
+        assert!(root.get_by_path(&["Work", "VPN"]).is_none());
+
+        root.get_by_path_mut(&["Work", "Legacy"])
+            .unwrap()
+            .add_record(dummy_record("Old VPN"));
+        assert_eq!(root.get_descendant(&[0, 0]).unwrap().records().len(), 2);
+        assert!(root.get_by_path_mut(&["Missing"]).is_none());
+    }
+
+    #[test]
+    fn take_record_detaches() {
+        let mut root = dummy_tree();
+        let work = root.get_child_mut(0).unwrap();
+        assert!(work.take_record(2).is_none());
+        let record = work.take_record(0).unwrap();
+        assert_eq!(record.label(), "Work Email");
+        assert_eq!(work.records().len(), 1);
+        assert_eq!(work.get_record(0).unwrap().label(), "VPN");
+        root.add_record(record);
+        assert_eq!(root.get_record(1).unwrap().label(), "Work Email");
+    }
+
+    #[test]
+    fn sort_by_label_ignores_case() {
+        let mut root = Collection::new("root".to_owned());
+        for label in ["beta", "Alpha", "gamma", "alpha", "Beta", "ALPHA"] {
+            root.add_record(dummy_record(label));
+            root.add_child(Collection::new(label.to_owned()));
+        }
+        let expected = vec!["Alpha", "alpha", "ALPHA", "beta", "Beta", "gamma"];
+
+        root.sort_records_by_label();
+        let labels: Vec<&String> = root.records().iter().map(Record::label).collect();
+        assert_eq!(labels, expected);
+
+        root.sort_children_by_label();
+        let labels: Vec<&String> = root.children().iter().map(Collection::label).collect();
+        assert_eq!(labels, expected);
+    }
+
+    #[test]
+    fn has_labels_per_sibling_kind() {
+        let root = dummy_tree();
+        assert!(root.has_child_label("Work"));
+        assert!(!root.has_child_label("work"));
+        assert!(!root.has_child_label("Gmail"));
+        assert!(!root.has_child_label("Legacy"));
+        assert!(root.has_record_label("Gmail"));
+        assert!(!root.has_record_label("Work"));
+        assert!(!root.has_record_label("VPN"));
+    }
+
+    #[test]
+    fn duplicate_labels_per_sibling_kind() {
+        let mut root = dummy_tree();
+        root.add_record(dummy_record("Gmail"));
+        root.add_record(dummy_record("Work"));
+        root.add_child(Collection::new("Work".to_owned()));
+        let work = root.get_child_mut(0).unwrap();
+        work.add_record(dummy_record("VPN"));
+        assert_eq!(
+            root.duplicate_labels(),
+            vec![
+                vec!["root", "Work"],
+                vec!["root", "Gmail"],
+                vec!["root", "Work", "VPN"],
+            ]
+        );
+    }
+
+    #[test]
+    fn iter_all_records_matches_find_records() {
+        let root = dummy_tree();
+        let walked: Vec<(String, &String)> = root
+            .iter_all_records()
+            .map(|(path, record)| (path.join("/"), record.label()))
+            .collect();
+        assert_eq!(
+            walked,
+            vec![
+                ("root".to_owned(), &"Gmail".to_owned()),
+                ("root/Work".to_owned(), &"Work Email".to_owned()),
+                ("root/Work".to_owned(), &"VPN".to_owned()),
+                ("root/Work/Legacy".to_owned(), &"Old MAIL".to_owned()),
+            ]
+        );
+        let found: Vec<(Vec<String>, &String)> = root
+            .find_records("")
+            .into_iter()
+            .map(|(path, record)| (path, record.label()))
+            .collect();
+        let walked: Vec<(Vec<String>, &String)> = root
+            .iter_all_records()
+            .map(|(path, record)| (path, record.label()))
+            .collect();
+        assert_eq!(walked, found);
+    }
+
+    #[test]
+    fn iter_all_records_mut_edits_in_place() {
+        let mut root = dummy_tree();
+        for (path, record) in root.iter_all_records_mut() {
+            let label = format!("{}/{}", path.join("/"), record.label());
+            record.set_label(&label);
+        }
+        let labels: Vec<&String> = root
+            .iter_all_records()
+            .map(|(_, record)| record.label())
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "root/Gmail",
+                "root/Work/Work Email",
+                "root/Work/VPN",
+                "root/Work/Legacy/Old MAIL",
+            ]
+        );
+    }
+
+    #[test]
+    fn iter_all_records_deeply_nested() {
+        const DEPTH: usize = 2000;
+        let mut collection = Collection::new(format!("{}", DEPTH));
+        collection.add_record(dummy_record("deepest"));
+        for depth in (0..DEPTH).rev() {
+            let mut parent = Collection::new(format!("{}", depth));
+            parent.add_child(collection);
+            collection = parent;
+        }
+        collection.add_record(dummy_record("shallowest"));
+
+        let records: Vec<(Vec<String>, &Record)> = collection.iter_all_records().collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].1.label(), "shallowest");
+        assert_eq!(records[1].1.label(), "deepest");
+        assert_eq!(records[1].0.len(), DEPTH + 1);
+
+        // Dropping the tree recurses, so unwind it by hand
+        while let Some(child) = collection.children.pop() {
+            collection = child;
+        }
+    }
+
+    #[test]
+    fn replace_extra_skips_secrets() {
+        let mut root = dummy_tree();
+        root.add_extra("category", b"work stuff", false).unwrap();
+        let work = root.get_child_mut(0).unwrap();
+        work.add_extra("category", b"work", true).unwrap();
+        work.get_record_mut(0)
+            .unwrap()
+            .add_extra("category", b"work", false)
+            .unwrap();
+        work.get_record_mut(1)
+            .unwrap()
+            .add_extra("category", b"work", true)
+            .unwrap();
+        root.get_record_mut(0)
+            .unwrap()
+            .add_extra("tag", b"work", false)
+            .unwrap();
+
+        assert_eq!(root.replace_extra("category", "work", "office"), Ok(2));
+        assert_eq!(
+            root.get_extra("category").unwrap().as_str(),
+            Some("office stuff")
+        );
+        let work = root.get_child(0).unwrap();
+        assert_eq!(work.get_extra("category").unwrap().inner(), b"work");
+        let category = |index: usize| work.get_record(index).unwrap().get_extra("category");
+        assert_eq!(category(0).unwrap().as_str(), Some("office"));
+        assert_eq!(category(1).unwrap().inner(), b"work");
+        assert!(category(1).unwrap().is_secret());
+        let tag = root.get_record(0).unwrap().get_extra("tag").unwrap();
+        assert_eq!(tag.as_str(), Some("work"));
+
+        assert_eq!(root.replace_extra("category", "work", "office"), Ok(0));
+        assert_eq!(root.replace_extra("category", "", "office"), Ok(0));
+    }
+
+    #[test]
+    fn replace_extra_rejects_reserved_keys() {
+        let mut root = dummy_tree();
+        let record = root.get_record_mut(0).unwrap();
+        record.insert_extra("cipher", Value::new(b"aes256-gcm", false));
+        record.insert_extra("binding", Value::new(b"label", false));
+
+        for key in ["cipher", "binding", "label", "label.nonce"] {
+            assert_eq!(
+                root.replace_extra(key, "a", "b"),
+                Err(ReservedKeyError {
+                    key: key.to_owned()
+                })
+            );
+        }
+        let record = root.get_record_mut(0).unwrap();
+        assert!(record.replace_extra("binding", "label", "x").is_err());
+        assert_eq!(record.get_extra("cipher").unwrap().inner(), b"aes256-gcm");
+        assert_eq!(record.get_extra("binding").unwrap().inner(), b"label");
+    }
+
+    #[test]
+    fn iter_all_records_empty() {
+        let root = Collection::new("root".to_owned());
+        assert_eq!(root.iter_all_records().count(), 0);
+    }
+
+    #[test]
+    fn get_or_create_by_path_creates_missing() {
+        let mut root = dummy_tree();
+        root.get_or_create_by_path(&["Work", "Legacy"])
+            .add_record(dummy_record("Old VPN"));
+        assert_eq!(
+            root.get_by_path(&["Work", "Legacy"])
+                .unwrap()
+                .record_count(),
+            2
+        );
+        assert_eq!(root.children()[0].child_count(), 1);
+
+        root.get_or_create_by_path(&["Home", "Router"])
+            .add_record(dummy_record("Admin"));
+        let router = root.get_by_path(&["Home", "Router"]).unwrap();
+        assert_eq!(router.records()[0].label(), "Admin");
+        assert_eq!(root.child_count(), 2);
+        assert_eq!(root.get_or_create_by_path(&[]).label(), "root");
+    }
+
+    #[test]
+    fn collection_paths_depth_first() {
+        let mut root = dummy_tree();
+        root.add_child(Collection::new("Home".to_owned()));
+        let paths = root.collection_paths();
+        let expected: Vec<Vec<String>> = [
+            vec!["root"],
+            vec!["root", "Work"],
+            vec!["root", "Work", "Legacy"],
+            vec!["root", "Home"],
+        ]
+        .iter()
+        .map(|path| path.iter().map(|label| label.to_string()).collect())
+        .collect();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn move_record_and_child() {
+        let mut root = dummy_tree();
+        root.add_record(dummy_record("Bank"));
+        root.add_record(dummy_record("Forum"));
+        root.move_record(2, 0).unwrap();
+        let labels: Vec<&String> = root.records().iter().map(Record::label).collect();
+        assert_eq!(labels, vec!["Forum", "Gmail", "Bank"]);
+        root.move_record(0, 2).unwrap();
+        let labels: Vec<&String> = root.records().iter().map(Record::label).collect();
+        assert_eq!(labels, vec!["Gmail", "Bank", "Forum"]);
+
+        root.add_child(Collection::new("Home".to_owned()));
+        root.move_child(1, 0).unwrap();
+        assert_eq!(root.children()[0].label(), "Home");
+        assert_eq!(root.children()[1].label(), "Work");
+    }
+
+    #[test]
+    fn move_out_of_bounds() {
+        let mut root = dummy_tree();
+        assert_eq!(root.move_record(1, 0), Err(IndexError { index: 1, len: 1 }));
+        assert_eq!(root.move_record(0, 3), Err(IndexError { index: 3, len: 1 }));
+        assert_eq!(root.move_child(0, 1), Err(IndexError { index: 1, len: 1 }));
+        assert_eq!(root.records()[0].label(), "Gmail");
+    }
+
+    #[test]
+    fn record_address_follows_labels() {
+        let root = dummy_tree();
+        assert_eq!(
+            root.record_address(&["Work", "Legacy", "Old MAIL"]),
+            Some((vec![0, 0], 0))
+        );
+        assert_eq!(root.record_address(&["Gmail"]), Some((vec![], 0)));
+        assert_eq!(root.record_address(&["Work", "Gmail"]), None);
+        assert_eq!(root.record_address(&["Home", "Gmail"]), None);
+        assert_eq!(root.record_address(&[]), None);
+    }
+
+    #[test]
+    fn record_and_child_counts() {
+        let root = dummy_tree();
+        assert_eq!(root.record_count(), 1);
+        assert_eq!(root.child_count(), 1);
+        assert_eq!(root.total_record_count(), 4);
+
+        let work = &root.children()[0];
+        assert_eq!(work.record_count(), 2);
+        assert_eq!(work.child_count(), 1);
+        assert_eq!(work.total_record_count(), 3);
+        assert_eq!(Collection::new("empty".to_owned()).total_record_count(), 0);
+    }
+
+    #[test]
+    fn cloned_subtree_is_independent() {
+        let root = dummy_tree();
+        let mut work = root.children()[0].clone();
+        assert_eq!(&work, &root.children()[0]);
+
+        work.get_record_mut(0)
+            .unwrap()
+            .set_secret(Box::new([1]), &[0; 12]);
+        assert_ne!(&work, &root.children()[0]);
+        assert_eq!(root.children()[0].records()[0].secret()[..], [0]);
+    }
+
+    #[test]
+    fn prune_empty_removes_empty_branches() {
+        let mut root = dummy_tree();
+        let mut archive = Collection::new("Archive".to_owned());
+        archive.add_child(Collection::new("2019".to_owned()));
+        archive.add_child(Collection::new("2020".to_owned()));
+        root.add_child(archive);
+        let work = root.get_child_mut(0).unwrap();
+        let mut old = Collection::new("Old".to_owned());
+        old.add_child(Collection::new("Older".to_owned()));
+        work.add_child(old);
+        work.get_child_mut(0)
+            .unwrap()
+            .add_child(Collection::new("Empty".to_owned()));
+
+        assert_eq!(root.prune_empty(), 6);
+        assert_eq!(
+            root.collection_paths(),
+            vec![
+                vec!["root".to_owned()],
+                vec!["root".to_owned(), "Work".to_owned()],
+                vec!["root".to_owned(), "Work".to_owned(), "Legacy".to_owned()],
+            ]
+        );
+        assert_eq!(root.total_record_count(), 4);
+        assert_eq!(root.prune_empty(), 0);
+    }
+
+    #[test]
+    fn prune_empty_keeps_empty_root() {
+        let mut root = Collection::new("root".to_owned());
+        root.add_child(Collection::new("Empty".to_owned()));
+        assert_eq!(root.prune_empty(), 1);
+        assert_eq!(root.child_count(), 0);
+        assert_eq!(root.label(), "root");
+    }
+
+    #[test]
+    fn find_by_tag_matches_addresses() {
+        let mut root = dummy_tree();
+        root.get_record_mut(0).unwrap().set_tags(&["mail"]);
+        let work = root.get_child_mut(0).unwrap();
+        work.get_record_mut(0).unwrap().set_tags(&["mail", "work"]);
+        work.get_child_mut(0)
+            .unwrap()
+            .get_record_mut(0)
+            .unwrap()
+            .set_tags(&[" Mail "]);
+
+        let hits = root.find_by_tag("mail");
+        let labels: Vec<&String> = hits.iter().map(|(_, record)| record.label()).collect();
+        assert_eq!(labels, vec!["Gmail", "Work Email", "Old MAIL"]);
+        assert_eq!(hits[2].0, vec!["root", "Work", "Legacy"]);
+        assert_eq!(
+            root.find_record_addresses_by_tag("mail"),
+            vec![(vec![], 0), (vec![0], 0), (vec![0, 0], 0)]
+        );
+        assert_eq!(root.find_by_tag("work").len(), 1);
+        assert!(root.find_by_tag("vpn").is_empty());
+        assert!(root.find_record_addresses_by_tag("").is_empty());
+    }
+
+    fn incoming_tree() -> Collection {
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(Record::new("VPN".to_owned(), Box::new([9])));
+        work.add_record(Record::new("Wiki".to_owned(), Box::new([9])));
+        let mut home = Collection::new("Home".to_owned());
+        home.add_record(Record::new("Router".to_owned(), Box::new([9])));
+        let mut root = Collection::new("other".to_owned());
+        root.add_record(Record::new("Gmail".to_owned(), Box::new([9])));
+        root.add_child(work);
+        root.add_child(home);
+        root
+    }
+
+    fn secrets_of<'a>(collection: &'a Collection, label: &str) -> Vec<&'a [u8]> {
+        collection
+            .records()
+            .iter()
+            .filter(|record| record.label() == label)
+            .map(|record| &record.secret()[..])
+            .collect()
+    }
+
+    #[test]
+    fn merge_keep_existing() {
+        let mut root = dummy_tree();
+        root.merge(incoming_tree(), ConflictPolicy::KeepExisting);
+
+        assert_eq!(root.label(), "root");
+        assert_eq!(secrets_of(&root, "Gmail"), vec![&[0][..]]);
+        let labels: Vec<&String> = root.children().iter().map(Collection::label).collect();
+        assert_eq!(labels, vec!["Work", "Home"]);
+        let work = root.get_child(0).unwrap();
+        assert_eq!(secrets_of(work, "VPN"), vec![&[0][..]]);
+        assert_eq!(secrets_of(work, "Wiki"), vec![&[9][..]]);
+        assert_eq!(work.child_count(), 1);
+        assert_eq!(root.total_record_count(), 6);
+        assert!(root.duplicate_labels().is_empty());
+    }
+
+    #[test]
+    fn merge_overwrite() {
+        let mut root = dummy_tree();
+        root.merge(incoming_tree(), ConflictPolicy::Overwrite);
+
+        assert_eq!(secrets_of(&root, "Gmail"), vec![&[9][..]]);
+        let work = root.get_child(0).unwrap();
+        assert_eq!(secrets_of(work, "VPN"), vec![&[9][..]]);
+        let labels: Vec<&String> = work.records().iter().map(Record::label).collect();
+        assert_eq!(labels, vec!["Work Email", "VPN", "Wiki"]);
+        assert_eq!(root.total_record_count(), 6);
+    }
+
+    #[test]
+    fn merge_keep_both() {
+        let mut root = dummy_tree();
+        root.merge(incoming_tree(), ConflictPolicy::KeepBoth);
+
+        assert_eq!(secrets_of(&root, "Gmail"), vec![&[0][..], &[9][..]]);
+        let work = root.get_child(0).unwrap();
+        assert_eq!(secrets_of(work, "VPN"), vec![&[0][..], &[9][..]]);
+        assert_eq!(root.total_record_count(), 8);
+        assert_eq!(root.duplicate_labels().len(), 2);
+    }
+
+    #[test]
+    fn archived_records_are_hidden_by_default() {
+        let mut work = dummy_tree().take_child(0).unwrap();
+        assert_eq!(work.listed_record_indices(false), vec![0, 1]);
+
+        work.get_record_mut(0).unwrap().set_archived(true);
+        assert!(work.get_record(0).unwrap().is_archived());
+        assert_eq!(work.listed_record_indices(false), vec![1]);
+        assert_eq!(work.listed_record_indices(true), vec![0, 1]);
+        assert_eq!(work.find_records("work email").len(), 1);
+
+        work.get_record_mut(0).unwrap().set_archived(false);
+        assert_eq!(work.listed_record_indices(false), vec![0, 1]);
+    }
+
+    fn dummy_record(label: &str) -> Record {
+        Record::new(label.to_owned(), Box::new([0]))
+    }
+
+    #[test]
+    fn tree_lines_draw_every_entry() {
+        let mut root = dummy_tree();
+        root.add_child(Collection::new("Empty".to_owned()));
+        let lines: Vec<String> = root
+            .tree_lines(None)
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "root/",
+                "├── Gmail",
+                "├── Work/",
+                "│   ├── Work Email",
+                "│   ├── VPN",
+                "│   └── Legacy/",
+                "│       └── Old MAIL",
+                "└── Empty/",
+            ]
+        );
+    }
+
+    #[test]
+    fn tree_lines_stop_at_max_depth() {
+        let root = dummy_tree();
+        let lines = |max_depth| -> Vec<String> {
+            (root.tree_lines(Some(max_depth)).iter())
+                .map(|line| line.to_string())
+                .collect()
+        };
+        assert_eq!(lines(0), ["root/"]);
+        assert_eq!(lines(1), ["root/", "├── Gmail", "└── Work/"]);
+        assert_eq!(
+            lines(2),
+            [
+                "root/",
+                "├── Gmail",
+                "└── Work/",
+                "    ├── Work Email",
+                "    ├── VPN",
+                "    └── Legacy/",
+            ]
+        );
+    }
+
+    #[test]
+    fn iter_all_collections_walks_in_record_order() {
+        let root = dummy_tree();
+        let paths: Vec<String> = root
+            .iter_all_collections()
+            .map(|(path, _)| path.join("/"))
+            .collect();
+        assert_eq!(paths, ["root", "root/Work", "root/Work/Legacy"]);
+    }
+
+    fn dummy_tree() -> Collection {
+        let mut legacy = Collection::new("Legacy".to_owned());
+        legacy.add_record(dummy_record("Old MAIL"));
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(dummy_record("Work Email"));
+        work.add_record(dummy_record("VPN"));
+        work.add_child(legacy);
+        let mut root = Collection::new("root".to_owned());
+        root.add_record(dummy_record("Gmail"));
+        root.add_child(work);
+        root
+    }
+}
@@ -0,0 +1,337 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use zeroize::Zeroizing;
+
+use crate::cipher::CipherResult;
+
+use super::{
+    record::{Record, CHECKSUM_FIELD, FIELD_NONCE_SUFFIX, SECRET_FIELD},
+    value::Value,
+    Swd,
+};
+
+/// Records added, removed and changed between two vaults, each located
+/// by the labels leading to it below the root, ending with its own label
+///
+/// Records sharing a path are paired up in the order they appear.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct VaultDiff {
+    pub added: Vec<Vec<String>>,
+    pub removed: Vec<Vec<String>>,
+    pub changed: Vec<RecordDiff>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecordDiff {
+    pub path: Vec<String>,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Change of a single record field. Secret fields are only
+/// ever reported by name, never with their values.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FieldChange {
+    Secret(String),
+    Added {
+        key: String,
+        value: String,
+    },
+    Removed {
+        key: String,
+        value: String,
+    },
+    Changed {
+        key: String,
+        old: String,
+        new: String,
+    },
+}
+
+impl Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldChange::Secret(field) => write!(f, "{}: (secret changed)", field),
+            FieldChange::Added { key, value } => write!(f, "{}: added \"{}\"", key, value),
+            FieldChange::Removed { key, value } => write!(f, "{}: removed \"{}\"", key, value),
+            FieldChange::Changed { key, old, new } => {
+                write!(f, "{}: \"{}\" -> \"{}\"", key, old, new)
+            }
+        }
+    }
+}
+
+impl VaultDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl Swd {
+    /// Compares the records of this vault, taken as the older one, with
+    /// those of `other`. Secrets are compared by plaintext when both
+    /// vaults are unlocked, so vaults under different master keys can be
+    /// compared. A secret that cannot be decrypted on either side is
+    /// compared by ciphertext instead.
+    pub fn diff(&self, other: &Swd) -> VaultDiff {
+        let old_side = Side::new(self);
+        let new_side = Side::new(other);
+        let old_records = records_by_path(self);
+        let mut new_records = records_by_path(other);
+
+        let mut diff = VaultDiff::default();
+        for (path, old_record) in old_records {
+            let Some(position) = new_records
+                .iter()
+                .position(|(new_path, _)| *new_path == path)
+            else {
+                diff.removed.push(path);
+                continue;
+            };
+            let (_, new_record) = new_records.remove(position);
+            let changes = diff_records(old_record, &old_side, new_record, &new_side);
+            if !changes.is_empty() {
+                diff.changed.push(RecordDiff { path, changes });
+            }
+        }
+        diff.added = new_records.into_iter().map(|(path, _)| path).collect();
+        diff
+    }
+}
+
+/// A vault along with its key, if unlocked
+struct Side<'a> {
+    swd: &'a Swd,
+    key: Option<&'a Vec<u8>>,
+}
+
+impl<'a> Side<'a> {
+    fn new(swd: &'a Swd) -> Self {
+        Self {
+            swd,
+            key: swd.header().get_key(),
+        }
+    }
+
+    /// Decrypts the secret `field` of `record` with the cipher of the
+    /// record, see `Swd::bind_record_cipher`
+    fn decrypt(
+        &self,
+        record: &Record,
+        field: &str,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<Vec<u8>>> {
+        let bound = self.swd.bind_record_cipher(record, key)?;
+        record.decrypt_field_bytes(field, bound.decrypt_fn, &bound.key)
+    }
+}
+
+fn records_by_path(swd: &Swd) -> Vec<(Vec<String>, &Record)> {
+    swd.get_root()
+        .iter_all_records()
+        .map(|(mut path, record)| {
+            path.remove(0);
+            path.push(record.label().clone());
+            (path, record)
+        })
+        .collect()
+}
+
+fn diff_records(old: &Record, old_side: &Side, new: &Record, new_side: &Side) -> Vec<FieldChange> {
+    let mut changes = vec![];
+
+    let secret_fields: BTreeSet<&str> = old
+        .secret_fields()
+        .into_iter()
+        .chain(new.secret_fields())
+        .collect();
+    for field in secret_fields {
+        if secret_changed(field, old, old_side, new, new_side) {
+            changes.push(FieldChange::Secret(field.to_owned()));
+        }
+    }
+
+    let keys: BTreeSet<&String> = old
+        .extras()
+        .iter()
+        .chain(new.extras())
+        .filter(|(key, value)| !value.is_secret() && !is_derived(key))
+        .map(|(key, _)| key)
+        .collect();
+    for key in keys {
+        let old_value = old.get_extra(key).filter(|value| !value.is_secret());
+        let new_value = new.get_extra(key).filter(|value| !value.is_secret());
+        let change = match (old_value, new_value) {
+            (Some(old_value), Some(new_value)) => {
+                if old_value.inner() == new_value.inner() && old_value.kind() == new_value.kind() {
+                    continue;
+                }
+                FieldChange::Changed {
+                    key: key.clone(),
+                    old: old_value.describe(),
+                    new: new_value.describe(),
+                }
+            }
+            (Some(value), None) => FieldChange::Removed {
+                key: key.clone(),
+                value: value.describe(),
+            },
+            (None, Some(value)) => FieldChange::Added {
+                key: key.clone(),
+                value: value.describe(),
+            },
+            (None, None) => continue,
+        };
+        changes.push(change);
+    }
+
+    changes
+}
+
+fn secret_changed(
+    field: &str,
+    old: &Record,
+    old_side: &Side,
+    new: &Record,
+    new_side: &Side,
+) -> bool {
+    let (Some(old_ciphertext), Some(new_ciphertext)) =
+        (ciphertext(old, field), ciphertext(new, field))
+    else {
+        return true;
+    };
+
+    if let (Some(old_key), Some(new_key)) = (old_side.key, new_side.key) {
+        let old_plaintext = old_side.decrypt(old, field, old_key);
+        let new_plaintext = new_side.decrypt(new, field, new_key);
+        if let (Ok(old_plaintext), Ok(new_plaintext)) = (old_plaintext, new_plaintext) {
+            return old_plaintext != new_plaintext;
+        }
+    }
+    old_ciphertext != new_ciphertext
+}
+
+fn ciphertext<'a>(record: &'a Record, field: &str) -> Option<&'a [u8]> {
+    if field == SECRET_FIELD {
+        return Some(&record.secret()[..]);
+    }
+    record
+        .get_extra(field)
+        .filter(|value| value.is_secret())
+        .map(Value::inner)
+}
+
+/// Whether `key` holds a nonce or the checksum, which change whenever
+/// a secret is re-encrypted and are compared along with the secrets
+fn is_derived(key: &str) -> bool {
+    key == "nonce" || key == CHECKSUM_FIELD || key.ends_with(FIELD_NONCE_SUFFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::{
+        collection::Collection,
+        tests::{add_dummy_record, dummy_swd, mixed_cipher_swd},
+        Swd,
+    };
+
+    use super::FieldChange;
+
+    fn dummy_pair() -> (Swd, Swd) {
+        let mut old = dummy_swd(b"master key");
+        old.unlock(b"master key");
+        add_dummy_record(&mut old, "kept", "same secret");
+        add_dummy_record(&mut old, "edited", "old secret");
+        add_dummy_record(&mut old, "removed", "secret");
+
+        let mut new = dummy_swd(b"other master key");
+        new.unlock(b"other master key");
+        add_dummy_record(&mut new, "kept", "same secret");
+        add_dummy_record(&mut new, "edited", "new secret");
+        add_dummy_record(&mut new, "added", "secret");
+        (old, new)
+    }
+
+    #[test]
+    fn diff_reports_added_removed_and_changed() {
+        let (old, mut new) = dummy_pair();
+        let record = new.get_root_mut().get_record_mut(1).unwrap();
+        record.set_notes("moved to a new provider");
+        record.add_extra("icon", b"mail", false).unwrap();
+        let mut work = Collection::new("Work".to_owned());
+        work.add_record(new.get_root_mut().take_record(2).unwrap());
+        new.get_root_mut().add_child(work);
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.removed, vec![vec!["removed".to_owned()]]);
+        assert_eq!(
+            diff.added,
+            vec![vec!["Work".to_owned(), "added".to_owned()]]
+        );
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].path, vec!["edited".to_owned()]);
+        assert_eq!(
+            diff.changed[0].changes,
+            vec![
+                FieldChange::Secret("secret".to_owned()),
+                FieldChange::Added {
+                    key: "icon".to_owned(),
+                    value: "mail".to_owned()
+                },
+                FieldChange::Added {
+                    key: "notes".to_owned(),
+                    value: "moved to a new provider".to_owned()
+                },
+            ]
+        );
+        assert_eq!(
+            diff.changed[0].changes[0].to_string(),
+            "secret: (secret changed)"
+        );
+    }
+
+    #[test]
+    fn diff_of_identical_vaults_is_empty() {
+        let (old, _) = dummy_pair();
+        assert!(old.diff(&old).is_empty());
+    }
+
+    #[test]
+    fn diff_decrypts_records_with_their_own_cipher() {
+        let old = mixed_cipher_swd();
+        let mut new = mixed_cipher_swd();
+        assert_eq!(
+            old.record_cipher(old.get_root().get_record(1).unwrap()),
+            "aes128-gcm"
+        );
+        assert!(old.diff(&new).is_empty());
+
+        let mut record = new.get_root_mut().take_record(1).unwrap();
+        let key = new.header().get_key().unwrap().clone();
+        let bound = new.bind_record_cipher(&record, &key).unwrap();
+        record
+            .replace_secret(
+                b"other secret",
+                bound.encrypt_fn,
+                &bound.key,
+                bound.nonce_size,
+            )
+            .unwrap();
+        new.get_root_mut().add_record(record);
+        let diff = old.diff(&new);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].path, vec!["upgraded".to_owned()]);
+    }
+
+    #[test]
+    fn diff_locked_vaults_compares_ciphertext() {
+        let (mut old, mut new) = dummy_pair();
+        old.lock();
+        new.lock();
+        let diff = old.diff(&new);
+        let changed: Vec<&Vec<String>> = diff.changed.iter().map(|change| &change.path).collect();
+        assert_eq!(
+            changed,
+            vec![&vec!["kept".to_owned()], &vec!["edited".to_owned()]]
+        );
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use zeroize::Zeroizing;
+
+use crate::{
+    cipher::{CipherResult, DecryptFn, EncryptFn},
+    error::{CipherError, ParseError},
+    io::parser::ParseResult,
+};
+
+use super::{record::Record, value::Value, Entries};
+
+pub const LABEL_FIELD: &str = "label";
+/// Nonce of an encrypted label, stored next to it
+pub const LABEL_NONCE_FIELD: &str = "label.nonce";
+/// Label shown in place of an encrypted one until the vault is unlocked
+pub const ENCRYPTED_LABEL_PLACEHOLDER: &str = "(encrypted)";
+
+/// Label of a record or collection encrypted under the key, kept as
+/// stored until the vault is unlocked
+///
+/// An encrypted label is stored as a secret `label` field along with
+/// the `label.nonce` field, instead of the plain `label` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SealedLabel {
+    ciphertext: Box<[u8]>,
+    nonce: Vec<u8>,
+}
+
+impl SealedLabel {
+    /// Encrypts `label` under a fresh nonce of `nonce_size` bytes
+    pub(crate) fn seal(
+        label: &str,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<Self> {
+        let (ciphertext, nonce) =
+            Record::encrypt_secret(label.as_bytes(), encrypt_fn, key, nonce_size, None)?;
+        Ok(Self { ciphertext, nonce })
+    }
+
+    pub(crate) fn open(&self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> CipherResult<String> {
+        let mut decrypt_extras = HashMap::new();
+        decrypt_extras.insert("nonce".to_owned(), &self.nonce[..]);
+        let label = Zeroizing::new(decrypt_fn(&self.ciphertext, key, decrypt_extras)?);
+        String::from_utf8(label.to_vec()).map_err(|_| CipherError::InvalidUtf8)
+    }
+
+    /// Takes the label out of parsed `entries`, either in plain or sealed
+    /// along with its nonce. A secret label without a nonce is rejected.
+    pub(crate) fn take_label(entries: &mut Entries) -> ParseResult<(String, Option<Self>)> {
+        let Some(label) = entries.remove(LABEL_FIELD) else {
+            return Err(ParseError::MissingRequiredField(LABEL_FIELD.to_owned()));
+        };
+        if !label.is_secret() {
+            return Ok((label.parse_string()?, None));
+        }
+        let Some(nonce) = entries.remove(LABEL_NONCE_FIELD) else {
+            return Err(ParseError::ForbiddenSecretField(LABEL_FIELD.to_owned()));
+        };
+        let sealed = Self {
+            ciphertext: label.take(),
+            nonce: nonce.inner().to_vec(),
+        };
+        Ok((ENCRYPTED_LABEL_PLACEHOLDER.to_owned(), Some(sealed)))
+    }
+
+    /// Serializes the label fields, see `SealedLabel`
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Value::str_to_bytes(LABEL_FIELD, false);
+        bytes.extend_from_slice(&Value::bytes(&self.ciphertext, true).to_bytes());
+        bytes.extend_from_slice(&Value::str_to_bytes(LABEL_NONCE_FIELD, false));
+        bytes.extend_from_slice(&Value::bytes(&self.nonce, false).to_bytes());
+        bytes
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    cipher::{CipherResult, DecryptFn, EncryptFn},
+    error::ImportError,
+};
+
+use super::{
+    collection::Collection,
+    record::{Record, BINDING_FIELD, CHECKSUM_FIELD, CIPHER_FIELD},
+    value::Value,
+    Entries,
+};
+
+/// Plaintext, format independent representation of a collection tree,
+/// used to move data in and out of a vault
+///
+/// Only non-secret extras that are valid UTF-8 are carried over,
+/// cipher specific extras such as the nonce are left out since
+/// secrets are stored decrypted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortableCollection {
+    pub label: String,
+    #[serde(default)]
+    pub extras: BTreeMap<String, String>,
+    #[serde(default)]
+    pub collections: Vec<PortableCollection>,
+    #[serde(default)]
+    pub records: Vec<PortableRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PortableRecord {
+    pub label: String,
+    pub secret: String,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub modified_at: u64,
+    #[serde(default)]
+    pub extras: BTreeMap<String, String>,
+}
+
+pub const NON_PORTABLE_EXTRAS: [&str; 4] = ["nonce", BINDING_FIELD, CHECKSUM_FIELD, CIPHER_FIELD];
+
+/// A single CSV row, where `path` is the `/` separated
+/// path of the collection holding the record
+#[derive(Debug, Deserialize)]
+struct CsvRecord {
+    path: String,
+    label: String,
+    secret: String,
+}
+
+impl PortableCollection {
+    pub fn from_collection(
+        collection: &Collection,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Self> {
+        Self::from_collection_with(collection, &|record| record.decrypt_secret(decrypt_fn, key))
+    }
+
+    /// Same as `from_collection`, decrypting each record
+    /// secret with `decrypt_secret`
+    pub(crate) fn from_collection_with(
+        collection: &Collection,
+        decrypt_secret: &dyn Fn(&Record) -> CipherResult<Zeroizing<String>>,
+    ) -> CipherResult<Self> {
+        let collections = collection
+            .children()
+            .iter()
+            .map(|child| Self::from_collection_with(child, decrypt_secret))
+            .collect::<CipherResult<_>>()?;
+        let records = collection
+            .records()
+            .iter()
+            .map(|record| {
+                let secret = decrypt_secret(record)?;
+                Ok(PortableRecord::with_secret(record, &secret))
+            })
+            .collect::<CipherResult<_>>()?;
+
+        Ok(Self {
+            label: collection.label().clone(),
+            extras: portable_extras(collection.extras()),
+            collections,
+            records,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("BUG: this should never panic")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, ImportError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Builds a tree from CSV with `path`, `label` and `secret` columns,
+    /// creating intermediate collections as needed. The root is left
+    /// unlabeled.
+    pub fn from_csv(csv: &str) -> Result<Self, ImportError> {
+        let mut root = Self::new(String::new());
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+
+        for row in reader.deserialize() {
+            let CsvRecord {
+                path,
+                label,
+                secret,
+            } = row?;
+            let collection = path
+                .split('/')
+                .filter(|segment| !segment.is_empty())
+                .fold(&mut root, |collection, segment| {
+                    collection.child_or_insert(segment)
+                });
+            collection.records.push(PortableRecord {
+                label,
+                secret,
+                created_at: 0,
+                modified_at: 0,
+                extras: BTreeMap::new(),
+            });
+        }
+
+        Ok(root)
+    }
+
+    /// Encrypts every record secret under a fresh nonce of `nonce_size` bytes
+    /// and adds the extras, collections and records of this tree to `target`
+    pub fn import_into(
+        self,
+        target: &mut Collection,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<()> {
+        for (extra_key, value) in self.extras.iter() {
+            // The label is not an extra, one named so is dropped
+            let _ = target.add_extra(extra_key, value.as_bytes(), false);
+        }
+
+        for portable_child in self.collections {
+            let mut child = Collection::new(portable_child.label.clone());
+            portable_child.import_into(&mut child, encrypt_fn, key, nonce_size)?;
+            target.add_child(child);
+        }
+
+        for portable_record in self.records {
+            target.add_record(portable_record.into_record(encrypt_fn, key, nonce_size)?);
+        }
+
+        Ok(())
+    }
+
+    fn new(label: String) -> Self {
+        Self {
+            label,
+            extras: BTreeMap::new(),
+            collections: vec![],
+            records: vec![],
+        }
+    }
+
+    fn child_or_insert(&mut self, label: &str) -> &mut Self {
+        let index = match self
+            .collections
+            .iter()
+            .position(|child| child.label == label)
+        {
+            Some(index) => index,
+            None => {
+                self.collections.push(Self::new(label.to_owned()));
+                self.collections.len() - 1
+            }
+        };
+        &mut self.collections[index]
+    }
+}
+
+impl PortableRecord {
+    pub fn from_record(
+        record: &Record,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Self> {
+        let secret = record.decrypt_secret(decrypt_fn, key)?;
+        Ok(Self::with_secret(record, &secret))
+    }
+
+    fn with_secret(record: &Record, secret: &str) -> Self {
+        Self {
+            label: record.label().clone(),
+            secret: secret.to_owned(),
+            created_at: record.created_at(),
+            modified_at: record.modified_at(),
+            extras: portable_extras(record.extras()),
+        }
+    }
+
+    pub fn into_record(
+        self,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<Record> {
+        let mut record = Record::encrypted(
+            self.label,
+            self.secret.as_bytes(),
+            encrypt_fn,
+            key,
+            nonce_size,
+        )?;
+        for (extra_key, value) in self.extras.iter() {
+            if !NON_PORTABLE_EXTRAS.contains(&extra_key.as_str()) {
+                // Extras such as tags and notes round-trip as they were exported
+                record.insert_extra(extra_key, Value::new(value.as_bytes(), false));
+            }
+        }
+        if self.created_at != 0 {
+            record.set_timestamps(self.created_at, self.modified_at);
+        }
+        Ok(record)
+    }
+}
+
+fn portable_extras(extras: &Entries) -> BTreeMap<String, String> {
+    extras
+        .iter()
+        .filter(|(key, value)| !value.is_secret() && !NON_PORTABLE_EXTRAS.contains(&key.as_str()))
+        .filter_map(|(key, value)| {
+            let value = std::str::from_utf8(value.inner()).ok()?;
+            Some((key.clone(), value.to_owned()))
+        })
+        .collect()
+}
@@ -1,12 +1,60 @@
 use std::collections::HashMap;
 
-use crate::{cipher::DecryptFn, error::ParseError};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use zeroize::Zeroizing;
 
-use super::{value::Value, Entries};
+use crate::{
+    cipher::{random_nonce, Cipher, CipherResult, DecryptFn, EncryptFn, AAD_EXTRA},
+    error::{CipherError, ParseError, ReservedKeyError},
+    reserved::is_reserved_record_key,
+    totp::{decode_base32, totp},
+    util::unix_timestamp,
+};
+
+use super::{
+    entries_to_bytes,
+    label::{SealedLabel, ENCRYPTED_LABEL_PLACEHOLDER, LABEL_FIELD},
+    value::Value,
+    Entries,
+};
 
 pub const RECORD_STARTER_BYTE: u8 = 0x02;
 pub const REQUIRED_RECORD_FIELDS: [&str; 1] = ["label"];
 pub const REQUIRED_RECORD_SECRET_FIELDS: [&str; 1] = ["secret"];
+pub const CREATED_AT_FIELD: &str = "ctime";
+pub const MODIFIED_AT_FIELD: &str = "mtime";
+pub const TOTP_FIELD: &str = "totp";
+pub const KIND_FIELD: &str = "kind";
+/// Kind of records whose secret is an arbitrary binary blob
+pub const BINARY_KIND: &str = "binary";
+pub const SECRET_FIELD: &str = "secret";
+pub const NOTES_FIELD: &str = "notes";
+pub const TAGS_FIELD: &str = "tags";
+pub const ARCHIVED_FIELD: &str = "archived";
+pub const FIELD_NONCE_SUFFIX: &str = ".nonce";
+pub const BINDING_FIELD: &str = "binding";
+pub const HISTORY_FIELD_PREFIX: &str = "hist";
+pub const FIELD_TIME_SUFFIX: &str = ".time";
+/// Number of previous secrets kept in the history
+pub const HISTORY_LIMIT: usize = 5;
+pub const CHECKSUM_FIELD: &str = "cksum";
+pub const CIPHER_FIELD: &str = "cipher";
+/// Length the checksum of the secret is truncated to
+pub const CHECKSUM_LENGTH: usize = 8;
+const LABEL_BINDING: &[u8] = b"label";
+const CHECKSUM_CONTEXT: &[u8] = b"swords record checksum";
+
+/// Name, ciphertext and nonce of a re-encrypted secret field
+pub(crate) type EncryptedField = (String, Box<[u8]>, Vec<u8>);
+
+/// Secret fields of a record encrypted again by `Record::reencrypt`,
+/// along with the checksum of its secret under the new key
+pub(crate) struct Reencrypted {
+    fields: Vec<EncryptedField>,
+    checksum: Vec<u8>,
+}
 
 /// Record structure
 ///
@@ -14,79 +62,754 @@ pub const REQUIRED_RECORD_SECRET_FIELDS: [&str; 1] = ["secret"];
 /// [KEY] [VALUE]
 /// ...
 /// [KEY] [VALUE]
-#[derive(Debug)]
+///
+/// Creation and modification times are stored as Unix seconds
+/// in the `ctime` and `mtime` fields, both 8 byte big endian
+///
+/// A `totp` field set to 1 marks the secret as a base32 TOTP seed
+///
+/// A `tags` field holds comma separated tags, see `parse_tags`
+///
+/// An `archived` field set to 1 marks a retired record, which is kept
+/// but hidden from listings by default
+///
+/// A `kind` field set to `binary` marks the secret as arbitrary bytes,
+/// such as a file, which are revealed with `reveal_bytes`
+///
+/// Besides the primary secret, a record may hold more secret fields
+/// as secret extras, each encrypted under its own nonce stored in
+/// the non-secret `<field>.nonce` extra
+///
+/// A `binding` field set to `label` marks the secret fields as encrypted
+/// with the label as associated data, so they fail to decrypt once the
+/// record is relabeled or their ciphertext is moved to a record with
+/// another label. Only the label is bound, not the path of collections
+/// leading to the record: records do not know where they live, and
+/// binding the path would make moving a record, or relabeling or merging
+/// a collection, re-encrypt everything below it. Ciphertext swapped
+/// between records sharing a label, or a record moved to another
+/// collection in the file, is caught by the integrity trailer instead,
+/// see `Swd::verify_integrity`.
+///
+/// Replaced secrets are kept, newest first, as the secret fields
+/// `hist0`, `hist1`, ... along with the time they were replaced
+/// in the `<field>.time` extra
+///
+/// A `cksum` field holds a truncated HMAC of the secret plaintext under
+/// the key, checked whenever the secret is decrypted so that a cipher
+/// returning the wrong plaintext is caught. It is keyed so that it
+/// cannot be used to guess the secret without the key.
+///
+/// A `cipher` field names the cipher the secret fields are encrypted
+/// with when it is not the key cipher of the vault, see `reencrypt_with`
+///
+/// In vaults with encrypted labels, the label is stored sealed as
+/// described in `SealedLabel`.
+#[derive(Debug, Clone)]
 pub struct Record {
     label: String,
+    /// Stored label while it is encrypted, `label` then being a placeholder
+    sealed_label: Option<SealedLabel>,
     secret: Box<[u8]>,
-    revealed_secret: Option<String>,
+    revealed_secret: Option<Zeroizing<String>>,
+    created_at: u64,
+    modified_at: u64,
     extras: Entries,
 }
 
 impl Record {
     pub fn new(label: String, secret: Box<[u8]>) -> Self {
+        let now = unix_timestamp();
         Self {
             label,
+            sealed_label: None,
             secret,
             extras: HashMap::new(),
             revealed_secret: None,
+            created_at: now,
+            modified_at: now,
+        }
+    }
+
+    /// Creates a record whose secret is `plaintext` encrypted under a fresh
+    /// nonce of `nonce_size` bytes, bound to `label`
+    pub fn encrypted(
+        label: String,
+        plaintext: &[u8],
+        encrypt_fn: &(impl Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>> + ?Sized),
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<Self> {
+        let (secret, nonce) = Self::encrypt_secret(
+            plaintext,
+            encrypt_fn,
+            key,
+            nonce_size,
+            Some(label.as_bytes()),
+        )?;
+        let mut record = Record::new(label, secret);
+        record.insert_extra("nonce", Value::new(&nonce, false));
+        record.insert_extra(BINDING_FIELD, Value::new(LABEL_BINDING, false));
+        record.insert_extra(CHECKSUM_FIELD, Value::new(&checksum(key, plaintext), false));
+        Ok(record)
+    }
+
+    /// Creates a copy labelled with a " (copy)" suffix. Every secret
+    /// field is re-encrypted under a fresh nonce and bound to the new
+    /// label, decrypting to the same plaintext under the same key.
+    pub fn duplicate(
+        &self,
+        encrypt_fn: &Box<EncryptFn>,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<Record> {
+        let mut record = Record::new(format!("{} (copy)", self.label), self.secret.clone());
+        for (key, value) in self.extras.iter() {
+            let value = Value::with_kind(value.inner(), value.kind(), value.is_secret());
+            record.insert_extra(key, value);
         }
+        let reencrypted =
+            self.reencrypt(record.label(), encrypt_fn, decrypt_fn, key, key, nonce_size)?;
+        record.set_reencrypted(reencrypted);
+        Ok(record)
     }
 
     pub fn label(&self) -> &String {
         &self.label
     }
 
+    /// Relabels the record. Secret fields bound to the label
+    /// no longer decrypt until they are encrypted again, so the
+    /// revealed secret is forgotten as well.
+    pub fn set_label(&mut self, label: &str) {
+        self.label = label.to_owned();
+        self.sealed_label = None;
+        self.revealed_secret = None;
+        self.touch();
+    }
+
+    /// Whether the label is encrypted and not decrypted yet,
+    /// `label` returning a placeholder until it is
+    pub fn is_label_sealed(&self) -> bool {
+        self.sealed_label.is_some()
+    }
+
+    /// Encrypts the label under a fresh nonce, replacing it with
+    /// a placeholder. Does nothing if it is already encrypted.
+    pub(crate) fn seal_label(
+        &mut self,
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<()> {
+        if self.sealed_label.is_none() {
+            self.sealed_label = Some(SealedLabel::seal(&self.label, encrypt_fn, key, nonce_size)?);
+            self.label = ENCRYPTED_LABEL_PLACEHOLDER.to_owned();
+        }
+        Ok(())
+    }
+
+    /// Decrypts the label if it is encrypted
+    pub(crate) fn unseal_label(
+        &mut self,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<()> {
+        if let Some(sealed_label) = &self.sealed_label {
+            self.label = sealed_label.open(decrypt_fn, key)?;
+            self.sealed_label = None;
+        }
+        Ok(())
+    }
+
     pub fn secret(&self) -> &Box<[u8]> {
         &self.secret
     }
 
+    /// Replaces the secret with the `secret` ciphertext encrypted under
+    /// `nonce`, moving the previous one into the history
+    pub fn set_secret(&mut self, secret: Box<[u8]>, nonce: &[u8]) {
+        self.rotate_history();
+        self.set_encrypted_secret(secret, nonce);
+        self.touch();
+    }
+
+    /// Encrypts `plaintext` under a fresh nonce of `nonce_size` bytes and
+    /// makes it the secret, moving the previous one into the history
+    pub fn replace_secret(
+        &mut self,
+        plaintext: &[u8],
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<()> {
+        let (secret, nonce) =
+            Self::encrypt_secret(plaintext, encrypt_fn, key, nonce_size, self.aad())?;
+        self.set_secret(secret, &nonce);
+        self.insert_extra(CHECKSUM_FIELD, Value::new(&checksum(key, plaintext), false));
+        Ok(())
+    }
+
+    /// Previous secrets along with the time they were replaced, newest
+    /// first, skipping those that cannot be decrypted
+    pub fn history(&self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> Vec<(u64, String)> {
+        (0..HISTORY_LIMIT)
+            .map(Self::history_field)
+            .take_while(|name| self.extras.contains_key(name))
+            .filter_map(|name| {
+                let secret = self.decrypt_field(&name, decrypt_fn, key).ok()?;
+                let replaced_at = self
+                    .extras
+                    .get(&format!("{}{}", name, FIELD_TIME_SUFFIX))
+                    .and_then(Value::as_timestamp)
+                    .unwrap_or(0);
+                Some((replaced_at, secret.as_str().to_owned()))
+            })
+            .collect()
+    }
+
+    /// Shifts the history down by one, dropping the oldest entry once
+    /// the limit is reached, and stores the current secret as `hist0`.
+    /// A secret without a nonce was never encrypted and is not kept.
+    fn rotate_history(&mut self) {
+        let Some(nonce) = self.extras.get("nonce").map(|nonce| nonce.inner().to_vec()) else {
+            return;
+        };
+
+        for index in (0..HISTORY_LIMIT).rev() {
+            for suffix in ["", FIELD_NONCE_SUFFIX, FIELD_TIME_SUFFIX] {
+                let name = format!("{}{}", Self::history_field(index), suffix);
+                let Some(value) = self.extras.remove(&name) else {
+                    continue;
+                };
+                if index + 1 < HISTORY_LIMIT {
+                    let next = format!("{}{}", Self::history_field(index + 1), suffix);
+                    self.extras.insert(next, value);
+                }
+            }
+        }
+
+        let secret = self.secret.clone();
+        self.set_encrypted_field(&Self::history_field(0), secret, &nonce);
+        self.insert_extra(
+            &format!("{}{}", Self::history_field(0), FIELD_TIME_SUFFIX),
+            Value::timestamp(unix_timestamp(), false),
+        );
+    }
+
+    fn history_field(index: usize) -> String {
+        format!("{}{}", HISTORY_FIELD_PREFIX, index)
+    }
+
+    pub fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    pub fn modified_at(&self) -> u64 {
+        self.modified_at
+    }
+
+    pub(crate) fn set_timestamps(&mut self, created_at: u64, modified_at: u64) {
+        self.created_at = created_at;
+        self.modified_at = modified_at;
+    }
+
     pub fn revealed_secret(&self) -> Option<&String> {
-        self.revealed_secret.as_ref()
+        self.revealed_secret.as_deref()
     }
 
     pub fn get_extra(&self, key: &str) -> Option<&Value> {
         self.extras.get(key)
     }
 
-    pub fn add_extra(&mut self, key: &str, value: &[u8], is_secret: bool) {
+    pub fn extras(&self) -> &Entries {
+        &self.extras
+    }
+
+    /// Inserts or overwrites the extra `key`, failing if it is
+    /// reserved, see `reserved::is_reserved_record_key`
+    pub fn add_extra(
+        &mut self,
+        key: &str,
+        value: &[u8],
+        is_secret: bool,
+    ) -> Result<(), ReservedKeyError> {
+        self.add_extra_value(key, Value::new(value, is_secret))
+    }
+
+    /// Same as `add_extra` for a value of any kind
+    pub fn add_extra_value(&mut self, key: &str, value: Value) -> Result<(), ReservedKeyError> {
+        if is_reserved_record_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            });
+        }
+        self.insert_extra(key, value);
+        Ok(())
+    }
+
+    /// Inserts or overwrites the extra `key`, reserved or not
+    pub(crate) fn insert_extra(&mut self, key: &str, value: Value) {
+        self.extras.insert(key.to_owned(), value);
+    }
+
+    /// Replaces `from` with `to` in the non-secret text extra `key`,
+    /// returning whether it changed. Fails if `key` is reserved, like
+    /// `add_extra`.
+    pub fn replace_extra(
+        &mut self,
+        key: &str,
+        from: &str,
+        to: &str,
+    ) -> Result<bool, ReservedKeyError> {
+        if is_reserved_record_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            });
+        }
+        let Some(value) = self
+            .extras
+            .get(key)
+            .and_then(|value| value.replace_text(from, to))
+        else {
+            return Ok(false);
+        };
+        self.insert_extra(key, value);
+        self.touch();
+        Ok(true)
+    }
+
+    pub fn is_totp(&self) -> bool {
+        self.extras.get(TOTP_FIELD).and_then(Value::as_u64) == Some(1)
+    }
+
+    pub fn set_totp(&mut self, is_totp: bool) {
+        if is_totp {
+            self.insert_extra(TOTP_FIELD, Value::u64(1, false));
+        } else {
+            self.extras.remove(TOTP_FIELD);
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.extras
+            .get(KIND_FIELD)
+            .and_then(Value::as_str)
+            .is_some_and(|kind| kind == BINARY_KIND)
+    }
+
+    pub fn set_binary(&mut self, is_binary: bool) {
+        if is_binary {
+            self.insert_extra(KIND_FIELD, Value::new(BINARY_KIND.as_bytes(), false));
+        } else {
+            self.extras.remove(KIND_FIELD);
+        }
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.extras.get(ARCHIVED_FIELD).and_then(Value::as_u64) == Some(1)
+    }
+
+    pub fn set_archived(&mut self, is_archived: bool) {
+        if is_archived {
+            self.insert_extra(ARCHIVED_FIELD, Value::u64(1, false));
+        } else {
+            self.extras.remove(ARCHIVED_FIELD);
+        }
+        self.touch();
+    }
+
+    /// Computes the TOTP code for the current time from the
+    /// decrypted seed, or `None` if the record is not a TOTP
+    /// record or its seed cannot be decrypted or decoded
+    pub fn current_totp(&self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> Option<String> {
+        if !self.is_totp() {
+            return None;
+        }
+        let seed = self.decrypt_secret(decrypt_fn, key).ok()?;
+        let seed = Zeroizing::new(decode_base32(&seed)?);
+        Some(totp(&seed, unix_timestamp()))
+    }
+
+    /// Encrypts `plaintext` under a fresh nonce of `nonce_size` bytes and
+    /// stores it as the secret field `name`, replacing any previous value
+    pub fn set_secret_field(
+        &mut self,
+        name: &str,
+        plaintext: &[u8],
+        encrypt_fn: &Box<EncryptFn>,
+        key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<()> {
+        let (secret, nonce) =
+            Self::encrypt_secret(plaintext, encrypt_fn, key, nonce_size, self.aad())?;
+        self.set_encrypted_field(name, secret, &nonce);
+        self.touch();
+        Ok(())
+    }
+
+    /// Whether the secret fields are bound to the label
+    pub fn is_bound(&self) -> bool {
+        self.extras
+            .get(BINDING_FIELD)
+            .is_some_and(|value| value.inner() == LABEL_BINDING)
+    }
+
+    /// Associated data the secret fields are encrypted with, if bound
+    fn aad(&self) -> Option<&[u8]> {
+        self.is_bound().then_some(self.label.as_bytes())
+    }
+
+    /// Decrypts every secret field with `old_key` and encrypts it again
+    /// with `new_key` under a fresh nonce, bound to `label`
+    pub(crate) fn reencrypt(
+        &self,
+        label: &str,
+        encrypt_fn: &Box<EncryptFn>,
+        decrypt_fn: &Box<DecryptFn>,
+        old_key: &[u8],
+        new_key: &[u8],
+        nonce_size: usize,
+    ) -> CipherResult<Reencrypted> {
+        let mut checksum_of_secret = vec![];
+        let fields = self
+            .secret_fields()
+            .into_iter()
+            .map(|name| {
+                let field = self.decrypt_field_bytes(name, decrypt_fn, old_key)?;
+                if name == SECRET_FIELD {
+                    checksum_of_secret = checksum(new_key, &field);
+                }
+                let (field, nonce) = Self::encrypt_secret(
+                    &field,
+                    encrypt_fn,
+                    new_key,
+                    nonce_size,
+                    Some(label.as_bytes()),
+                )?;
+                Ok((name.to_owned(), field, nonce))
+            })
+            .collect::<CipherResult<_>>()?;
+        Ok(Reencrypted {
+            fields,
+            checksum: checksum_of_secret,
+        })
+    }
+
+    /// Cipher the secret fields are encrypted with, if not the key cipher
+    pub fn cipher(&self) -> Option<&str> {
+        self.extras.get(CIPHER_FIELD).and_then(Value::as_str)
+    }
+
+    /// Decrypts every secret field with `old_decrypt` and `old_key`
+    /// and encrypts it again with `new_encrypt` and `new_key` under a
+    /// fresh nonce of `new_nonce_size` bytes, recording `new_cipher` as
+    /// the cipher of the record. Nothing is changed if any field fails
+    /// to decrypt.
+    pub fn reencrypt_with(
+        &mut self,
+        old_decrypt: &Box<DecryptFn>,
+        old_key: &[u8],
+        new_encrypt: &Box<EncryptFn>,
+        new_key: &[u8],
+        new_nonce_size: usize,
+        new_cipher: &str,
+    ) -> CipherResult<()> {
+        let reencrypted = self.reencrypt(
+            &self.label,
+            new_encrypt,
+            old_decrypt,
+            old_key,
+            new_key,
+            new_nonce_size,
+        )?;
+        self.set_reencrypted(reencrypted);
+        self.insert_extra(CIPHER_FIELD, Value::new(new_cipher.as_bytes(), false));
+        self.touch();
+        Ok(())
+    }
+
+    /// Stores the fields returned by `reencrypt`, marking
+    /// the record as bound to its label
+    pub(crate) fn set_reencrypted(&mut self, reencrypted: Reencrypted) {
+        for (name, field, nonce) in reencrypted.fields {
+            self.set_encrypted_field(&name, field, &nonce);
+        }
+        self.insert_extra(BINDING_FIELD, Value::new(LABEL_BINDING, false));
+        self.insert_extra(CHECKSUM_FIELD, Value::new(&reencrypted.checksum, false));
+    }
+
+    /// Names of the secret fields, the primary secret first
+    /// followed by the secret extras in order
+    pub fn secret_fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = self
+            .extras
+            .iter()
+            .filter(|(_, value)| value.is_secret())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        fields.sort();
+        fields.insert(0, SECRET_FIELD);
+        fields
+    }
+
+    /// Decrypts the secret field `name` without caching it in the record
+    pub fn decrypt_field(
+        &self,
+        name: &str,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<String>> {
+        let field_bytes = self.decrypt_field_bytes(name, decrypt_fn, key)?;
+        let field = std::str::from_utf8(&field_bytes).map_err(|_| CipherError::InvalidUtf8)?;
+        Ok(Zeroizing::new(field.to_owned()))
+    }
+
+    /// Decrypts every secret field that can be decrypted, keyed by
+    /// field name with the primary secret under `secret`, and caches
+    /// the primary secret as `reveal` does
+    pub fn reveal_all(
+        &mut self,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> HashMap<String, String> {
+        let _ = self.reveal(decrypt_fn, key);
+        self.secret_fields()
+            .into_iter()
+            .filter_map(|name| {
+                let field = self.decrypt_field(name, decrypt_fn, key).ok()?;
+                Some((name.to_owned(), field.as_str().to_owned()))
+            })
+            .collect()
+    }
+
+    /// Non-secret free form notes, stored in the `notes` extra
+    pub fn notes(&self) -> Option<&str> {
+        self.extras.get(NOTES_FIELD).and_then(Value::as_str)
+    }
+
+    /// Replaces the notes, removing them when `notes` is empty
+    pub fn set_notes(&mut self, notes: &str) {
+        if notes.is_empty() {
+            self.extras.remove(NOTES_FIELD);
+        } else {
+            self.insert_extra(NOTES_FIELD, Value::new(notes.as_bytes(), false));
+        }
+        self.touch();
+    }
+
+    pub fn tags(&self) -> Vec<&str> {
         self.extras
-            .insert(key.to_owned(), Value::new(value, is_secret));
+            .get(TAGS_FIELD)
+            .and_then(Value::as_str)
+            .map(parse_tags)
+            .unwrap_or_default()
     }
 
-    pub fn reveal(&mut self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> bool {
-        let decrypt_extras: HashMap<String, &[u8]> = self
+    /// Whether the record is tagged `tag`, ignoring case
+    pub fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.trim().to_lowercase();
+        self.tags().iter().any(|own| own.to_lowercase() == tag)
+    }
+
+    /// Replaces the tags, dropping empty and repeated ones,
+    /// and removes the field when none are left
+    pub fn set_tags(&mut self, tags: &[&str]) {
+        let mut kept: Vec<&str> = vec![];
+        for tag in tags.iter().flat_map(|tag| parse_tags(tag)) {
+            if !kept
+                .iter()
+                .any(|own| own.to_lowercase() == tag.to_lowercase())
+            {
+                kept.push(tag);
+            }
+        }
+        if kept.is_empty() {
+            self.extras.remove(TAGS_FIELD);
+        } else {
+            self.insert_extra(TAGS_FIELD, Value::new(kept.join(",").as_bytes(), false));
+        }
+        self.touch();
+    }
+
+    /// Forgets the revealed secret and extras, zeroing them
+    pub fn conceal(&mut self) {
+        self.revealed_secret = None;
+        for value in self.extras.values_mut() {
+            value.conceal();
+        }
+    }
+
+    /// Decrypts the secret and caches it in the record, failing
+    /// if it cannot be decrypted or is not valid UTF-8
+    pub fn reveal(&mut self, decrypt_fn: &Box<DecryptFn>, key: &[u8]) -> CipherResult<&str> {
+        let secret = self.decrypt_secret(decrypt_fn, key)?;
+        Ok(self.revealed_secret.insert(secret).as_str())
+    }
+
+    /// Decrypts the secret as raw bytes, for binary records whose secret
+    /// need not be UTF-8. Unlike `reveal`, the plaintext is not cached.
+    pub fn reveal_bytes(
+        &mut self,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Vec<u8>> {
+        let secret = self.decrypt_secret_bytes(decrypt_fn, key)?;
+        Ok(secret.to_vec())
+    }
+
+    /// Decrypts the secret without caching it in the record
+    pub fn decrypt_secret(
+        &self,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<String>> {
+        let secret_bytes = self.decrypt_secret_bytes(decrypt_fn, key)?;
+        let secret = std::str::from_utf8(&secret_bytes).map_err(|_| CipherError::InvalidUtf8)?;
+        Ok(Zeroizing::new(secret.to_owned()))
+    }
+
+    /// Decrypts the secret as raw bytes without caching it in the record
+    pub fn decrypt_secret_bytes(
+        &self,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<Vec<u8>>> {
+        let mut decrypt_extras: HashMap<String, &[u8]> = self
             .extras
             .iter()
             .map(|(key, value)| (key.clone(), value.inner()))
             .collect();
-        let result = decrypt_fn(&self.secret, key, decrypt_extras);
+        decrypt_extras.remove(AAD_EXTRA);
+        if let Some(aad) = self.aad() {
+            decrypt_extras.insert(AAD_EXTRA.to_owned(), aad);
+        }
+        let secret = Zeroizing::new(decrypt_fn(&self.secret, key, decrypt_extras)?);
+        self.verify_checksum(&secret, key)?;
+        Ok(secret)
+    }
 
-        if let Err(_) = result {
-            return false;
+    /// Checks `plaintext` against the `cksum` extra, which
+    /// records written before checksums were added lack
+    fn verify_checksum(&self, plaintext: &[u8], key: &[u8]) -> CipherResult<()> {
+        let Some(expected) = self.extras.get(CHECKSUM_FIELD) else {
+            return Ok(());
+        };
+        checksum_mac(key, plaintext)
+            .verify_truncated_left(expected.inner())
+            .map_err(|_| CipherError::ChecksumMismatch)
+    }
+
+    pub(crate) fn decrypt_field_bytes(
+        &self,
+        name: &str,
+        decrypt_fn: &Box<DecryptFn>,
+        key: &[u8],
+    ) -> CipherResult<Zeroizing<Vec<u8>>> {
+        if name == SECRET_FIELD {
+            return self.decrypt_secret_bytes(decrypt_fn, key);
         }
 
-        let secret_bytes = result.unwrap();
-        let secret = std::str::from_utf8(&secret_bytes).unwrap().to_owned();
-        self.revealed_secret = Some(secret);
-        true
+        let field = self
+            .extras
+            .get(name)
+            .filter(|value| value.is_secret())
+            .ok_or_else(|| CipherError::MissingRequiredExtra(name.to_owned()))?;
+        let nonce_name = format!("{}{}", name, FIELD_NONCE_SUFFIX);
+        let nonce = self
+            .extras
+            .get(&nonce_name)
+            .ok_or(CipherError::MissingRequiredExtra(nonce_name))?;
+        let mut decrypt_extras = HashMap::new();
+        decrypt_extras.insert("nonce".to_owned(), nonce.inner());
+        if let Some(aad) = self.aad() {
+            decrypt_extras.insert(AAD_EXTRA.to_owned(), aad);
+        }
+        Ok(Zeroizing::new(decrypt_fn(
+            field.inner(),
+            key,
+            decrypt_extras,
+        )?))
+    }
+
+    /// Encrypts `plaintext` under a fresh random nonce of `nonce_size`
+    /// bytes along with `aad` as associated data, returning the
+    /// ciphertext along with the nonce
+    pub(crate) fn encrypt_secret(
+        plaintext: &[u8],
+        encrypt_fn: &(impl Fn(&[u8], &[u8], HashMap<String, &[u8]>) -> CipherResult<Vec<u8>> + ?Sized),
+        key: &[u8],
+        nonce_size: usize,
+        aad: Option<&[u8]>,
+    ) -> CipherResult<(Box<[u8]>, Vec<u8>)> {
+        let nonce = random_nonce(nonce_size);
+        let mut encrypt_extras = HashMap::new();
+        encrypt_extras.insert("nonce".to_owned(), &nonce[..]);
+        if let Some(aad) = aad {
+            encrypt_extras.insert(AAD_EXTRA.to_owned(), aad);
+        }
+        let secret = encrypt_fn(plaintext, key, encrypt_extras)?;
+        Ok((secret.into_boxed_slice(), nonce))
+    }
+
+    /// Replaces the ciphertext and its nonce without touching the
+    /// modification time. The checksum of the previous secret is dropped,
+    /// it is up to the caller to store one for the new secret.
+    pub(crate) fn set_encrypted_secret(&mut self, secret: Box<[u8]>, nonce: &[u8]) {
+        self.secret = secret;
+        self.revealed_secret = None;
+        self.extras.remove(CHECKSUM_FIELD);
+        self.insert_extra("nonce", Value::bytes(nonce, false));
+    }
+
+    /// Replaces the ciphertext and nonce of the secret field `name`
+    /// without touching the modification time
+    pub(crate) fn set_encrypted_field(&mut self, name: &str, secret: Box<[u8]>, nonce: &[u8]) {
+        if name == SECRET_FIELD {
+            self.set_encrypted_secret(secret, nonce);
+            return;
+        }
+        self.insert_extra(name, Value::bytes(&secret, true));
+        self.insert_extra(
+            &format!("{}{}", name, FIELD_NONCE_SUFFIX),
+            Value::bytes(nonce, false),
+        );
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
         bytes.push(RECORD_STARTER_BYTE);
-        bytes.extend_from_slice(&Self::label_bytes());
-        bytes.extend_from_slice(&Value::str_to_bytes(&self.label, false));
+        match &self.sealed_label {
+            Some(sealed_label) => bytes.extend_from_slice(&sealed_label.to_bytes()),
+            None => {
+                bytes.extend_from_slice(&Self::label_bytes());
+                bytes.extend_from_slice(&Value::str_to_bytes(&self.label, false));
+            }
+        }
         bytes.extend_from_slice(&Self::secret_bytes());
-        bytes.extend_from_slice(&Value::new(&self.secret, true).to_bytes());
+        bytes.extend_from_slice(&Value::bytes(&self.secret, true).to_bytes());
+        bytes.extend_from_slice(&Value::str_to_bytes(CREATED_AT_FIELD, false));
+        bytes.extend_from_slice(&Value::timestamp(self.created_at, false).to_bytes());
+        bytes.extend_from_slice(&Value::str_to_bytes(MODIFIED_AT_FIELD, false));
+        bytes.extend_from_slice(&Value::timestamp(self.modified_at, false).to_bytes());
 
-        for (key, value) in self.extras.iter() {
-            bytes.extend_from_slice(&Value::str_to_bytes(key, false));
-            bytes.extend_from_slice(&value.to_bytes());
-        }
+        bytes.extend_from_slice(&entries_to_bytes(&self.extras));
 
         bytes
     }
 
+    fn touch(&mut self) {
+        self.modified_at = unix_timestamp();
+    }
+
+    fn parse_timestamp(value: Option<Value>) -> u64 {
+        value
+            .and_then(|value| value.inner().try_into().ok())
+            .map(u64::from_be_bytes)
+            .unwrap_or(0)
+    }
+
     fn label_bytes() -> Vec<u8> {
         Value::new(b"label", false).to_bytes()
     }
@@ -96,6 +819,125 @@ impl Record {
     }
 }
 
+/// Checksum of `plaintext` under `key`, see `Record`
+fn checksum(key: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut checksum = checksum_mac(key, plaintext)
+        .finalize()
+        .into_bytes()
+        .to_vec();
+    checksum.truncate(CHECKSUM_LENGTH);
+    checksum
+}
+
+fn checksum_mac(key: &[u8], plaintext: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(CHECKSUM_CONTEXT);
+    mac.update(plaintext);
+    mac
+}
+
+/// Splits comma separated tags, trimming them and dropping empty ones
+pub fn parse_tags(tags: &str) -> Vec<&str> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Builds an encrypted record in one go, generating the nonce and
+/// binding the secret to the label like `Record::encrypted`
+#[derive(Default)]
+pub struct RecordBuilder {
+    label: String,
+    plaintext: Zeroizing<Vec<u8>>,
+    is_totp: bool,
+    is_binary: bool,
+    notes: Option<String>,
+    extras: Vec<(String, Value)>,
+}
+
+impl RecordBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_owned();
+        self
+    }
+
+    pub fn secret_plaintext(mut self, plaintext: &[u8]) -> Self {
+        self.plaintext = Zeroizing::new(plaintext.to_vec());
+        self
+    }
+
+    pub fn totp(mut self, is_totp: bool) -> Self {
+        self.is_totp = is_totp;
+        self
+    }
+
+    pub fn binary(mut self, is_binary: bool) -> Self {
+        self.is_binary = is_binary;
+        self
+    }
+
+    pub fn notes(mut self, notes: &str) -> Self {
+        self.notes = Some(notes.to_owned());
+        self
+    }
+
+    /// Adds a non-secret extra, failing if `key` is reserved,
+    /// see `Record::add_extra_value`
+    pub fn extra(mut self, key: &str, value: Value) -> Result<Self, ReservedKeyError> {
+        if is_reserved_record_key(key) {
+            return Err(ReservedKeyError {
+                key: key.to_owned(),
+            });
+        }
+        self.extras.push((key.to_owned(), value));
+        Ok(self)
+    }
+
+    /// Encrypts the secret with `cipher` and `key` under a fresh nonce
+    /// and returns the record
+    pub fn encrypt_with(self, cipher: &dyn Cipher, key: &[u8]) -> CipherResult<Record> {
+        let encrypt_fn = |data: &[u8], key: &[u8], extras: HashMap<String, &[u8]>| {
+            cipher.encrypt(data, key, extras)
+        };
+        let mut record = Record::encrypted(
+            self.label,
+            &self.plaintext,
+            &encrypt_fn,
+            key,
+            cipher.nonce_size(),
+        )?;
+        record.set_totp(self.is_totp);
+        record.set_binary(self.is_binary);
+        if let Some(notes) = self.notes {
+            record.set_notes(&notes);
+        }
+        for (key, value) in self.extras {
+            record.insert_extra(&key, value);
+        }
+        Ok(record)
+    }
+}
+
+/// Records are equal when their stored fields are,
+/// regardless of whether either has been revealed
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        self.label == other.label
+            && self.sealed_label == other.sealed_label
+            && self.secret == other.secret
+            && self.created_at == other.created_at
+            && self.modified_at == other.modified_at
+            && self.extras == other.extras
+    }
+}
+
+impl Eq for Record {}
+
 impl TryFrom<Entries> for Record {
     type Error = ParseError;
     fn try_from(mut raw_record: Entries) -> Result<Self, Self::Error> {
@@ -104,7 +946,9 @@ impl TryFrom<Entries> for Record {
                 return Err(ParseError::MissingRequiredField(required_field.to_owned()));
             }
 
-            if raw_record.get(required_field).unwrap().is_secret() {
+            // The label is only secret when encrypted, which `take_label` checks
+            if required_field != LABEL_FIELD && raw_record.get(required_field).unwrap().is_secret()
+            {
                 return Err(ParseError::ForbiddenSecretField(required_field.to_owned()));
             }
         }
@@ -121,14 +965,19 @@ impl TryFrom<Entries> for Record {
             }
         }
 
-        let label = raw_record.remove("label").unwrap().parse_string()?;
+        let (label, sealed_label) = SealedLabel::take_label(&mut raw_record)?;
         let secret = raw_record.remove("secret").unwrap().take();
+        let created_at = Self::parse_timestamp(raw_record.remove(CREATED_AT_FIELD));
+        let modified_at = Self::parse_timestamp(raw_record.remove(MODIFIED_AT_FIELD));
 
         Ok(Self {
             label,
+            sealed_label,
             secret,
             extras: raw_record,
             revealed_secret: None,
+            created_at,
+            modified_at,
         })
     }
 }
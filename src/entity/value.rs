@@ -1,31 +1,224 @@
-use std::str::Utf8Error;
+use std::{collections::HashMap, str::Utf8Error};
 
-use crate::{error::ParseError, io::parser::ParseResult};
+use zeroize::Zeroizing;
 
-#[derive(Debug)]
+use crate::{
+    cipher::DecryptFn,
+    error::{ParseError, ValueError},
+    io::parser::ParseResult,
+    util::format_timestamp,
+};
+
+/// Value structure
+/// ```text
+/// [STARTER_BYTE] [KIND_BYTE] [LENGTH] [VALUE]
+/// ```
+///
+/// Length consist of 2 byte ordered in big endian ordering, or 4 bytes
+/// for values longer than `MAX_SHORT_VALUE_LENGTH`, which start with
+/// a large value starter byte instead.
+/// Files older than format version 3 have no kind byte,
+/// their values are read as text.
+#[derive(Debug, Clone)]
 pub struct Value {
     value: Box<[u8]>,
-    revealed_value: Option<String>,
+    revealed_value: Option<Zeroizing<String>>,
     is_secret: bool,
+    kind: ValueKind,
+}
+
+/// Values are equal when their bytes, kind and secrecy are,
+/// regardless of whether either has been revealed
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value && self.is_secret == other.is_secret && self.kind == other.kind
+    }
 }
 
+impl Eq for Value {}
+
 pub const VALUE_STARTER_BYTE: u8 = 0x00;
 pub const KEY_STARTER_BYTE: u8 = 0x00;
 pub const SECRET_VALUE_STARTER_BYTE: u8 = 0x01;
+pub const LARGE_VALUE_STARTER_BYTE: u8 = 0x05;
+pub const LARGE_SECRET_VALUE_STARTER_BYTE: u8 = 0x06;
 pub const VALUE_LENGTH_BYTES_LENGTH: usize = 2;
+pub const LARGE_VALUE_LENGTH_BYTES_LENGTH: usize = 4;
+/// Longest value the 2 byte length prefix can describe
+pub const MAX_SHORT_VALUE_LENGTH: usize = u16::MAX as usize;
+/// Longest value the 4 byte length prefix of large values can describe
+pub const MAX_VALUE_LENGTH: usize = u32::MAX as usize;
+/// First format version whose values carry a kind byte
+pub const VALUE_KIND_VERSION: u32 = 3;
+/// First format version with large values
+pub const LARGE_VALUE_VERSION: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// UTF-8 text
+    Text,
+    /// Opaque bytes
+    Bytes,
+    /// Unsigned 64 bit integer in big endian ordering
+    U64,
+    /// Seconds since the unix epoch in big endian ordering
+    Timestamp,
+}
+
+impl ValueKind {
+    pub fn to_byte(self) -> u8 {
+        match self {
+            ValueKind::Text => 0x00,
+            ValueKind::Bytes => 0x01,
+            ValueKind::U64 => 0x02,
+            ValueKind::Timestamp => 0x03,
+        }
+    }
+
+    /// Length every value of this kind must have, if fixed
+    pub fn fixed_length(self) -> Option<usize> {
+        match self {
+            ValueKind::U64 | ValueKind::Timestamp => Some(8),
+            ValueKind::Text | ValueKind::Bytes => None,
+        }
+    }
+}
+
+impl TryFrom<u8> for ValueKind {
+    type Error = ParseError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0x00 => Ok(ValueKind::Text),
+            0x01 => Ok(ValueKind::Bytes),
+            0x02 => Ok(ValueKind::U64),
+            0x03 => Ok(ValueKind::Timestamp),
+            _ => Err(ParseError::InvalidValueKind(byte)),
+        }
+    }
+}
 
 impl Value {
+    /// Text value, see `try_new` for values of unchecked length
     pub fn new(value: &[u8], is_secret: bool) -> Self {
+        Self::with_kind(value, ValueKind::Text, is_secret)
+    }
+
+    /// Same as `new`, failing when `value` is longer than
+    /// `MAX_VALUE_LENGTH` and could not be written
+    pub fn try_new(value: &[u8], is_secret: bool) -> Result<Self, ValueError> {
+        Self::try_with_kind(value, ValueKind::Text, is_secret)
+    }
+
+    pub fn try_with_kind(
+        value: &[u8],
+        kind: ValueKind,
+        is_secret: bool,
+    ) -> Result<Self, ValueError> {
+        if value.len() > MAX_VALUE_LENGTH {
+            return Err(ValueError::TooLong(value.len()));
+        }
+        Ok(Self::with_kind(value, kind, is_secret))
+    }
+
+    pub fn with_kind(value: &[u8], kind: ValueKind, is_secret: bool) -> Self {
         Self {
             value: value.into(),
             is_secret,
             revealed_value: None,
+            kind,
+        }
+    }
+
+    pub fn bytes(value: &[u8], is_secret: bool) -> Self {
+        Self::with_kind(value, ValueKind::Bytes, is_secret)
+    }
+
+    pub fn u64(value: u64, is_secret: bool) -> Self {
+        Self::with_kind(&value.to_be_bytes(), ValueKind::U64, is_secret)
+    }
+
+    pub fn timestamp(value: u64, is_secret: bool) -> Self {
+        Self::with_kind(&value.to_be_bytes(), ValueKind::Timestamp, is_secret)
+    }
+
+    pub fn kind(&self) -> ValueKind {
+        self.kind
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self.kind {
+            ValueKind::Text => std::str::from_utf8(&self.value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self.kind {
+            ValueKind::U64 => self.to_u64(),
+            _ => None,
+        }
+    }
+
+    pub fn as_timestamp(&self) -> Option<u64> {
+        match self.kind {
+            ValueKind::Timestamp => self.to_u64(),
+            _ => None,
+        }
+    }
+
+    /// Human readable form of the value for listings, which never
+    /// reveals a secret value
+    pub fn describe(&self) -> String {
+        if self.is_secret {
+            return "(secret)".to_owned();
+        }
+        if let Some(text) = self.as_str() {
+            return text.to_owned();
+        }
+        if let Some(number) = self.as_u64() {
+            return number.to_string();
+        }
+        if let Some(timestamp) = self.as_timestamp() {
+            return format_timestamp(timestamp);
         }
+        format!("{} bytes", self.value.len())
+    }
+
+    /// Caches the plaintext of this value for `revealed`, decrypting it
+    /// under `nonce` if it is secret. Non-secret values reveal their own
+    /// text, so that any extra can be revealed the same way. Returns false
+    /// if decryption fails or the plaintext is not UTF-8.
+    pub fn reveal(&mut self, decrypt_fn: &Box<DecryptFn>, key: &[u8], nonce: &[u8]) -> bool {
+        let plaintext = if self.is_secret {
+            let mut decrypt_extras = HashMap::new();
+            decrypt_extras.insert("nonce".to_owned(), nonce);
+            match decrypt_fn(&self.value, key, decrypt_extras) {
+                Ok(plaintext) => Zeroizing::new(plaintext),
+                Err(_) => return false,
+            }
+        } else {
+            Zeroizing::new(self.value.to_vec())
+        };
+
+        let Ok(revealed) = std::str::from_utf8(&plaintext) else {
+            return false;
+        };
+        self.revealed_value = Some(Zeroizing::new(revealed.to_owned()));
+        true
+    }
+
+    pub fn revealed(&self) -> Option<&String> {
+        self.revealed_value.as_deref()
+    }
+
+    /// Forgets the revealed plaintext, zeroing it
+    pub fn conceal(&mut self) {
+        self.revealed_value = None;
     }
 
     pub fn parse_string(self) -> ParseResult<String> {
-        self.try_into()
-            .map_err(|err| ParseError::EncodingError(err))
+        self.try_into().map_err(ParseError::EncodingError)
     }
 
     pub fn is_secret(&self) -> bool {
@@ -40,26 +233,54 @@ impl Value {
         &self.value
     }
 
+    /// Replaces every occurrence of `from` in a non-secret text value,
+    /// or `None` if the value is secret, not text or does not contain it
+    pub(crate) fn replace_text(&self, from: &str, to: &str) -> Option<Value> {
+        if self.is_secret || from.is_empty() {
+            return None;
+        }
+        let text = self.as_str()?;
+        if !text.contains(from) {
+            return None;
+        }
+        Some(Value::new(text.replace(from, to).as_bytes(), false))
+    }
+
     pub fn str_to_bytes(string: &str, is_secret: bool) -> Vec<u8> {
         Self::new(string.as_bytes(), is_secret).to_bytes()
     }
 
+    /// Serializes the value, whose length must not exceed
+    /// `MAX_VALUE_LENGTH`, which `try_new` guarantees. Values longer
+    /// than `MAX_SHORT_VALUE_LENGTH` are written as large values.
     pub fn to_bytes(&self) -> Vec<u8> {
         let length = self.value.len();
-        let size = length + VALUE_LENGTH_BYTES_LENGTH;
+        debug_assert!(length <= MAX_VALUE_LENGTH, "value too long to write");
+        let is_large = length > MAX_SHORT_VALUE_LENGTH;
+        let size = length + LARGE_VALUE_LENGTH_BYTES_LENGTH + 2;
         let mut bytes: Vec<u8> = Vec::with_capacity(size);
-        let length_bytes = &(length as u16).to_be_bytes();
-        bytes.push(self.get_starter_byte());
-        bytes.extend_from_slice(length_bytes);
+        bytes.push(self.get_starter_byte(is_large));
+        bytes.push(self.kind.to_byte());
+        if is_large {
+            bytes.extend_from_slice(&(length as u32).to_be_bytes());
+        } else {
+            bytes.extend_from_slice(&(length as u16).to_be_bytes());
+        }
         bytes.extend_from_slice(&self.value);
         bytes
     }
 
-    fn get_starter_byte(&self) -> u8 {
-        if self.is_secret {
-            SECRET_VALUE_STARTER_BYTE
-        } else {
-            VALUE_STARTER_BYTE
+    fn to_u64(&self) -> Option<u64> {
+        let bytes: [u8; 8] = self.value.as_ref().try_into().ok()?;
+        Some(u64::from_be_bytes(bytes))
+    }
+
+    fn get_starter_byte(&self, is_large: bool) -> u8 {
+        match (self.is_secret, is_large) {
+            (false, false) => VALUE_STARTER_BYTE,
+            (true, false) => SECRET_VALUE_STARTER_BYTE,
+            (false, true) => LARGE_VALUE_STARTER_BYTE,
+            (true, true) => LARGE_SECRET_VALUE_STARTER_BYTE,
         }
     }
 }
@@ -71,3 +292,113 @@ impl TryFrom<Value> for String {
         Ok(std::str::from_utf8(&value.value)?.to_owned())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{cipher::CipherRegistry, error::ParseError};
+
+    use super::{
+        Value, ValueKind, LARGE_SECRET_VALUE_STARTER_BYTE, MAX_SHORT_VALUE_LENGTH,
+        VALUE_STARTER_BYTE,
+    };
+
+    #[test]
+    fn reveal_secret_value() {
+        let registry = CipherRegistry::default();
+        let key = [7u8; 32];
+        let nonce = [3u8; 12];
+        let mut extras = HashMap::new();
+        extras.insert("nonce".to_owned(), &nonce[..]);
+        let encrypted = registry.get_encryptor("aes256-gcm")(b"alice", &key, extras).unwrap();
+        let decrypt = registry.get_decryptor("aes256-gcm");
+
+        let mut value = Value::bytes(&encrypted, true);
+        assert!(value.revealed().is_none());
+        assert!(!value.reveal(decrypt, &[8u8; 32], &nonce));
+        assert!(value.revealed().is_none());
+        assert!(value.reveal(decrypt, &key, &nonce));
+        assert_eq!(value.revealed().unwrap(), "alice");
+        value.conceal();
+        assert!(value.revealed().is_none());
+    }
+
+    #[test]
+    fn reveal_non_secret_value() {
+        let registry = CipherRegistry::default();
+        let decrypt = registry.get_decryptor("aes256-gcm");
+        let mut value = Value::new(b"mail", false);
+        assert!(value.reveal(decrypt, &[], &[]));
+        assert_eq!(value.revealed().unwrap(), "mail");
+
+        let mut value = Value::bytes(&[0xff, 0xfe], false);
+        assert!(!value.reveal(decrypt, &[], &[]));
+        assert!(value.revealed().is_none());
+    }
+
+    #[test]
+    fn to_bytes_emits_kind() {
+        let bytes = Value::u64(1, false).to_bytes();
+        assert_eq!(
+            bytes,
+            vec![VALUE_STARTER_BYTE, 0x02, 0, 8, 0, 0, 0, 0, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn long_values_are_written_large() {
+        let value = vec![b'a'; 70000];
+        let bytes = Value::try_new(&value[..MAX_SHORT_VALUE_LENGTH], false)
+            .unwrap()
+            .to_bytes();
+        assert_eq!(&bytes[..4], &[VALUE_STARTER_BYTE, 0x00, 0xff, 0xff]);
+        assert_eq!(bytes.len(), MAX_SHORT_VALUE_LENGTH + 4);
+
+        let bytes = Value::try_with_kind(&value, ValueKind::Bytes, true)
+            .unwrap()
+            .to_bytes();
+        assert_eq!(
+            &bytes[..6],
+            &[
+                LARGE_SECRET_VALUE_STARTER_BYTE,
+                0x01,
+                0x00,
+                0x01,
+                0x11,
+                0x70
+            ]
+        );
+        assert_eq!(bytes.len(), 70000 + 6);
+    }
+
+    #[test]
+    fn typed_accessors() {
+        assert_eq!(Value::u64(42, false).as_u64(), Some(42));
+        assert_eq!(Value::u64(42, false).as_timestamp(), None);
+        assert_eq!(Value::timestamp(42, false).as_timestamp(), Some(42));
+        assert_eq!(Value::timestamp(42, false).as_u64(), None);
+        assert_eq!(Value::new(b"abc", false).as_str(), Some("abc"));
+        assert_eq!(Value::bytes(b"abc", false).as_str(), None);
+        assert_eq!(
+            Value::with_kind(b"abc", ValueKind::U64, false).as_u64(),
+            None
+        );
+    }
+
+    #[test]
+    fn kind_from_byte() {
+        for kind in [
+            ValueKind::Text,
+            ValueKind::Bytes,
+            ValueKind::U64,
+            ValueKind::Timestamp,
+        ] {
+            assert_eq!(ValueKind::try_from(kind.to_byte()), Ok(kind));
+        }
+        assert_eq!(
+            ValueKind::try_from(0x04),
+            Err(ParseError::InvalidValueKind(0x04))
+        );
+    }
+}
@@ -1,20 +1,385 @@
-use std::str::Utf8Error;
+use std::{error::Error, fmt::Display, io, str::Utf8Error};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParseError {
     InvalidMagicNumber,
     InvalidVersionNumber,
-    UnexpectedStarterByte,
+    UnsupportedVersion(u32),
+    UnexpectedStarterByte { offset: usize, found: u8 },
     UnexpectedEndOfFile,
     MissingRequiredField(String),
     ForbiddenSecretField(String),
     ForbiddenNonSecretField(String),
     UnexpectedEndOfValue(usize, usize),
+    CollectionLengthMismatch(usize, usize),
+    InvalidValueKind(u8),
+    InvalidValueLength(usize, usize),
+    IntegrityCheckFailed,
+    IntegrityUnverified,
+    MaxDepthExceeded,
+    ResourceLimitExceeded,
     EncodingError(Utf8Error),
 }
 
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::InvalidMagicNumber => write!(f, "invalid magic number"),
+            ParseError::InvalidVersionNumber => write!(f, "invalid version number"),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            ParseError::UnexpectedStarterByte { offset, found } => write!(
+                f,
+                "unexpected starter byte 0x{:02x} at offset {}",
+                found, offset
+            ),
+            ParseError::UnexpectedEndOfFile => write!(f, "unexpected end of file"),
+            ParseError::MissingRequiredField(field) => {
+                write!(f, "missing required field \"{}\"", field)
+            }
+            ParseError::ForbiddenSecretField(field) => {
+                write!(f, "field \"{}\" must not be secret", field)
+            }
+            ParseError::ForbiddenNonSecretField(field) => {
+                write!(f, "field \"{}\" must be secret", field)
+            }
+            ParseError::UnexpectedEndOfValue(remaining, needed) => write!(
+                f,
+                "unexpected end of value: have {} bytes, need {}",
+                remaining, needed
+            ),
+            ParseError::CollectionLengthMismatch(expected, actual) => write!(
+                f,
+                "collection length mismatch: expected {} bytes, found {}",
+                expected, actual
+            ),
+            ParseError::InvalidValueKind(kind) => write!(f, "invalid value kind 0x{:02x}", kind),
+            ParseError::InvalidValueLength(expected, actual) => write!(
+                f,
+                "invalid value length: expected {} bytes, found {}",
+                expected, actual
+            ),
+            ParseError::IntegrityCheckFailed => write!(f, "integrity check failed"),
+            ParseError::IntegrityUnverified => {
+                write!(f, "file has no integrity trailer and cannot be verified")
+            }
+            ParseError::MaxDepthExceeded => write!(f, "collections are nested too deeply"),
+            ParseError::ResourceLimitExceeded => write!(f, "vault exceeds the parser limits"),
+            ParseError::EncodingError(_) => write!(f, "value is not valid UTF-8"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::EncodingError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum CipherError {
     MissingRequiredExtra(String),
+    InvalidNonceLength(usize),
+    InvalidKeyLength(usize),
     EncryptionError,
+    InvalidUtf8,
+    ChecksumMismatch,
+    UnknownCipher(String),
+}
+
+impl Display for CipherError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CipherError::MissingRequiredExtra(extra) => {
+                write!(f, "missing required extra \"{}\"", extra)
+            }
+            CipherError::InvalidNonceLength(length) => {
+                write!(f, "invalid nonce length of {} bytes", length)
+            }
+            CipherError::InvalidKeyLength(length) => {
+                write!(f, "invalid key length of {} bytes", length)
+            }
+            CipherError::EncryptionError => write!(f, "encryption or decryption failed"),
+            CipherError::InvalidUtf8 => write!(f, "decrypted secret is not valid UTF-8"),
+            CipherError::ChecksumMismatch => {
+                write!(f, "decrypted secret does not match its checksum")
+            }
+            CipherError::UnknownCipher(name) => write!(f, "unknown cipher \"{}\"", name),
+        }
+    }
+}
+
+impl Error for CipherError {}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ValueError {
+    /// Length of a value that does not fit its length prefix
+    TooLong(usize),
+}
+
+impl Display for ValueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueError::TooLong(length) => write!(
+                f,
+                "value of {} bytes exceeds the maximum of {} bytes",
+                length,
+                crate::entity::value::MAX_VALUE_LENGTH
+            ),
+        }
+    }
+}
+
+impl Error for ValueError {}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    Cipher(CipherError),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Json(err) => write!(f, "invalid JSON: {}", err),
+            ImportError::Csv(err) => write!(f, "invalid CSV: {}", err),
+            ImportError::Cipher(err) => write!(f, "failed to encrypt secret: {}", err),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImportError::Json(err) => Some(err),
+            ImportError::Csv(err) => Some(err),
+            ImportError::Cipher(err) => Some(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::Json(err)
+    }
+}
+
+impl From<csv::Error> for ImportError {
+    fn from(err: csv::Error) -> Self {
+        ImportError::Csv(err)
+    }
+}
+
+impl From<CipherError> for ImportError {
+    fn from(err: CipherError) -> Self {
+        ImportError::Cipher(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "failed to read file: {}", err),
+            LoadError::Parse(err) => write!(f, "failed to parse file: {}", err),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Parse(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<ParseError> for LoadError {
+    fn from(err: ParseError) -> Self {
+        LoadError::Parse(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    ReadOnly,
+    Locked,
+    Io(io::Error),
+    ReservedKey(ReservedKeyError),
+}
+
+impl Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WriteError::ReadOnly => write!(f, "vault is opened read-only"),
+            WriteError::Locked => write!(f, "vault is locked"),
+            WriteError::Io(err) => write!(f, "failed to write file: {}", err),
+            WriteError::ReservedKey(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for WriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WriteError::ReadOnly | WriteError::Locked => None,
+            WriteError::Io(err) => Some(err),
+            WriteError::ReservedKey(err) => Some(err),
+        }
+    }
 }
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> Self {
+        WriteError::Io(err)
+    }
+}
+
+impl From<ReservedKeyError> for WriteError {
+    fn from(err: ReservedKeyError) -> Self {
+        WriteError::ReservedKey(err)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemoveError {
+    ReadOnly,
+    Root,
+    /// Path, below the root, that leads to nothing
+    NotFound(String),
+}
+
+impl Display for RemoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemoveError::ReadOnly => write!(f, "vault is opened read-only"),
+            RemoveError::Root => write!(f, "the root collection cannot be removed"),
+            RemoveError::NotFound(path) => write!(f, "no record or collection at {}", path),
+        }
+    }
+}
+
+impl Error for RemoveError {}
+
+/// Extra key swords manages itself, see `reserved`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ReservedKeyError {
+    pub key: String,
+}
+
+impl Display for ReservedKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\" is a reserved key", self.key)
+    }
+}
+
+impl Error for ReservedKeyError {}
+
+/// Index past the end of the entries it was used on
+#[derive(Debug, PartialEq, Eq)]
+pub struct IndexError {
+    pub index: usize,
+    pub len: usize,
+}
+
+impl Display for IndexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "index {} is out of bounds for {} entries",
+            self.index, self.len
+        )
+    }
+}
+
+impl Error for IndexError {}
+
+/// Name that is not registered, along with the registered names
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownNameError {
+    pub name: String,
+    pub valid_names: Vec<String>,
+}
+
+impl Display for UnknownNameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown name \"{}\", expected one of: {}",
+            self.name,
+            self.valid_names.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownNameError {}
+
+/// Record secret field that failed to decrypt during verification
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    pub path: Vec<String>,
+    pub label: String,
+    pub field: String,
+    pub error: CipherError,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} ({}): {}",
+            self.path.join("/"),
+            self.label,
+            self.field,
+            self.error
+        )
+    }
+}
+
+impl Error for VerifyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PolicyViolation {
+    TooShort(usize),
+    MissingMixedCase,
+    MissingDigit,
+    MissingSymbol,
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyViolation::TooShort(min_len) => {
+                write!(f, "must consist of at least {} characters", min_len)
+            }
+            PolicyViolation::MissingMixedCase => {
+                write!(f, "must contain both lowercase and uppercase letters")
+            }
+            PolicyViolation::MissingDigit => write!(f, "must contain a digit"),
+            PolicyViolation::MissingSymbol => write!(f, "must contain a symbol"),
+        }
+    }
+}
+
+impl Error for PolicyViolation {}
@@ -1,17 +1,29 @@
 use aes_gcm::aead::generic_array::GenericArray;
+use sha2::{Sha256, Sha512};
 use sha3::{digest::OutputSizeUser, Digest, Sha3_256};
 use std::collections::HashMap;
+use zeroize::Zeroizing;
+
+use crate::error::UnknownNameError;
 
 pub type HashFunction = dyn Fn(&[u8]) -> Vec<u8>;
+/// Salted and iterated hash function taking the data,
+/// the salt and the iteration count
+pub type KeyDerivationFunction = dyn Fn(&[u8], &[u8], u32) -> Vec<u8>;
+
+pub const PBKDF2_DEFAULT_ITERATIONS: u32 = 600_000;
+const PBKDF2_OUTPUT_LENGTH: usize = 32;
 
 pub struct HashFunctionRegistry {
     functions: HashMap<String, Box<HashFunction>>,
+    key_derivation_functions: HashMap<String, Box<KeyDerivationFunction>>,
 }
 
 impl HashFunctionRegistry {
     pub fn new() -> Self {
         Self {
             functions: HashMap::new(),
+            key_derivation_functions: HashMap::new(),
         }
     }
 
@@ -19,12 +31,54 @@ impl HashFunctionRegistry {
         self.functions.insert(name.to_owned(), Box::new(hash_fn));
     }
 
+    pub fn register_key_derivation(&mut self, name: &str, kdf: Box<KeyDerivationFunction>) {
+        self.key_derivation_functions
+            .insert(name.to_owned(), Box::new(kdf));
+    }
+
     pub fn get_function(&self, name: &str) -> &Box<HashFunction> {
         self.functions.get(name).unwrap()
     }
 
+    pub fn get_key_derivation(&self, name: &str) -> Option<&Box<KeyDerivationFunction>> {
+        self.key_derivation_functions.get(name)
+    }
+
+    pub fn is_key_derivation(&self, name: &str) -> bool {
+        self.key_derivation_functions.contains_key(name)
+    }
+
+    /// Hashes `data` with `salt` using the function registered as `name`.
+    /// Plain hash functions hash the salt appended to the data and
+    /// ignore `iterations`, key derivation functions get them separately.
+    pub fn hash_salted(&self, name: &str, data: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        if let Some(kdf) = self.get_key_derivation(name) {
+            return kdf(data, salt, iterations);
+        }
+        let hash = self.get_function(name);
+        let mut salted = Zeroizing::new(data.to_vec());
+        salted.extend_from_slice(salt);
+        hash(&salted)
+    }
+
     pub fn get_names(&self) -> Vec<&String> {
-        self.functions.keys().collect()
+        self.functions
+            .keys()
+            .chain(self.key_derivation_functions.keys())
+            .collect()
+    }
+
+    /// Checks that a hash or key derivation function is registered as `name`
+    pub fn validate_name(&self, name: &str) -> Result<(), UnknownNameError> {
+        if self.functions.contains_key(name) || self.is_key_derivation(name) {
+            return Ok(());
+        }
+        let mut valid_names: Vec<String> = self.get_names().into_iter().cloned().collect();
+        valid_names.sort();
+        Err(UnknownNameError {
+            name: name.to_owned(),
+            valid_names,
+        })
     }
 }
 
@@ -32,10 +86,32 @@ impl Default for HashFunctionRegistry {
     fn default() -> Self {
         let mut registry = HashFunctionRegistry::new();
         registry.register("sha3-256", Box::new(sha3_256));
+        registry.register("sha2-256", Box::new(sha2_256));
+        registry.register("sha2-512", Box::new(sha2_512));
+        registry.register("blake3", Box::new(blake3_256));
+        registry.register_key_derivation("pbkdf2-sha256", Box::new(pbkdf2_sha256));
         registry
     }
 }
 
+fn blake3_256(data: &[u8]) -> Vec<u8> {
+    blake3::hash(data).as_bytes().to_vec()
+}
+
+fn pbkdf2_sha256(data: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut output = vec![0; PBKDF2_OUTPUT_LENGTH];
+    pbkdf2::pbkdf2_hmac::<Sha256>(data, salt, iterations, &mut output);
+    output
+}
+
+fn sha2_256(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+fn sha2_512(data: &[u8]) -> Vec<u8> {
+    Sha512::digest(data).to_vec()
+}
+
 fn sha3_256(data: &[u8]) -> Vec<u8> {
     let mut hasher = Sha3_256::new();
     hasher.update(data);
@@ -45,7 +121,11 @@ fn sha3_256(data: &[u8]) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use super::{sha3_256, HashFunctionRegistry};
+    use super::{blake3_256, pbkdf2_sha256, sha2_256, sha2_512, sha3_256, HashFunctionRegistry};
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
 
     #[test]
     fn sha3_256_hash() {
@@ -63,4 +143,98 @@ mod tests {
 
         assert_eq!(direct_result, registry_result);
     }
+
+    #[test]
+    fn sha2_256_vectors() {
+        assert_eq!(
+            hex(&sha2_256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(&sha2_256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let registry = HashFunctionRegistry::default();
+        let hash = registry.get_function("sha2-256");
+        assert_eq!(hash(b"abc"), sha2_256(b"abc"));
+    }
+
+    #[test]
+    fn sha2_512_vectors() {
+        assert_eq!(
+            hex(&sha2_512(b"abc")),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        let registry = HashFunctionRegistry::default();
+        let hash = registry.get_function("sha2-512");
+        assert_eq!(hash(b"abc"), sha2_512(b"abc"));
+    }
+
+    #[test]
+    fn blake3_vectors() {
+        assert_eq!(
+            hex(&blake3_256(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            hex(&blake3_256(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        let registry = HashFunctionRegistry::default();
+        let hash = registry.get_function("blake3");
+        assert_eq!(hash(b"abc"), blake3_256(b"abc"));
+    }
+
+    #[test]
+    fn pbkdf2_sha256_rfc_vectors() {
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+        assert_eq!(
+            hex(&pbkdf2_sha256(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+
+    #[test]
+    fn registry_hash_salted() {
+        let registry = HashFunctionRegistry::default();
+        let salted = registry.hash_salted("sha3-256", b"data", b"salt", 1);
+        assert_eq!(salted, sha3_256(b"datasalt"));
+        let derived = registry.hash_salted("pbkdf2-sha256", b"password", b"salt", 2);
+        assert_eq!(derived, pbkdf2_sha256(b"password", b"salt", 2));
+        assert!(registry.is_key_derivation("pbkdf2-sha256"));
+        assert!(!registry.is_key_derivation("sha3-256"));
+        assert_eq!(registry.get_names().len(), 5);
+    }
+
+    #[test]
+    fn registry_validate_name() {
+        let registry = HashFunctionRegistry::default();
+        assert!(registry.validate_name("sha3-256").is_ok());
+        assert!(registry.validate_name("pbkdf2-sha256").is_ok());
+
+        let err = registry.validate_name("md5").unwrap_err();
+        assert_eq!(err.name, "md5");
+        assert_eq!(
+            err.valid_names,
+            vec![
+                "blake3",
+                "pbkdf2-sha256",
+                "sha2-256",
+                "sha2-512",
+                "sha3-256"
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "unknown name \"md5\", expected one of: blake3, pbkdf2-sha256, sha2-256, sha2-512, sha3-256"
+        );
+    }
 }
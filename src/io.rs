@@ -3,6 +3,10 @@ use std::{
     io::{self, Read},
 };
 
+use crate::{entity::Swd, error::LoadError};
+
+use self::parser::{check_magic_number, Parser};
+
 pub mod parser;
 
 pub type IOResult<T> = io::Result<T>;
@@ -13,3 +17,99 @@ pub fn read_file(file_path: &str) -> IOResult<Vec<u8>> {
     file.read_to_end(&mut buffer)?;
     Ok(buffer)
 }
+
+/// Reads and parses the vault at `file_path`, returning it
+/// with the default cipher and hash function registries attached
+pub fn load(file_path: &str) -> Result<Swd, LoadError> {
+    let bytes = read_file(file_path)?;
+    let swd = Parser::new().parse(&bytes)?;
+    Ok(swd)
+}
+
+/// Reads `reader` to its end and parses the vault it holds, like `load`.
+/// Used for vaults piped through stdin.
+pub fn load_from(mut reader: impl Read) -> Result<Swd, LoadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let swd = Parser::new().parse(&bytes)?;
+    Ok(swd)
+}
+
+/// Whether `bytes` look like a vault, judging by the magic number
+/// alone. The rest of the input is not parsed.
+pub fn is_swd_file(bytes: &[u8]) -> bool {
+    check_magic_number(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, io::ErrorKind, process};
+
+    use crate::{
+        entity::tests::dummy_swd,
+        error::{LoadError, ParseError},
+        util::MAGIC_NUMBER,
+    };
+
+    use super::{is_swd_file, load, load_from};
+
+    #[test]
+    fn load_roundtrip() {
+        let path = env::temp_dir().join(format!("swords-load-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, dummy_swd(b"master key").to_bytes()).unwrap();
+        let result = load(path);
+        fs::remove_file(path).unwrap();
+        let mut swd = result.unwrap();
+        assert_eq!(swd.get_root().label(), "root");
+        assert!(swd.unlock(b"master key"));
+    }
+
+    #[test]
+    fn load_from_reader_roundtrip() {
+        let bytes = dummy_swd(b"master key").to_bytes();
+        let mut swd = load_from(&bytes[..]).unwrap();
+        assert_eq!(swd.get_root().label(), "root");
+        assert!(swd.unlock(b"master key"));
+
+        let result = load_from(&bytes[..bytes.len() / 2]);
+        assert!(matches!(result, Err(LoadError::Parse(_))));
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let result = load("this/file/does/not/exist.swd");
+        assert!(matches!(result, Err(LoadError::Io(err)) if err.kind() == ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn load_invalid_magic_number() {
+        let path = env::temp_dir().join(format!("swords-load-invalid-{}.swd", process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, [0; 16]).unwrap();
+        let result = load(path);
+        fs::remove_file(path).unwrap();
+        assert!(matches!(
+            result,
+            Err(LoadError::Parse(ParseError::InvalidMagicNumber))
+        ));
+    }
+
+    #[test]
+    fn is_swd_file_checks_magic_number() {
+        assert!(is_swd_file(&MAGIC_NUMBER));
+        assert!(is_swd_file(&dummy_swd(b"master key").to_bytes()));
+    }
+
+    #[test]
+    fn is_swd_file_short_input() {
+        assert!(!is_swd_file(&[]));
+        assert!(!is_swd_file(&MAGIC_NUMBER[..4]));
+    }
+
+    #[test]
+    fn is_swd_file_wrong_prefix() {
+        assert!(!is_swd_file(&[0; 16]));
+        assert!(!is_swd_file(b"PK\x03\x04 not a vault"));
+    }
+}
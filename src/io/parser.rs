@@ -3,10 +3,18 @@ use std::{collections::HashMap, os::raw};
 use crate::{
     cipher::CipherRegistry,
     entity::{
-        collection::{Collection, COLLECTION_ENDER_BYTE, COLLECTION_STARTER_BYTE},
+        collection::{
+            Collection, COLLECTION_ENDER_BYTE, COLLECTION_LENGTH_BYTES_LENGTH,
+            COLLECTION_LENGTH_VERSION, COLLECTION_STARTER_BYTE,
+        },
         record::{Record, RECORD_STARTER_BYTE},
-        value::{Value, SECRET_VALUE_STARTER_BYTE, VALUE_LENGTH_BYTES_LENGTH, VALUE_STARTER_BYTE},
-        Entries, Header, Swd, VERSION_BYTES_LENGTH,
+        value::{
+            Value, ValueKind, LARGE_SECRET_VALUE_STARTER_BYTE, LARGE_VALUE_LENGTH_BYTES_LENGTH,
+            LARGE_VALUE_STARTER_BYTE, LARGE_VALUE_VERSION, SECRET_VALUE_STARTER_BYTE,
+            VALUE_KIND_VERSION, VALUE_LENGTH_BYTES_LENGTH, VALUE_STARTER_BYTE,
+        },
+        Entries, Header, Swd, INTEGRITY_TRAILER_LENGTH, INTEGRITY_VERSION, VERSION,
+        VERSION_BYTES_LENGTH,
     },
     error::ParseError,
     hash::HashFunctionRegistry,
@@ -15,35 +23,174 @@ use crate::{
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// How deep collections may nest by default, counting the root as depth 0
+pub const DEFAULT_MAX_DEPTH: usize = 128;
+/// How many bytes of values a vault may hold by default
+pub const DEFAULT_MAX_TOTAL_BYTES: usize = 64 * 1024 * 1024;
+/// How many key value entries a vault may hold by default
+pub const DEFAULT_MAX_ENTRIES: usize = 1024 * 1024;
+
+/// Bounds on the resources a single parse may use,
+/// for callers parsing untrusted input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserConfig {
+    /// Deepest collection nesting allowed, the root being depth 0
+    pub max_depth: usize,
+    /// Total length of all parsed values, keys included
+    pub max_total_bytes: usize,
+    /// Number of key value entries across the header and all entities
+    pub max_entries: usize,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+            max_entries: DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
 pub struct Parser<'a> {
+    input: &'a [u8],
     remaining_input: &'a [u8],
+    version: u32,
+    config: ParserConfig,
+    total_bytes: usize,
+    entries: usize,
+    lenient: bool,
+    errors: Vec<ParseError>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new() -> Self {
+        Self::with_config(ParserConfig::default())
+    }
+
+    pub fn with_config(config: ParserConfig) -> Self {
         Self {
+            input: &[],
             remaining_input: &[],
+            version: VERSION,
+            config,
+            total_bytes: 0,
+            entries: 0,
+            lenient: false,
+            errors: vec![],
         }
     }
 
+    /// Limits how deep collections may nest. Collections are parsed
+    /// recursively, so this bounds the stack used by untrusted input.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    /// Parses a vault with the default cipher and hash function registries
     pub fn parse(&mut self, input: &'a [u8]) -> ParseResult<Swd> {
+        self.parse_with_registries(
+            input,
+            CipherRegistry::default(),
+            HashFunctionRegistry::default(),
+        )
+    }
+
+    /// Parses as much of a damaged vault as possible. Records and collections
+    /// that fail to parse are skipped up to the next entity that does, and
+    /// every error met on the way is returned along with whatever was salvaged.
+    /// No vault is returned if the header itself cannot be read.
+    pub fn parse_lenient(&mut self, input: &'a [u8]) -> (Option<Swd>, Vec<ParseError>) {
+        self.lenient = true;
+        self.errors.clear();
+        let result = self.parse(input);
+        self.lenient = false;
+
+        let mut errors = std::mem::take(&mut self.errors);
+        match result {
+            Ok(swd) => (Some(swd), errors),
+            Err(err) => {
+                errors.push(err);
+                (None, errors)
+            }
+        }
+    }
+
+    /// Parses a vault whose header names resolve against the given registries
+    pub fn parse_with_registries(
+        &mut self,
+        input: &'a [u8],
+        cipher_registry: CipherRegistry,
+        hash_function_registry: HashFunctionRegistry,
+    ) -> ParseResult<Swd> {
+        self.input = input;
         self.remaining_input = input;
+        self.total_bytes = 0;
+        self.entries = 0;
         self.ensure_magic_number()?;
+        // The header values are read before the version is known,
+        // so the layout of its leading version entry decides it
+        self.version = if self.has_legacy_header() {
+            VALUE_KIND_VERSION - 1
+        } else {
+            VERSION
+        };
         let header = self.parse_header()?;
-        let collection = self.parse_collection()?;
+        if header.version() > VERSION {
+            return Err(ParseError::UnsupportedVersion(header.version()));
+        }
+        if (header.version() >= VALUE_KIND_VERSION) != (self.version >= VALUE_KIND_VERSION) {
+            return Err(ParseError::InvalidVersionNumber);
+        }
+        self.version = header.version();
+        let collection = self.parse_collection(0)?;
+
+        let mut swd = Swd::from_root(header, collection, cipher_registry, hash_function_registry);
+        if self.version >= INTEGRITY_VERSION {
+            let signed_bytes = &input[..input.len() - self.remaining_input.len()];
+            let trailer = match self.remaining_input.len() {
+                0 => None,
+                INTEGRITY_TRAILER_LENGTH => Some(self.remaining_input),
+                _ => {
+                    self.tolerate(ParseError::IntegrityCheckFailed)?;
+                    None
+                }
+            };
+            swd.set_integrity(signed_bytes, trailer);
+        } else {
+            swd.set_unsigned();
+        }
+        if self.version < VERSION {
+            swd.migrate(self.version);
+        }
 
-        Ok(Swd::from_root(
-            header,
-            collection,
-            CipherRegistry::default(),
-            HashFunctionRegistry::default(),
-        ))
+        Ok(swd)
     }
 
     fn inject_input(&mut self, input: &'a [u8]) {
+        self.input = input;
         self.remaining_input = input;
     }
 
+    /// Position of the next byte to be parsed, counted from the start of the input
+    fn offset(&self) -> usize {
+        self.input.len() - self.remaining_input.len()
+    }
+
+    fn unexpected_starter_byte(&self) -> ParseError {
+        ParseError::UnexpectedStarterByte {
+            offset: self.offset(),
+            found: self.remaining_input[0],
+        }
+    }
+
+    /// Whether the header starts with a version key without a kind byte
+    fn has_legacy_header(&self) -> bool {
+        self.remaining_input
+            .starts_with(&[VALUE_STARTER_BYTE, 0, 1, b'v'])
+    }
+
     fn parse_header(&mut self) -> ParseResult<Header> {
         let mut raw_header: Entries = HashMap::new();
 
@@ -57,7 +204,7 @@ impl<'a> Parser<'a> {
             starter_byte = self.peek_starter_byte()?;
         }
 
-        let mut header: Header = raw_header.try_into()?;
+        let header: Header = raw_header.try_into()?;
 
         Ok(header)
     }
@@ -79,30 +226,61 @@ impl<'a> Parser<'a> {
         Ok(record)
     }
 
-    fn parse_collection(&mut self) -> ParseResult<Collection> {
+    fn parse_collection(&mut self, depth: usize) -> ParseResult<Collection> {
+        if depth > self.config.max_depth {
+            return Err(ParseError::MaxDepthExceeded);
+        }
         let mut starter_byte = self.ensure_starter_byte(COLLECTION_STARTER_BYTE)?;
         let mut extras: Entries = HashMap::new();
         let mut records: Vec<Record> = vec![];
         let mut children: Vec<Collection> = vec![];
 
+        let mut expected_length = None;
+        if self.version >= COLLECTION_LENGTH_VERSION {
+            let length_bytes = self.take_bytes_or(
+                COLLECTION_LENGTH_BYTES_LENGTH,
+                ParseError::UnexpectedEndOfFile,
+            )?;
+            let length = u32::from_be_bytes(length_bytes.try_into().unwrap()) as usize;
+            expected_length = Some(length);
+        }
+        let remaining_length = self.remaining_input.len();
+        let body_end = expected_length.map(|length| self.offset() + length);
+
         starter_byte = self.peek_starter_byte()?;
         while starter_byte != COLLECTION_ENDER_BYTE {
-            match starter_byte {
-                VALUE_STARTER_BYTE => {
-                    let (key, value) = self.parse_key_value()?;
+            let entity_start = self.offset();
+            let result = match starter_byte {
+                VALUE_STARTER_BYTE => self.parse_key_value().map(|(key, value)| {
                     extras.insert(key, value);
+                }),
+                COLLECTION_STARTER_BYTE => self
+                    .parse_collection(depth + 1)
+                    .map(|collection| children.push(collection)),
+                RECORD_STARTER_BYTE => self.parse_record().map(|record| records.push(record)),
+                _ => Err(self.unexpected_starter_byte()),
+            };
+            if let Err(err) = result {
+                self.recover(err, entity_start, depth, body_end)?;
+            }
+            starter_byte = match self.peek_starter_byte() {
+                Ok(starter_byte) => starter_byte,
+                Err(err) => {
+                    // A truncated collection keeps what was read before the end
+                    self.tolerate(err)?;
+                    break;
                 }
-                COLLECTION_STARTER_BYTE => {
-                    let collection = self.parse_collection()?;
-                    children.push(collection);
-                }
-                RECORD_STARTER_BYTE => {
-                    let record = self.parse_record()?;
-                    records.push(record);
-                }
-                _ => return Err(ParseError::UnexpectedStarterByte),
+            };
+        }
+
+        let actual_length = remaining_length - self.remaining_input.len();
+        if let Some(expected_length) = expected_length {
+            if expected_length != actual_length && !self.remaining_input.is_empty() {
+                self.tolerate(ParseError::CollectionLengthMismatch(
+                    expected_length,
+                    actual_length,
+                ))?;
             }
-            starter_byte = self.peek_starter_byte()?;
         }
 
         self.take_bytes_or(1, ParseError::UnexpectedEndOfFile);
@@ -114,46 +292,140 @@ impl<'a> Parser<'a> {
         Ok(collection)
     }
 
+    /// Records `err` when parsing leniently, fails with it otherwise
+    fn tolerate(&mut self, err: ParseError) -> ParseResult<()> {
+        if !self.lenient {
+            return Err(err);
+        }
+        // Enclosing collections run into the same end of input
+        if self.errors.last() != Some(&err) {
+            self.errors.push(err);
+        }
+        Ok(())
+    }
+
+    /// Skips past the entity that failed to parse at `entity_start` up to the
+    /// next record or collection that parses cleanly, or to the end of the
+    /// enclosing collection body if none does. Resource limits are never
+    /// recovered from.
+    fn recover(
+        &mut self,
+        err: ParseError,
+        entity_start: usize,
+        depth: usize,
+        body_end: Option<usize>,
+    ) -> ParseResult<()> {
+        if matches!(
+            err,
+            ParseError::MaxDepthExceeded | ParseError::ResourceLimitExceeded
+        ) {
+            return Err(err);
+        }
+        self.tolerate(err)?;
+
+        let input = self.input;
+        let scan_end = body_end.unwrap_or(input.len()).min(input.len());
+        let (total_bytes, entries) = (self.total_bytes, self.entries);
+        let mut position = entity_start + 1;
+        while position < scan_end {
+            let starter_byte = input[position];
+            if starter_byte == COLLECTION_ENDER_BYTE && body_end.is_none() {
+                break;
+            }
+            if starter_byte == RECORD_STARTER_BYTE || starter_byte == COLLECTION_STARTER_BYTE {
+                self.remaining_input = &input[position..];
+                self.lenient = false;
+                let parsed = if starter_byte == RECORD_STARTER_BYTE {
+                    self.parse_record().is_ok()
+                } else {
+                    self.parse_collection(depth + 1).is_ok()
+                };
+                self.lenient = true;
+                (self.total_bytes, self.entries) = (total_bytes, entries);
+                if parsed && self.offset() <= scan_end {
+                    break;
+                }
+            }
+            position += 1;
+        }
+
+        self.remaining_input = &input[position.min(input.len())..];
+        Ok(())
+    }
+
     fn parse_key_value(&mut self) -> ParseResult<(String, Value)> {
+        self.entries += 1;
+        if self.entries > self.config.max_entries {
+            return Err(ParseError::ResourceLimitExceeded);
+        }
         let key = self.parse_value(false)?;
         let starter_byte = self.peek_starter_byte()?;
-        let is_secret_value = starter_byte == SECRET_VALUE_STARTER_BYTE;
+        let is_secret_value = starter_byte == SECRET_VALUE_STARTER_BYTE
+            || starter_byte == LARGE_SECRET_VALUE_STARTER_BYTE;
         let value = self.parse_value(is_secret_value)?;
 
         Ok((key.parse_string()?, value))
     }
 
     fn parse_value(&mut self, is_secret: bool) -> ParseResult<Value> {
-        let starter_byte = if is_secret {
-            SECRET_VALUE_STARTER_BYTE
+        let (starter_byte, large_starter_byte) = if is_secret {
+            (SECRET_VALUE_STARTER_BYTE, LARGE_SECRET_VALUE_STARTER_BYTE)
         } else {
-            VALUE_STARTER_BYTE
+            (VALUE_STARTER_BYTE, LARGE_VALUE_STARTER_BYTE)
+        };
+        let is_large = if self.version >= LARGE_VALUE_VERSION {
+            self.ensure_starter_byte_in(&[starter_byte, large_starter_byte])? == large_starter_byte
+        } else {
+            self.ensure_starter_byte(starter_byte)?;
+            false
         };
-        self.ensure_starter_byte(starter_byte)?;
-        self.ensure_remaining_length_or(
-            VALUE_LENGTH_BYTES_LENGTH,
-            ParseError::UnexpectedEndOfFile,
-        )?;
-
-        let (length_bytes, remaining_input) =
-            self.remaining_input.split_at(VALUE_LENGTH_BYTES_LENGTH);
+
+        let mut kind = ValueKind::Text;
+        if self.version >= VALUE_KIND_VERSION {
+            let kind_byte = self.take_bytes_or(1, ParseError::UnexpectedEndOfFile)?;
+            kind = kind_byte[0].try_into()?;
+        }
+
+        let length_bytes_length = if is_large {
+            LARGE_VALUE_LENGTH_BYTES_LENGTH
+        } else {
+            VALUE_LENGTH_BYTES_LENGTH
+        };
+        self.ensure_remaining_length_or(length_bytes_length, ParseError::UnexpectedEndOfFile)?;
+
+        let (length_bytes, remaining_input) = self.remaining_input.split_at(length_bytes_length);
         self.remaining_input = remaining_input;
-        let length: usize = u16::from_be_bytes(length_bytes.try_into().unwrap()) as usize;
+        let length: usize = if is_large {
+            u32::from_be_bytes(length_bytes.try_into().unwrap()) as usize
+        } else {
+            u16::from_be_bytes(length_bytes.try_into().unwrap()) as usize
+        };
 
         self.ensure_remaining_length(length, |remain, need| {
             ParseError::UnexpectedEndOfValue(remain, need)
         })?;
 
+        self.total_bytes += length;
+        if self.total_bytes > self.config.max_total_bytes {
+            return Err(ParseError::ResourceLimitExceeded);
+        }
+
         let (value_bytes, remaining_input) = self.remaining_input.split_at(length);
         self.remaining_input = remaining_input;
 
-        Ok(Value::new(value_bytes.into(), is_secret))
+        if let Some(expected_length) = kind.fixed_length() {
+            if expected_length != length {
+                return Err(ParseError::InvalidValueLength(expected_length, length));
+            }
+        }
+
+        Ok(Value::with_kind(value_bytes, kind, is_secret))
     }
 
     fn ensure_magic_number(&mut self) -> ParseResult<()> {
         let magic_number =
             self.take_bytes_or(MAGIC_NUMBER.len(), ParseError::UnexpectedEndOfFile)?;
-        if !Parser::check_magic_number(magic_number) {
+        if !check_magic_number(magic_number) {
             return Err(ParseError::InvalidMagicNumber);
         }
         Ok(())
@@ -162,7 +434,7 @@ impl<'a> Parser<'a> {
     fn ensure_starter_byte(&mut self, starter_byte: u8) -> ParseResult<u8> {
         self.ensure_remaining_input()?;
         if self.remaining_input[0] != starter_byte {
-            return Err(ParseError::UnexpectedStarterByte);
+            return Err(self.unexpected_starter_byte());
         }
         self.remaining_input = &self.remaining_input[1..];
         Ok(starter_byte)
@@ -177,7 +449,7 @@ impl<'a> Parser<'a> {
             }
         }
 
-        Err(ParseError::UnexpectedStarterByte)
+        Err(self.unexpected_starter_byte())
     }
 
     fn peek_starter_byte(&mut self) -> ParseResult<u8> {
@@ -200,18 +472,8 @@ impl<'a> Parser<'a> {
         self.take_bytes(length, |_, _| err)
     }
 
-    fn check_magic_number(magic_number: &[u8]) -> bool {
-        for i in 0..MAGIC_NUMBER.len() {
-            if magic_number[i] != MAGIC_NUMBER[i] {
-                return false;
-            }
-        }
-
-        true
-    }
-
     fn ensure_remaining_input(&self) -> ParseResult<()> {
-        if self.remaining_input.len() == 0 {
+        if self.remaining_input.is_empty() {
             return Err(ParseError::UnexpectedEndOfFile);
         }
 
@@ -235,19 +497,41 @@ impl<'a> Parser<'a> {
     }
 }
 
+impl<'a> Default for Parser<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether `bytes` starts with the magic number
+pub(crate) fn check_magic_number(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC_NUMBER)
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
+
+    use proptest::{collection::vec, prelude::*};
+
     use crate::{
+        cipher::CipherRegistry,
         entity::{
             collection::{Collection, COLLECTION_ENDER_BYTE, COLLECTION_STARTER_BYTE},
-            record::RECORD_STARTER_BYTE,
-            value::{SECRET_VALUE_STARTER_BYTE, VALUE_STARTER_BYTE},
+            record::{Record, RECORD_STARTER_BYTE},
+            tests::{add_dummy_record, dummy_swd},
+            value::{
+                Value, ValueKind, LARGE_SECRET_VALUE_STARTER_BYTE, LARGE_VALUE_STARTER_BYTE,
+                SECRET_VALUE_STARTER_BYTE, VALUE_STARTER_BYTE,
+            },
+            Entries, Header, Swd, INTEGRITY_TRAILER_LENGTH, VERSION,
         },
         error::ParseError,
+        hash::HashFunctionRegistry,
         util::MAGIC_NUMBER,
     };
 
-    use super::Parser;
+    use super::{Parser, ParserConfig};
 
     #[test]
     fn ensure_magic_number_success() {
@@ -279,7 +563,7 @@ mod test {
     #[test]
     fn parse_value_success() {
         let mut parser = Parser::new();
-        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 5, 0x68, 0x65, 0x6c, 0x6c, 0x6f]);
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 0, 5, 0x68, 0x65, 0x6c, 0x6c, 0x6f]);
         let result = parser.parse_value(false);
         assert!(result.is_ok());
         let value = result.unwrap();
@@ -294,6 +578,7 @@ mod test {
         parser.inject_input(&[
             SECRET_VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -316,7 +601,13 @@ mod test {
         let result = parser.parse_value(false);
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte);
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: SECRET_VALUE_STARTER_BYTE
+            }
+        );
     }
 
     #[test]
@@ -326,7 +617,13 @@ mod test {
         let result = parser.parse_value(true);
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte);
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: VALUE_STARTER_BYTE
+            }
+        );
     }
 
     #[test]
@@ -336,7 +633,13 @@ mod test {
         let result = parser.parse_value(false);
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte);
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: 0xff
+            }
+        );
     }
 
     #[test]
@@ -352,7 +655,7 @@ mod test {
     #[test]
     fn parse_value_eof_len() {
         let mut parser = Parser::new();
-        parser.inject_input(&[VALUE_STARTER_BYTE, 0]);
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 0]);
         let result = parser.parse_value(false);
         assert!(result.is_err());
         let err = result.unwrap_err();
@@ -362,25 +665,245 @@ mod test {
     #[test]
     fn parse_value_eof() {
         let mut parser = Parser::new();
-        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 3, 0, 0]);
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 0, 3, 0, 0]);
         let result = parser.parse_value(false);
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert_eq!(err, ParseError::UnexpectedEndOfValue(2, 3));
     }
 
+    #[test]
+    fn parse_value_typed() {
+        let mut parser = Parser::new();
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0x02, 0, 8, 0, 0, 0, 0, 0, 0, 1, 0]);
+        let result = parser.parse_value(false);
+        assert!(result.is_ok());
+        let value = result.unwrap();
+        assert_eq!(value.kind(), ValueKind::U64);
+        assert_eq!(value.as_u64(), Some(256));
+    }
+
+    #[test]
+    fn parse_value_invalid_kind() {
+        let mut parser = Parser::new();
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0xff, 0, 0]);
+        let result = parser.parse_value(false);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert_eq!(err, ParseError::InvalidValueKind(0xff));
+    }
+
+    #[test]
+    fn parse_value_invalid_length() {
+        let mut parser = Parser::new();
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0x03, 0, 2, 0, 1]);
+        let result = parser.parse_value(false);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert_eq!(err, ParseError::InvalidValueLength(8, 2));
+    }
+
+    #[test]
+    fn parse_value_without_kind() {
+        let mut parser = Parser::new();
+        parser.version = 2;
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 2, 0x68, 0x69]);
+        let result = parser.parse_value(false);
+        assert!(result.is_ok());
+        let value = result.unwrap();
+        assert_eq!(value.kind(), ValueKind::Text);
+        assert_eq!(value.as_str(), Some("hi"));
+    }
+
+    #[test]
+    fn parse_large_value_roundtrip() {
+        let bytes: Vec<u8> = (0..100 * 1024).map(|i| i as u8).collect();
+        let input = Value::bytes(&bytes, true).to_bytes();
+        assert_eq!(input[0], LARGE_SECRET_VALUE_STARTER_BYTE);
+
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let value = parser.parse_value(true).unwrap();
+        assert!(value.is_secret());
+        assert_eq!(value.kind(), ValueKind::Bytes);
+        assert_eq!(value.inner(), &bytes[..]);
+        assert!(parser.remaining_input.is_empty());
+    }
+
+    #[test]
+    fn parse_large_value_unsupported_version() {
+        let mut parser = Parser::new();
+        parser.version = 4;
+        parser.inject_input(&[LARGE_VALUE_STARTER_BYTE, 0, 0, 0, 0, 2, 0x68, 0x69]);
+        let result = parser.parse_value(false);
+        assert_eq!(
+            result.unwrap_err(),
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: LARGE_VALUE_STARTER_BYTE
+            }
+        );
+    }
+
+    #[test]
+    fn parse_legacy_file() {
+        let mut input = MAGIC_NUMBER.to_vec();
+        for (key, value) in [
+            (&b"v"[..], &2u32.to_be_bytes()[..]),
+            (b"mkhf", b"sha3-256"),
+            (b"khf", b"sha3-256"),
+            (b"kc", b"aes256-gcm"),
+            (b"mks", &[1, 2, 3]),
+            (b"ks", &[4, 5, 6]),
+            (b"mkh", &[7, 8, 9]),
+        ] {
+            input.append(&mut legacy_value(key, false));
+            input.append(&mut legacy_value(value, false));
+        }
+        let mut body = legacy_value(b"label", false);
+        body.append(&mut legacy_value(b"root", false));
+        body.push(RECORD_STARTER_BYTE);
+        body.append(&mut legacy_value(b"label", false));
+        body.append(&mut legacy_value(b"abc", false));
+        body.append(&mut legacy_value(b"secret", false));
+        body.append(&mut legacy_value(b"def", true));
+        input.append(&mut wrap_collection(body));
+
+        let result = Parser::new().parse(&input);
+        assert!(result.is_ok());
+        let swd = result.unwrap();
+        assert_eq!(swd.header().version(), VERSION);
+        assert_eq!(swd.migrated_from(), Some(2));
+        assert_eq!(swd.header().key_cipher(), "aes256-gcm");
+        let root = swd.get_root();
+        assert_eq!(root.label(), "root");
+        assert_eq!(root.get_record(0).unwrap().label(), "abc");
+    }
+
+    #[test]
+    fn parse_unsupported_version() {
+        let header = Header::new(
+            VERSION + 1,
+            "sha3-256".to_owned(),
+            "sha3-256".to_owned(),
+            "aes256-gcm".to_owned(),
+            &[1, 2, 3],
+            &[4, 5, 6],
+            &[7, 8, 9],
+            HashMap::new(),
+        );
+        let mut input = MAGIC_NUMBER.to_vec();
+        input.append(&mut header.to_bytes());
+        input.append(&mut dummy_collection());
+        let result = Parser::new().parse(&input);
+        assert!(matches!(
+            result,
+            Err(ParseError::UnsupportedVersion(version)) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn parse_lenient_skips_corrupt_record() {
+        let mut swd = dummy_swd(b"master key");
+        for label in ["first", "middle", "last"] {
+            swd.get_root_mut()
+                .add_record(Record::new(label.to_owned(), Box::new([1, 2, 3])));
+        }
+        let mut input = swd.to_bytes();
+        let label_start = input
+            .windows(b"middle".len())
+            .position(|window| window == b"middle")
+            .unwrap();
+        // Kind byte of the middle record's label value
+        input[label_start - 3] = 0xff;
+
+        assert!(matches!(
+            Parser::new().parse(&input),
+            Err(ParseError::InvalidValueKind(0xff))
+        ));
+        let (swd, errors) = Parser::new().parse_lenient(&input);
+        assert_eq!(errors, vec![ParseError::InvalidValueKind(0xff)]);
+        let swd = swd.unwrap();
+        let labels: Vec<&String> = swd
+            .get_root()
+            .records()
+            .iter()
+            .map(|record| record.label())
+            .collect();
+        assert_eq!(labels, vec!["first", "last"]);
+    }
+
+    #[test]
+    fn parse_lenient_keeps_records_before_truncation() {
+        let mut swd = dummy_swd(b"master key");
+        for label in ["first", "second"] {
+            swd.get_root_mut()
+                .add_record(Record::new(label.to_owned(), Box::new([1, 2, 3])));
+        }
+        let mut input = swd.to_bytes();
+        input.truncate(input.len() - INTEGRITY_TRAILER_LENGTH - 10);
+
+        let (swd, errors) = Parser::new().parse_lenient(&input);
+        assert_eq!(errors[0], ParseError::UnexpectedEndOfFile);
+        assert_eq!(swd.unwrap().get_root().records().len(), 1);
+    }
+
+    #[test]
+    fn parse_lenient_without_header() {
+        let (swd, errors) = Parser::new().parse_lenient(&MAGIC_NUMBER);
+        assert!(swd.is_none());
+        assert_eq!(errors, vec![ParseError::UnexpectedEndOfFile]);
+    }
+
+    #[test]
+    fn parse_unlock_and_reveal() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let input = swd.to_bytes();
+
+        let mut swd = Parser::new().parse(&input).unwrap();
+        assert!(swd.unlock(b"master key"));
+        let mut record = swd.get_root_mut().take_record(0).unwrap();
+        let (_, decrypt) = swd.get_key_cipher();
+        let key = swd.header().get_key().unwrap();
+        assert_eq!(record.reveal(decrypt, key), Ok("top secret"));
+        assert_eq!(record.revealed_secret().unwrap(), "top secret");
+    }
+
+    #[test]
+    fn parse_large_extra_roundtrip() {
+        let mut swd = dummy_swd(b"master key");
+        swd.unlock(b"master key");
+        add_dummy_record(&mut swd, "abc", "top secret");
+        let attachment = vec![b'a'; 100 * 1024];
+        let record = swd.get_root_mut().get_record_mut(0).unwrap();
+        record.add_extra("attachment", &attachment, false).unwrap();
+        let input = swd.to_bytes();
+
+        let swd = Parser::new().parse(&input).unwrap();
+        let record = swd.get_root().get_record(0).unwrap();
+        assert_eq!(
+            record.get_extra("attachment").unwrap().inner(),
+            &attachment[..]
+        );
+        assert_eq!(record.label(), "abc");
+    }
+
     #[test]
     fn parse_key_value_success() {
         let mut parser = Parser::new();
         parser.inject_input(&[
             VALUE_STARTER_BYTE,
             0,
+            0,
             3,
             0x6d,
             0x73,
             0x67,
             VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -403,12 +926,14 @@ mod test {
         parser.inject_input(&[
             VALUE_STARTER_BYTE,
             0,
+            0,
             3,
             0x6d,
             0x73,
             0x67,
             SECRET_VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -441,12 +966,14 @@ mod test {
         parser.inject_input(&[
             SECRET_VALUE_STARTER_BYTE,
             0,
+            0,
             3,
             0x6d,
             0x73,
             0x67,
             VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -457,7 +984,13 @@ mod test {
         let result = parser.parse_key_value();
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte)
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: SECRET_VALUE_STARTER_BYTE
+            }
+        )
     }
 
     #[test]
@@ -472,6 +1005,7 @@ mod test {
             0x67,
             VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -482,13 +1016,19 @@ mod test {
         let result = parser.parse_key_value();
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte)
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: 0xff
+            }
+        )
     }
 
     #[test]
     fn parse_key_value_eof_key() {
         let mut parser = Parser::new();
-        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 3, 0x6d]);
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 0, 3, 0x6d]);
         let result = parser.parse_key_value();
         assert!(result.is_err());
         let err = result.unwrap_err();
@@ -501,12 +1041,14 @@ mod test {
         parser.inject_input(&[
             VALUE_STARTER_BYTE,
             0,
+            0,
             3,
             0x6d,
             0x73,
             0x67,
             VALUE_STARTER_BYTE,
             0,
+            0,
             5,
             0x68,
             0x65,
@@ -521,13 +1063,43 @@ mod test {
     #[test]
     fn parse_key_value_no_value() {
         let mut parser = Parser::new();
-        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 3, 0x6d, 0x73, 0x67]);
+        parser.inject_input(&[VALUE_STARTER_BYTE, 0, 0, 3, 0x6d, 0x73, 0x67]);
         let result = parser.parse_key_value();
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert_eq!(err, ParseError::UnexpectedEndOfFile)
     }
 
+    #[test]
+    fn parse_header_roundtrip() {
+        // Distinct values for every field, so that a field
+        // missing or written under the wrong key is caught
+        let header = Header::new(
+            3,
+            "sha3-256".to_owned(),
+            "blake3".to_owned(),
+            "aes128-gcm".to_owned(),
+            &[1, 2, 3],
+            &[4, 5, 6],
+            &[7, 8, 9],
+            HashMap::new(),
+        );
+        let mut input = header.to_bytes();
+        input.push(COLLECTION_STARTER_BYTE);
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let result = parser.parse_header();
+        assert!(result.is_ok());
+        let parsed = result.unwrap();
+        assert_eq!(parsed.version(), 3);
+        assert_eq!(parsed.master_key_hash_fn(), "sha3-256");
+        assert_eq!(parsed.key_hash_fn(), "blake3");
+        assert_eq!(parsed.key_cipher(), "aes128-gcm");
+        assert_eq!(parsed.master_key_hash(), &vec![1, 2, 3]);
+        assert_eq!(parsed.master_key_salt(), &vec![4, 5, 6]);
+        assert_eq!(parsed.key_salt(), &vec![7, 8, 9]);
+    }
+
     #[test]
     fn parse_record_success() {
         let mut parser = Parser::new();
@@ -539,8 +1111,40 @@ mod test {
         assert!(result.is_ok());
         let record = result.unwrap();
         assert_eq!(record.label(), "abc");
-        let expected_value: Box<[u8]> = Box::new(['d' as u8, 'e' as u8, 'f' as u8]);
+        let expected_value: Box<[u8]> = Box::new([b'd', b'e', b'f']);
         assert_eq!(record.secret(), &expected_value);
+        assert_eq!(record.created_at(), 0);
+        assert_eq!(record.modified_at(), 0);
+    }
+
+    #[test]
+    fn parse_record_timestamps_roundtrip() {
+        let record = Record::new("abc".to_owned(), Box::new([1, 2, 3]));
+        let input = record.to_bytes();
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let result = parser.parse_record();
+        assert!(result.is_ok());
+        let parsed = result.unwrap();
+        assert_ne!(parsed.created_at(), 0);
+        assert_eq!(parsed.created_at(), record.created_at());
+        assert_eq!(parsed.modified_at(), record.modified_at());
+        assert!(parsed.get_extra("ctime").is_none());
+        assert!(parsed.get_extra("mtime").is_none());
+    }
+
+    #[test]
+    fn parse_record_notes_roundtrip() {
+        let mut record = Record::new("abc".to_owned(), Box::new([1, 2, 3]));
+        assert!(record.notes().is_none());
+        record.set_notes("work account, recovery email X");
+        let input = record.to_bytes();
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let mut parsed = parser.parse_record().unwrap();
+        assert_eq!(parsed.notes(), Some("work account, recovery email X"));
+        parsed.set_notes("");
+        assert!(parsed.notes().is_none());
     }
 
     #[test]
@@ -553,7 +1157,13 @@ mod test {
         let result = parser.parse_record();
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte);
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: 0xff
+            }
+        );
     }
 
     #[test]
@@ -597,7 +1207,7 @@ mod test {
         let mut parser = Parser::new();
         let input = dummy_collection();
         parser.inject_input(&input);
-        let result = parser.parse_collection();
+        let result = parser.parse_collection(0);
         assert!(result.is_ok());
         let collection: Collection = result.unwrap();
         assert_eq!(collection.label(), "abc");
@@ -610,7 +1220,7 @@ mod test {
         let mut parser = Parser::new();
         let input = dummy_collection_nested();
         parser.inject_input(&input);
-        let result = parser.parse_collection();
+        let result = parser.parse_collection(0);
         assert!(result.is_ok());
         let collection: Collection = result.unwrap();
         assert_eq!(collection.label(), "abc");
@@ -620,34 +1230,349 @@ mod test {
         assert_eq!(records.len(), 3);
     }
 
+    #[test]
+    fn parse_collection_label_only() {
+        let mut parser = Parser::new();
+        let input = wrap_collection(dummy_label());
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_ok());
+        let collection = result.unwrap();
+        assert_eq!(collection.label(), "abc");
+        assert!(collection.children().is_empty());
+        assert!(collection.records().is_empty());
+        assert!(parser.remaining_input.is_empty());
+    }
+
+    #[test]
+    fn parse_collection_children_without_records() {
+        let mut parser = Parser::new();
+        let mut body = dummy_label();
+        body.append(&mut wrap_collection(dummy_label()));
+        body.append(&mut dummy_collection());
+        let input = wrap_collection(body);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_ok());
+        let collection = result.unwrap();
+        assert!(collection.records().is_empty());
+        assert_eq!(collection.children().len(), 2);
+        assert!(collection.children()[0].records().is_empty());
+        assert_eq!(collection.children()[1].records().len(), 2);
+        assert!(parser.remaining_input.is_empty());
+    }
+
+    #[test]
+    fn parse_collection_without_metadata() {
+        let mut parser = Parser::new();
+        let input = wrap_collection(vec![]);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert_eq!(err, ParseError::MissingRequiredField("label".to_owned()));
+    }
+
+    #[test]
+    fn parse_collection_empty_roundtrip() {
+        let mut root = Collection::new("root".to_owned());
+        root.add_child(Collection::new("empty".to_owned()));
+        let input = root.to_bytes();
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_ok());
+        let collection = result.unwrap();
+        assert_eq!(collection.label(), "root");
+        assert!(collection.records().is_empty());
+        assert_eq!(collection.children().len(), 1);
+        assert_eq!(collection.children()[0].label(), "empty");
+        assert!(collection.children()[0].children().is_empty());
+        assert!(collection.children()[0].records().is_empty());
+    }
+
     #[test]
     fn parse_collection_unexpected_starter_byte() {
         let mut parser = Parser::new();
         let mut input = dummy_collection();
         input[0] = 0xff;
         parser.inject_input(&input);
-        let result = parser.parse_collection();
+        let result = parser.parse_collection(0);
         assert!(result.is_err());
         let err = result.unwrap_err();
-        assert_eq!(err, ParseError::UnexpectedStarterByte);
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: 0,
+                found: 0xff
+            }
+        );
     }
 
     #[test]
-    fn parse_collection_missing_label() {
+    fn parse_collection_reports_error_offset() {
         let mut parser = Parser::new();
         let mut input = dummy_collection();
-        let label_length = dummy_label().len();
-        input.drain(1..label_length + 1);
+        let ender_offset = input.len() - 1;
+        input[ender_offset] = 0xff;
         parser.inject_input(&input);
-        let result = parser.parse_collection();
+        let err = parser.parse_collection(0).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::UnexpectedStarterByte {
+                offset: ender_offset,
+                found: 0xff
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("unexpected starter byte 0xff at offset {}", ender_offset)
+        );
+    }
+
+    #[test]
+    fn parse_collection_missing_label() {
+        let mut parser = Parser::new();
+        let mut body = vec![];
+        body.append(&mut dummy_record());
+        let input = wrap_collection(body);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert_eq!(err, ParseError::MissingRequiredField("label".to_owned()));
     }
 
+    #[test]
+    fn parse_collection_length_mismatch() {
+        let mut parser = Parser::new();
+        let mut input = dummy_collection();
+        input[4] += 1;
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        let length = input.len() - 6;
+        assert_eq!(
+            err,
+            ParseError::CollectionLengthMismatch(length + 1, length)
+        );
+    }
+
+    #[test]
+    fn parse_collection_without_length() {
+        let mut parser = Parser::new();
+        parser.version = 1;
+        let mut input = vec![COLLECTION_STARTER_BYTE];
+        input.append(&mut legacy_value(b"label", false));
+        input.append(&mut legacy_value(b"abc", false));
+        input.push(RECORD_STARTER_BYTE);
+        input.append(&mut legacy_value(b"label", false));
+        input.append(&mut legacy_value(b"def", false));
+        input.append(&mut legacy_value(b"secret", false));
+        input.append(&mut legacy_value(b"ghi", true));
+        input.push(COLLECTION_ENDER_BYTE);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_ok());
+        let collection = result.unwrap();
+        assert_eq!(collection.label(), "abc");
+        assert_eq!(collection.records().len(), 1);
+    }
+
+    #[test]
+    fn parse_collection_max_depth_exceeded() {
+        let mut parser = Parser::new();
+        let input = [COLLECTION_STARTER_BYTE, 0, 0, 0, 0].repeat(100_000);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_err());
+        let err = result.unwrap_err();
+        assert_eq!(err, ParseError::MaxDepthExceeded);
+    }
+
+    #[test]
+    fn parse_collection_custom_max_depth() {
+        let mut leaf = Collection::new("leaf".to_owned());
+        for depth in 0..3 {
+            let mut parent = Collection::new(format!("{}", depth));
+            parent.add_child(leaf);
+            leaf = parent;
+        }
+        let input = leaf.to_bytes();
+
+        let mut parser = Parser::new().with_max_depth(3);
+        parser.inject_input(&input);
+        assert!(parser.parse_collection(0).is_ok());
+
+        let mut parser = Parser::new().with_max_depth(2);
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert_eq!(result.unwrap_err(), ParseError::MaxDepthExceeded);
+    }
+
+    #[test]
+    fn parse_resource_limits() {
+        let mut swd = dummy_swd(b"master key");
+        swd.get_root_mut()
+            .add_record(Record::new("abc".to_owned(), Box::new([0; 100])));
+        let input = swd.to_bytes();
+        assert!(Parser::new().parse(&input).is_ok());
+
+        let config = ParserConfig {
+            max_total_bytes: 100,
+            ..ParserConfig::default()
+        };
+        let result = Parser::with_config(config).parse(&input);
+        assert!(matches!(result, Err(ParseError::ResourceLimitExceeded)));
+
+        let config = ParserConfig {
+            max_entries: 3,
+            ..ParserConfig::default()
+        };
+        let result = Parser::with_config(config).parse(&input);
+        assert!(matches!(result, Err(ParseError::ResourceLimitExceeded)));
+    }
+
+    #[test]
+    fn parse_collection_roundtrip() {
+        let mut child = Collection::new("child".to_owned());
+        child.add_record(Record::new("abc".to_owned(), Box::new([1, 2, 3])));
+        let mut root = Collection::new("root".to_owned());
+        root.add_child(child);
+        root.add_record(Record::new("def".to_owned(), Box::new([4, 5, 6])));
+        let input = root.to_bytes();
+        let mut parser = Parser::new();
+        parser.inject_input(&input);
+        let result = parser.parse_collection(0);
+        assert!(result.is_ok());
+        let collection = result.unwrap();
+        assert_eq!(collection.label(), "root");
+        assert_eq!(collection.children().len(), 1);
+        assert_eq!(collection.children()[0].records().len(), 1);
+        assert_eq!(collection.records().len(), 1);
+    }
+
+    /// Extra keys are namespaced so they never collide with required fields
+    const EXTRA_KEY: &str = "x\\.[a-z]{1,6}";
+    const LABEL: &str = ".{0,12}";
+
+    fn arb_value() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            (LABEL, any::<bool>()).prop_map(|(text, secret)| Value::new(text.as_bytes(), secret)),
+            (vec(any::<u8>(), 0..24), any::<bool>())
+                .prop_map(|(bytes, secret)| Value::bytes(&bytes, secret)),
+            (any::<u64>(), any::<bool>()).prop_map(|(number, secret)| Value::u64(number, secret)),
+            (any::<u64>(), any::<bool>())
+                .prop_map(|(timestamp, secret)| Value::timestamp(timestamp, secret)),
+        ]
+    }
+
+    fn arb_extras() -> impl Strategy<Value = Entries> {
+        proptest::collection::hash_map(EXTRA_KEY, arb_value(), 0..4)
+    }
+
+    fn arb_record() -> impl Strategy<Value = Record> {
+        (
+            LABEL,
+            vec(any::<u8>(), 0..48),
+            any::<u64>(),
+            any::<u64>(),
+            arb_extras(),
+        )
+            .prop_map(|(label, secret, created_at, modified_at, extras)| {
+                let mut record = Record::new(label, secret.into_boxed_slice());
+                record.set_timestamps(created_at, modified_at);
+                for (key, value) in extras {
+                    record.insert_extra(&key, value);
+                }
+                record
+            })
+    }
+
+    fn arb_collection() -> impl Strategy<Value = Collection> {
+        let extras = || proptest::collection::hash_map(EXTRA_KEY, (LABEL, any::<bool>()), 0..3);
+        let leaf =
+            (LABEL, extras(), vec(arb_record(), 0..4)).prop_map(|(label, extras, records)| {
+                let mut collection = Collection::new(label);
+                for (key, (value, secret)) in extras {
+                    collection
+                        .add_extra(&key, value.as_bytes(), secret)
+                        .unwrap();
+                }
+                for record in records {
+                    collection.add_record(record);
+                }
+                collection
+            });
+        leaf.prop_recursive(3, 32, 4, move |inner| {
+            (LABEL, extras(), vec(arb_record(), 0..4), vec(inner, 0..4)).prop_map(
+                |(label, extras, records, children)| {
+                    let mut collection = Collection::new(label);
+                    for (key, (value, secret)) in extras {
+                        collection
+                            .add_extra(&key, value.as_bytes(), secret)
+                            .unwrap();
+                    }
+                    for record in records {
+                        collection.add_record(record);
+                    }
+                    for child in children {
+                        collection.add_child(child);
+                    }
+                    collection
+                },
+            )
+        })
+    }
+
+    fn arb_header() -> impl Strategy<Value = Header> {
+        (
+            vec(any::<u8>(), 0..32),
+            vec(any::<u8>(), 0..16),
+            vec(any::<u8>(), 0..16),
+            arb_extras(),
+        )
+            .prop_map(|(master_key_hash, master_key_salt, key_salt, extras)| {
+                Header::new(
+                    VERSION,
+                    "sha3-256".to_owned(),
+                    "blake3".to_owned(),
+                    "aes256-gcm".to_owned(),
+                    &master_key_hash,
+                    &master_key_salt,
+                    &key_salt,
+                    extras,
+                )
+            })
+    }
+
+    fn vault(header: Header, root: Collection) -> Swd {
+        Swd::from_root(
+            header,
+            root,
+            CipherRegistry::default(),
+            HashFunctionRegistry::default(),
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn roundtrip_preserves_structure(header in arb_header(), root in arb_collection()) {
+            let swd = vault(header, root);
+            let bytes = swd.to_bytes();
+            let parsed = Parser::new().parse(&bytes).unwrap();
+            prop_assert_eq!(parsed.header(), swd.header());
+            prop_assert_eq!(parsed.get_root(), swd.get_root());
+
+            prop_assert_eq!(parsed.to_bytes(), bytes);
+        }
+    }
+
     fn dummy_label() -> Vec<u8> {
         let mut data = vec![];
         data.push(VALUE_STARTER_BYTE);
+        data.push(0);
         let key = "label";
         let len = key.len() as u16;
         for byte in len.to_be_bytes() {
@@ -657,6 +1582,7 @@ mod test {
             data.push(ch as u8);
         }
         data.push(VALUE_STARTER_BYTE);
+        data.push(0);
         let key = "abc";
         let len = key.len() as u16;
         for byte in len.to_be_bytes() {
@@ -671,6 +1597,7 @@ mod test {
     fn dummy_secret() -> Vec<u8> {
         let mut data = vec![];
         data.push(VALUE_STARTER_BYTE);
+        data.push(0);
         let key: &str = "secret";
         let len = key.len() as u16;
         for byte in len.to_be_bytes() {
@@ -680,6 +1607,7 @@ mod test {
             data.push(ch as u8);
         }
         data.push(SECRET_VALUE_STARTER_BYTE);
+        data.push(0);
         let key: &str = "def";
         let len = key.len() as u16;
         for byte in len.to_be_bytes() {
@@ -691,6 +1619,17 @@ mod test {
         data
     }
 
+    fn legacy_value(value: &[u8], is_secret: bool) -> Vec<u8> {
+        let mut data = vec![if is_secret {
+            SECRET_VALUE_STARTER_BYTE
+        } else {
+            VALUE_STARTER_BYTE
+        }];
+        data.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        data.extend_from_slice(value);
+        data
+    }
+
     fn dummy_record() -> Vec<u8> {
         let mut data = vec![RECORD_STARTER_BYTE];
         data.append(&mut dummy_label());
@@ -698,24 +1637,33 @@ mod test {
         data
     }
 
-    fn dummy_collection() -> Vec<u8> {
+    fn wrap_collection(mut body: Vec<u8>) -> Vec<u8> {
         let mut data = vec![COLLECTION_STARTER_BYTE];
-        data.append(&mut dummy_label());
-        data.append(&mut dummy_record());
-        data.append(&mut dummy_record());
+        let len = body.len() as u32;
+        for byte in len.to_be_bytes() {
+            data.push(byte);
+        }
+        data.append(&mut body);
         data.push(COLLECTION_ENDER_BYTE);
         data
     }
 
+    fn dummy_collection() -> Vec<u8> {
+        let mut body = vec![];
+        body.append(&mut dummy_label());
+        body.append(&mut dummy_record());
+        body.append(&mut dummy_record());
+        wrap_collection(body)
+    }
+
     fn dummy_collection_nested() -> Vec<u8> {
-        let mut data = vec![COLLECTION_STARTER_BYTE];
-        data.append(&mut dummy_label());
-        data.append(&mut dummy_collection());
-        data.append(&mut dummy_collection());
-        data.append(&mut dummy_record());
-        data.append(&mut dummy_record());
-        data.append(&mut dummy_record());
-        data.push(COLLECTION_ENDER_BYTE);
-        data
+        let mut body = vec![];
+        body.append(&mut dummy_label());
+        body.append(&mut dummy_collection());
+        body.append(&mut dummy_collection());
+        body.append(&mut dummy_record());
+        body.append(&mut dummy_record());
+        body.append(&mut dummy_record());
+        wrap_collection(body)
     }
 }
@@ -1,8 +1,18 @@
 #![allow(unused)]
+#![allow(clippy::borrowed_box, clippy::too_many_arguments)]
 
 pub mod cipher;
+pub mod editor;
 pub mod entity;
 pub mod error;
 pub mod hash;
 pub mod io;
+pub mod migrate;
+pub mod path;
+pub mod policy;
+pub mod reserved;
+pub mod storage;
+pub mod totp;
 pub mod util;
+
+pub use io::load;
@@ -2,16 +2,21 @@
 
 use std::{
     collections::HashMap,
-    fs::{self, read, File},
-    io::{stdout, Write},
+    fmt::Display,
+    fs::{self, File},
+    io::{stderr, stdin, stdout, Read, Write},
     ops::Index,
     path::Path,
-    thread,
-    time::Duration,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex, MutexGuard, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
 };
 
 use arboard::Clipboard;
-use clap::{Args, Parser as CliParser, Subcommand};
+use clap::{Args, Parser as CliParser, Subcommand, ValueEnum};
 use crossterm::{
     cursor::{MoveTo, RestorePosition, SavePosition},
     event::{self, Event, KeyEventKind},
@@ -19,21 +24,29 @@ use crossterm::{
     style::{
         Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
     },
-    terminal::{Clear, ClearType},
+    terminal::{self, Clear, ClearType},
+};
+use inquire::{
+    error::InquireResult, Confirm, InquireError, Password, PasswordDisplayMode, Select, Text,
 };
-use inquire::{Password, PasswordDisplayMode, Select, Text};
 use rand::RngCore;
 use swords::{
-    cipher::{Cipher, CipherRegistry},
-    entity::{collection::Collection, record::Record, Header, Swd},
-    hash::HashFunctionRegistry,
-    io::parser::Parser,
+    cipher::{BoundCipher, Cipher, CipherFns, CipherRegistry, CipherResult},
+    editor,
+    entity::{
+        collection::{Collection, Removed},
+        portable::PortableCollection,
+        record::{parse_tags, Record, RecordBuilder},
+        value::Value,
+        Header, Swd, VERSION,
+    },
+    hash::{HashFunctionRegistry, PBKDF2_DEFAULT_ITERATIONS},
+    path::Breadcrumb,
+    policy::{validate_kdf_choice, KdfStrength, PasswordPolicy},
+    storage::{home_dir, list_vaults, resolve_vault_path},
+    util::{format_timestamp, strip_trailing_newline, wrap_text},
 };
-
-// FIXME: derive version from Cargo.toml
-// TODO: find a way to fit MAJOR.MINOR.PATCH format
-// into u32
-const VERSION: u32 = 1;
+use zeroize::Zeroizing;
 
 fn main() {
     let Cli { command } = Cli::parse();
@@ -42,20 +55,57 @@ fn main() {
         Commands::New(args) => new(args),
         Commands::Open(args) => {
             let file_path = args.file_path.clone();
-            let result = open(args);
+            let clipboard_timeout = Duration::from_secs(args.clear_clipboard_after);
+            let print_secrets = args.print;
+            let read_only = args.read_only;
+            let backup = !args.no_backup;
+            let idle_timeout = idle_timeout(args.lock_after);
+            let rotate = args.rotate_nonces;
+            let out_path = args
+                .out
+                .or_else(|| (file_path != STDIN_PATH).then_some(file_path));
+            let result = open(args.file_path);
             if let Some(mut swd) = result {
-                swd = interact(swd);
-                save(file_path, swd);
+                swd.set_read_only(read_only || out_path.is_none());
+                if !read_only && out_path.is_none() {
+                    execute!(
+                        stderr(),
+                        SetForegroundColor(Color::Yellow),
+                        Print("Opened from stdin without --out, changes will not be saved\n"),
+                        ResetColor
+                    );
+                }
+                swd = interact(swd, clipboard_timeout, print_secrets, idle_timeout);
+                if rotate && !swd.is_read_only() {
+                    rotate_nonces(&mut swd);
+                }
+                if let (false, Some(out_path)) = (swd.is_read_only(), out_path) {
+                    save(out_path, swd, backup);
+                }
                 execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
             }
         }
+        Commands::Export(args) => export(args),
+        Commands::Import(args) => import(args),
+        Commands::Verify(args) => verify(args),
+        Commands::Get(args) => get(args),
+        Commands::Add(args) => add(args),
+        Commands::Rm(args) => rm(args),
+        Commands::Tree(args) => tree(args),
+        Commands::Diff(args) => diff(args),
+        Commands::List => list(),
     }
 }
 
 fn new(args: NewArgs) {
-    let NewArgs { mut file_path } = args;
-    let name = file_path.clone();
-    file_path.push_str(".swd");
+    let NewArgs {
+        file_path,
+        like,
+        encrypt_labels,
+        algorithms,
+    } = args;
+    let file_path = vault_path(&file_path);
+    let name = vault_name(&file_path);
     if file_exists(&file_path) {
         execute!(
             stdout(),
@@ -66,81 +116,221 @@ fn new(args: NewArgs) {
         return;
     }
 
+    if let Err(err) = algorithms.validate() {
+        fail(&err);
+    }
+
+    let template = match like {
+        Some(template_path) => match open(template_path) {
+            Some(template) => Some(template),
+            None => return,
+        },
+        None => None,
+    };
+
     execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-    let master_key = loop {
-        let result = Password::new("Master key:")
-            .with_help_message("Must consists of at least 8 characters")
-            .with_display_mode(PasswordDisplayMode::Masked)
-            .prompt();
-        match result {
-            Ok(password) if password.len() > 8 => break password,
-            Ok(_) => {
-                execute!(
-                    stdout(),
-                    SetForegroundColor(Color::Red),
-                    Print("Master key is too short!\n"),
-                    ResetColor
-                );
-            }
-            _ => continue,
-        }
+    let (mut swd, _) = match template {
+        Some(template) => create_vault_like(name, template.header()),
+        None => create_vault(name, &algorithms),
+    };
+    if encrypt_labels {
+        swd.set_labels_encrypted(true)
+            .expect("BUG: this should never panic");
+    }
+
+    create_vault_file(&file_path, &swd);
+
+    execute!(
+        stdout(),
+        SetForegroundColor(Color::Green),
+        Print(format!("{} was created", file_path)),
+        ResetColor
+    );
+}
+
+/// Prompts for a master key and the vault settings not preset
+/// in `algorithms`, returning a new empty unlocked vault along
+/// with its master key
+fn create_vault(name: String, algorithms: &AlgorithmArgs) -> (Swd, Zeroizing<String>) {
+    let Some(master_key) = prompt_new_master_key("Master key:", &PasswordPolicy::default()) else {
+        fail(CANCELLED);
     };
 
     let cipher_registry = CipherRegistry::default();
     let hash_registry = HashFunctionRegistry::default();
 
-    let master_key_hash_function = loop {
-        let result =
-            Select::new("Choose master key hash function", hash_registry.get_names()).prompt();
-        match result {
-            Ok(hasher) => break hasher,
-            _ => continue,
-        }
+    let Some(master_key_hash_function) = algorithms
+        .master_kdf
+        .as_ref()
+        .or_else(|| prompt_select("Choose master key hash function", hash_registry.get_names()))
+    else {
+        fail(CANCELLED);
+    };
+    let Some(key_hash_function) = algorithms
+        .key_kdf
+        .as_ref()
+        .or_else(|| prompt_select("Choose key hash function", hash_registry.get_names()))
+    else {
+        fail(CANCELLED);
+    };
+    let Some(key_cipher) = algorithms
+        .cipher
+        .as_ref()
+        .or_else(|| prompt_select("Choose key cipher", cipher_registry.get_names()))
+    else {
+        fail(CANCELLED);
     };
 
-    let key_hash_function = loop {
-        let result = Select::new("Choose key hash function", hash_registry.get_names()).prompt();
-        match result {
-            Ok(hasher) => break hasher,
-            _ => continue,
-        }
+    warn_fast_kdfs(master_key_hash_function, key_hash_function);
+
+    let mut swd = Swd::create_with_hash_functions(
+        master_key.as_bytes(),
+        key_cipher,
+        master_key_hash_function,
+        key_hash_function,
+    );
+    swd.get_root_mut().set_label(&name);
+    (swd, master_key)
+}
+
+/// Prompts for a master key only, returning a new empty unlocked vault
+/// with the settings of `template` along with its master key
+fn create_vault_like(name: String, template: &Header) -> (Swd, Zeroizing<String>) {
+    let cipher_registry = CipherRegistry::default();
+    let hash_registry = HashFunctionRegistry::default();
+    if cipher_registry
+        .validate_name(template.key_cipher())
+        .is_err()
+        || hash_registry
+            .validate_name(template.master_key_hash_fn())
+            .is_err()
+        || hash_registry.validate_name(template.key_hash_fn()).is_err()
+    {
+        fail("The template vault uses a cipher or hash function that is not available");
+    }
+
+    let Some(master_key) = prompt_new_master_key("Master key:", &PasswordPolicy::default()) else {
+        fail(CANCELLED);
     };
+    warn_fast_kdfs(template.master_key_hash_fn(), template.key_hash_fn());
+
+    let mut swd = Swd::create_like(master_key.as_bytes(), template);
+    swd.get_root_mut().set_label(&name);
+    (swd, master_key)
+}
+
+/// Warns about each of the master key hash and the key derivation
+/// that use a plain hash function instead of a key derivation function
+fn warn_fast_kdfs(master_key_hash_fn: &str, key_hash_fn: &str) {
+    for (purpose, name) in [
+        ("master key hash", master_key_hash_fn),
+        ("key derivation", key_hash_fn),
+    ] {
+        if validate_kdf_choice(name) != KdfStrength::FastHash {
+            continue;
+        }
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Yellow),
+            Print(format!(
+                "Warning: the {} uses {}, a fast hash that makes guessing the master key cheap. \
+                 Prefer a key derivation function such as PBKDF2 or Argon2.\n",
+                purpose, name
+            )),
+            ResetColor
+        );
+    }
+}
 
-    let key_cipher = loop {
-        let result = Select::new("Choose key cipher", cipher_registry.get_names()).prompt();
-        match result {
-            Ok(cipher) => break cipher,
-            _ => continue,
+/// Prompts for a new master key until one satisfies `policy`,
+/// listing every requirement a rejected key misses, or until
+/// the prompt is canceled
+fn prompt_new_master_key(message: &str, policy: &PasswordPolicy) -> Option<Zeroizing<String>> {
+    let help_message = policy.describe();
+    loop {
+        let (message, help_message) = (message.to_owned(), help_message.clone());
+        let password = answer(move || {
+            Password::new(&message)
+                .with_help_message(&help_message)
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .prompt()
+        })
+        .map(Zeroizing::new)?;
+        match policy.validate(&password) {
+            Ok(()) => break Some(password),
+            Err(violations) => {
+                execute!(
+                    stdout(),
+                    SetForegroundColor(Color::Red),
+                    Print("Master key was rejected:\n"),
+                );
+                for violation in violations {
+                    execute!(stdout(), Print(format!("  - it {}\n", violation)));
+                }
+                execute!(stdout(), ResetColor);
+            }
         }
+    }
+}
+
+fn import(args: ImportArgs) {
+    let ImportArgs {
+        source_path,
+        file_path,
+        format,
+    } = args;
+    let file_path = vault_path(&file_path);
+    let name = vault_name(&file_path);
+    if file_exists(&file_path) {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Red),
+            Print("File already exist"),
+            ResetColor
+        );
+        return;
+    }
+
+    let result = fs::read_to_string(&source_path);
+    if let Err(err) = result {
+        println!("{}", err);
+        return;
+    }
+    let content = result.unwrap();
+    let result = match format {
+        ImportFormat::Json => PortableCollection::from_json(&content),
+        ImportFormat::Csv => PortableCollection::from_csv(&content),
     };
+    if let Err(err) = result {
+        println!("{}", err);
+        return;
+    }
+    let portable = result.unwrap();
 
-    let mut rng = rand::thread_rng();
-    let mut master_key_salt = [0; 16];
-    let mut key_salt = [0; 16];
-    rng.fill_bytes(&mut master_key_salt);
-    rng.fill_bytes(&mut key_salt);
+    execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-    let mut salted_master_key = master_key.as_bytes().to_vec();
-    salted_master_key.extend_from_slice(&master_key_salt);
-    let hash = hash_registry.get_function(&master_key_hash_function);
-    let master_key_hash = hash(&salted_master_key);
+    let (mut swd, _) = create_vault(name, &AlgorithmArgs::default());
 
-    let header = Header::new(
-        VERSION,
-        master_key_hash_function.to_owned(),
-        key_hash_function.to_owned(),
-        key_cipher.to_owned(),
-        &master_key_hash,
-        &master_key_salt,
-        &key_salt,
-        HashMap::new(),
-    );
+    let cipher_registry = CipherRegistry::default();
+    let encrypt = cipher_registry.get_encryptor(swd.header().key_cipher());
+    let nonce_size = cipher_registry.get_nonce_size(swd.header().key_cipher());
+    let key = Zeroizing::new(unlocked_key(&swd).to_vec());
+    if let Err(err) = portable.import_into(swd.get_root_mut(), encrypt, &key, nonce_size) {
+        println!("{}", err);
+        return;
+    }
 
-    let swd = Swd::new(header, name, cipher_registry, hash_registry);
+    for duplicate in swd.get_root().duplicate_labels() {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Yellow),
+            Print(format!("Duplicate label: {}\n", duplicate.join("/"))),
+            ResetColor
+        );
+    }
 
-    let mut file = File::create(file_path.clone()).expect("error creating file");
-    file.write_all(&swd.to_bytes());
+    create_vault_file(&file_path, &swd);
 
     execute!(
         stdout(),
@@ -150,131 +340,829 @@ fn new(args: NewArgs) {
     );
 }
 
+/// Vault path standing for stdin
+const STDIN_PATH: &str = "-";
+
 // FIXME: return Result instead
-fn open(args: OpenArgs) -> Option<Swd> {
-    let OpenArgs { mut file_path } = args;
-    if !file_path.ends_with(".swd") {
-        file_path.push_str(".swd");
+/// Reads the vault at `file_path`, or from stdin when it is `-`
+fn open(file_path: String) -> Option<Swd> {
+    let result = if file_path == STDIN_PATH {
+        swords::io::load_from(stdin().lock())
+    } else {
+        let file_path = vault_path(&file_path);
+        if !file_exists(&file_path) {
+            execute!(
+                stdout(),
+                SetForegroundColor(Color::Red),
+                Print("File does not exist"),
+                ResetColor
+            );
+            return None;
+        }
+        swords::load(&file_path)
+    };
+
+    match result {
+        Ok(swd) => Some(swd),
+        Err(err) => {
+            println!("{}", err);
+            None
+        }
     }
+}
+
+fn export(args: ExportArgs) {
+    let ExportArgs {
+        file_path,
+        path,
+        format,
+    } = args;
+    let Some(mut swd) = open(file_path) else {
+        return;
+    };
+    prompt_master_key(&mut swd);
+
+    let segments: Vec<&str> = path
+        .iter()
+        .flat_map(|path| path.split('/'))
+        .filter(|s| !s.is_empty())
+        .collect();
+    let Some(collection) = swd.get_collection_by_path(&segments) else {
+        fail(&format!("No collection at {}", segments.join("/")));
+    };
+
+    let key = unlocked_key(&swd);
+    let result = match format {
+        ExportFormat::Json => swd
+            .to_portable(collection, key)
+            .map(|portable| portable.to_json()),
+    };
+
+    match result {
+        Ok(exported) => println!("{}", exported),
+        Err(err) => {
+            execute!(
+                stderr(),
+                SetForegroundColor(Color::Red),
+                Print(format!("Failed to export vault: {}", err)),
+                ResetColor
+            );
+        }
+    }
+}
+
+fn verify(args: VerifyArgs) {
+    let VerifyArgs { file_path } = args;
+    let Some(mut swd) = open(file_path) else {
+        return;
+    };
+    prompt_master_key(&mut swd);
+
+    let (_, decrypt) = swd.get_key_cipher();
+    let key = unlocked_key(&swd);
+    let failures = swd.verify(decrypt, key);
+    let total = swd.get_root().records_with_paths().len();
+    // Failures of the same record are adjacent
+    let mut corrupt: Vec<(&Vec<String>, &String)> = failures
+        .iter()
+        .map(|failure| (&failure.path, &failure.label))
+        .collect();
+    corrupt.dedup();
+
+    execute!(
+        stdout(),
+        SetForegroundColor(Color::Green),
+        Print(format!("{} healthy", total - corrupt.len())),
+        ResetColor,
+        Print(", "),
+        SetForegroundColor(if corrupt.is_empty() {
+            Color::Green
+        } else {
+            Color::Red
+        }),
+        Print(format!("{} corrupt\n", corrupt.len())),
+        ResetColor,
+    );
 
-    if !file_exists(&file_path) {
+    for failure in failures.iter() {
         execute!(
             stdout(),
             SetForegroundColor(Color::Red),
-            Print("File does not exist"),
-            ResetColor
+            Print(format!("{}\n", failure)),
+            ResetColor,
         );
-        return None;
     }
+}
+
+/// Environment variable `get` reads the master key from
+const MASTER_KEY_ENV: &str = "SWORDS_MASTER_KEY";
+/// Environment variable overriding the default of `--lock-after`
+const IDLE_TIMEOUT_ENV: &str = "SWORDS_IDLE_TIMEOUT";
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 5 * 60;
+
+/// Idle timeout given by `--lock-after`, SWORDS_IDLE_TIMEOUT or the
+/// default, in that order, where 0 seconds never locks
+fn idle_timeout(lock_after: Option<u64>) -> Option<Duration> {
+    let seconds = match (lock_after, std::env::var(IDLE_TIMEOUT_ENV)) {
+        (Some(seconds), _) => seconds,
+        (None, Ok(seconds)) => seconds
+            .trim()
+            .parse()
+            .unwrap_or_else(|_| fail(&format!("{} must be a number of seconds", IDLE_TIMEOUT_ENV))),
+        (None, Err(_)) => DEFAULT_IDLE_TIMEOUT_SECS,
+    };
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+fn get(args: GetArgs) {
+    let GetArgs { file_path, path } = args;
+    let Some(mut swd) = open(file_path) else {
+        std::process::exit(1);
+    };
+
+    unlock_from_env_or_prompt(&mut swd);
+
+    let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let Some(label) = segments.pop() else {
+        fail("No record path given");
+    };
+    let record = swd
+        .get_collection_by_path(&segments)
+        .and_then(|collection| {
+            collection
+                .records()
+                .iter()
+                .find(|record| record.label() == label)
+        });
+    let Some(record) = record else {
+        fail(&format!("No record at {}", path));
+    };
+
+    let bound = match swd.bind_record_cipher(record, unlocked_key(&swd)) {
+        Ok(bound) => bound,
+        Err(err) => fail(&format!("Failed to decrypt {}: {}", path, err)),
+    };
+    let (decrypt, key) = (bound.decrypt_fn, &bound.key);
+    if record.is_binary() {
+        let written = record
+            .decrypt_secret_bytes(decrypt, key)
+            .map_err(|err| format!("Failed to decrypt {}: {}", path, err))
+            .and_then(|secret| {
+                stdout()
+                    .write_all(&secret)
+                    .map_err(|err| format!("Failed to write {}: {}", path, err))
+            });
+        if let Err(message) = written {
+            fail(&message);
+        }
+        return;
+    }
+    match record.decrypt_secret(decrypt, key) {
+        Ok(secret) => println!("{}", *secret),
+        Err(err) => fail(&format!("Failed to decrypt {}: {}", path, err)),
+    }
+}
 
-    let result = read(file_path);
+fn add(args: AddArgs) {
+    let AddArgs {
+        file_path,
+        path,
+        label,
+        binary,
+        no_backup,
+    } = args;
+    if file_path == STDIN_PATH {
+        fail("The secret is read from stdin, the vault cannot be");
+    }
+    let Some(mut swd) = open(file_path.clone()) else {
+        std::process::exit(1);
+    };
+    if label.is_empty() {
+        fail("The record label cannot be empty");
+    }
+
+    unlock_from_env_or_prompt(&mut swd);
+
+    let mut input = Zeroizing::new(vec![]);
+    if let Err(err) = stdin().read_to_end(&mut input) {
+        fail(&format!("Failed to read the secret from stdin: {}", err));
+    }
+    let secret = if binary {
+        &input[..]
+    } else {
+        match std::str::from_utf8(&input) {
+            Ok(text) => strip_trailing_newline(text).as_bytes(),
+            Err(_) => fail("The secret is not valid UTF-8, use --binary to store it as is"),
+        }
+    };
+
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let cipher = swd.cipher_registry().get(swd.header().key_cipher());
+    let key = unlocked_key(&swd);
+    let record = match RecordBuilder::new()
+        .label(&label)
+        .secret_plaintext(secret)
+        .binary(binary)
+        .encrypt_with(cipher, key)
+    {
+        Ok(record) => record,
+        Err(err) => fail(&format!("Failed to encrypt the secret: {}", err)),
+    };
+    if let Err(err) = Value::try_new(record.secret(), true) {
+        fail(&format!("The secret cannot be stored: {}", err));
+    }
+
+    let collection = swd.get_root_mut().get_or_create_by_path(&segments);
+    if collection.has_record_label(&label) {
+        fail(&format!(
+            "A record labeled {} already exists at {}/",
+            label,
+            segments.join("/")
+        ));
+    }
+    collection.add_record(record);
+
+    let file_path = vault_path(&file_path);
+    let result = if no_backup {
+        swd.save_to_path_without_backup(&file_path)
+    } else {
+        swd.save_to_path(&file_path)
+    };
     if let Err(err) = result {
-        println!("{}", err);
-        return None;
+        fail(&format!("Failed to save the vault: {}", err));
     }
-    let mut parser = Parser::new();
-    let result = parser.parse(&result.unwrap());
-    if let Err(parse_error) = result {
-        println!("{:?}", parse_error);
-        return None;
+}
+
+fn rm(args: RmArgs) {
+    let RmArgs {
+        file_path,
+        path,
+        force,
+        no_backup,
+    } = args;
+    if file_path == STDIN_PATH {
+        fail("A vault read from stdin cannot be saved, rm needs a file");
+    }
+    let Some(mut swd) = open(file_path.clone()) else {
+        std::process::exit(1);
+    };
+    unlock_from_env_or_prompt(&mut swd);
+
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let question = match swd.remove_by_path(&segments) {
+        Ok(Removed::Record(_)) => format!("Remove the record {}?", path),
+        Ok(Removed::Collection(collection)) => format!(
+            "Remove the collection {} along with the {} records in it?",
+            path,
+            collection.total_record_count()
+        ),
+        Err(err) => fail(&format!("Cannot remove {}: {}", path, err)),
+    };
+    if !force {
+        let confirmed = answer(move || Confirm::new(&question).with_default(false).prompt());
+        if confirmed != Some(true) {
+            fail(CANCELLED);
+        }
     }
 
-    Some(result.unwrap())
+    let file_path = vault_path(&file_path);
+    let result = if no_backup {
+        swd.save_to_path_without_backup(&file_path)
+    } else {
+        swd.save_to_path(&file_path)
+    };
+    if let Err(err) = result {
+        fail(&format!("Failed to save the vault: {}", err));
+    }
+}
+
+fn tree(args: TreeArgs) {
+    let TreeArgs { file_path, depth } = args;
+    let Some(mut swd) = open(file_path) else {
+        std::process::exit(1);
+    };
+    unlock_from_env_or_prompt(&mut swd);
+
+    for line in swd.get_root().tree_lines(depth) {
+        if !line.is_collection {
+            execute!(stdout(), Print(format!("{}\n", line)));
+            continue;
+        }
+        execute!(
+            stdout(),
+            Print(&line.prefix),
+            SetAttribute(Attribute::Bold),
+            SetForegroundColor(Color::Blue),
+            Print(format!("{}/\n", line.label)),
+            SetAttribute(Attribute::Reset),
+            ResetColor,
+        );
+    }
 }
 
-fn save(mut file_path: String, swd: Swd) {
-    if !file_path.ends_with(".swd") {
-        file_path.push_str(".swd");
+fn diff(args: DiffArgs) {
+    let DiffArgs {
+        old_file_path,
+        new_file_path,
+    } = args;
+    let mut vaults = vec![];
+    for file_path in [old_file_path, new_file_path] {
+        let Some(mut swd) = open(file_path.clone()) else {
+            std::process::exit(1);
+        };
+        execute!(stderr(), Print(format!("Unlocking {}\n", file_path)));
+        prompt_master_key(&mut swd);
+        vaults.push(swd);
     }
 
-    if !file_exists(&file_path) {
-        File::create(&file_path);
+    let diff = vaults[0].diff(&vaults[1]);
+    if diff.is_empty() {
+        println!("No changes");
+        return;
+    }
+
+    for path in diff.removed.iter() {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Red),
+            Print(format!("- {}\n", path.join("/"))),
+            ResetColor
+        );
     }
-    fs::write(file_path, &swd.to_bytes());
+    for path in diff.added.iter() {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Green),
+            Print(format!("+ {}\n", path.join("/"))),
+            ResetColor
+        );
+    }
+    for record in diff.changed.iter() {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Yellow),
+            Print(format!("~ {}\n", record.path.join("/"))),
+            ResetColor
+        );
+        for change in record.changes.iter() {
+            println!("    {}", change);
+        }
+    }
+}
+
+/// Unlocks `swd` with the master key in SWORDS_MASTER_KEY if it is set,
+/// exiting when it is wrong, or prompts for the master key otherwise
+fn unlock_from_env_or_prompt(swd: &mut Swd) {
+    let Ok(master_key) = std::env::var(MASTER_KEY_ENV).map(Zeroizing::new) else {
+        prompt_master_key(swd);
+        return;
+    };
+    if !swd.unlock(master_key.as_bytes()) {
+        fail(&format!("{} does not unlock the vault", MASTER_KEY_ENV));
+    }
+    if let Err(err) = swd.verify_integrity() {
+        execute!(
+            stderr(),
+            SetForegroundColor(Color::Yellow),
+            Print(format!("Warning: {}\n", err)),
+            ResetColor
+        );
+    }
+}
+
+/// Key of an unlocked vault, exiting if `swd` is still locked
+fn unlocked_key(swd: &Swd) -> &[u8] {
+    match swd.key() {
+        Some(key) => key,
+        None => fail("The vault is locked"),
+    }
+}
+
+/// Path of the vault file `name` refers to, see `resolve_vault_path`
+fn vault_path(name: &str) -> String {
+    resolve_vault_path(name).to_string_lossy().into_owned()
+}
+
+/// Name of the vault stored at `file_path`, used as its root label
+fn vault_name(file_path: &str) -> String {
+    Path::new(file_path)
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Writes a newly created vault, creating its directory if needed
+fn create_vault_file(file_path: &str, swd: &Swd) {
+    if let Some(dir) = Path::new(file_path).parent() {
+        fs::create_dir_all(dir).expect("error creating the vault directory");
+    }
+    swd.save_to_path_without_backup(file_path)
+        .expect("error creating file");
 }
 
-const ROOT_MENU: [&str; 5] = [
+fn list() {
+    let home = home_dir();
+    let names = match list_vaults(&home) {
+        Ok(names) => names,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(err) => fail(&format!("Failed to list {}: {}", home.display(), err)),
+    };
+    if names.is_empty() {
+        println!("No vaults in {}", home.display());
+    }
+    for name in names {
+        println!("{}", name);
+    }
+}
+
+/// Reports `message` on stderr and exits with a failure status
+fn fail(message: &str) -> ! {
+    execute!(
+        stderr(),
+        SetForegroundColor(Color::Red),
+        Print(format!("{}\n", message)),
+        ResetColor
+    );
+    std::process::exit(1);
+}
+
+/// Re-encrypts the secrets of `swd` under fresh nonces, leaving
+/// them as they are when the vault was locked on exit
+fn rotate_nonces(swd: &mut Swd) {
+    let Some(key) = swd.key().map(<[u8]>::to_vec) else {
+        println!("The vault is locked, nonces were not rotated");
+        return;
+    };
+    let cipher = swd.header().key_cipher().to_owned();
+    if let Err(err) = swd.rotate_nonces(&cipher, &key) {
+        println!("Failed to rotate nonces, saving them as they were: {}", err);
+    }
+}
+
+/// Saves `swd` to `file_path`, keeping the previous file as a backup
+/// unless `backup` is unset
+fn save(file_path: String, swd: Swd, backup: bool) {
+    let file_path = vault_path(&file_path);
+
+    let result = if backup {
+        swd.save_to_path(&file_path)
+    } else {
+        swd.save_to_path_without_backup(&file_path)
+    };
+    if let Err(err) = result {
+        execute!(
+            stderr(),
+            SetForegroundColor(Color::Red),
+            Print(format!("Failed to save {}: {}\n", file_path, err)),
+            ResetColor
+        );
+    }
+}
+
+const ROOT_MENU: [&str; 13] = [
     "Collections",
     "Records",
+    "Search",
+    "Filter by Tag",
+    "Favorites",
     "New Collection",
     "New Record",
+    "Clean Up Empty Collections",
+    "Edit Description",
+    "Vault Info",
+    "Change Master Key",
+    "Lock",
     "Exit",
 ];
 
-const COLLECTION_MENU: [&str; 5] = [
+const COLLECTION_MENU: [&str; 7] = [
     "Collections",
     "Records",
     "New Collection",
     "New Record",
+    "Move Up",
+    "Move Down",
     "Back",
 ];
 
-const RECORD_MENU: [&str; 2] = ["Copy Secret to Clipboard", "Back"];
+/// Menu options that edit the vault, hidden in read-only mode
+const EDIT_OPTIONS: [&str; 17] = [
+    "New Collection",
+    "New Record",
+    "Clean Up Empty Collections",
+    "Edit Description",
+    "Change Master Key",
+    "Edit Secret",
+    "Change Cipher",
+    "Edit Notes",
+    "Edit Tags",
+    "Archive",
+    "Unarchive",
+    "Duplicate",
+    "Move",
+    "Move Up",
+    "Move Down",
+    "Add to Favorites",
+    "Remove from Favorites",
+];
+
+/// Menu options reordering entries, hidden while they are sorted by label
+const REORDER_OPTIONS: [&str; 2] = ["Move Up", "Move Down"];
+
+const RECORD_MENU: [&str; 13] = [
+    "Copy Secret to Clipboard",
+    "Print Secret",
+    "Edit Secret",
+    "Change Cipher",
+    "Add to Favorites",
+    "Edit Notes",
+    "Edit Tags",
+    "Archive",
+    "Duplicate",
+    "Move",
+    "Move Up",
+    "Move Down",
+    "Back",
+];
 
 struct CliState<'a> {
-    path: Vec<String>,
-    cipher: Cipher<'a>,
-    key: Vec<u8>,
+    path: Breadcrumb,
+    /// Child indices from the root to the current collection
+    location: Vec<usize>,
+    /// Record waiting to be moved once the menus unwind to the root,
+    /// as the location of its collection and its index there
+    pending_move: Option<(Vec<usize>, usize)>,
+    cipher: CipherFns<'a>,
+    key_cipher: &'a dyn Cipher,
+    key_cipher_name: String,
+    /// Ciphers records may be encrypted with besides the key cipher
+    ciphers: &'a CipherRegistry,
+    /// Size of the nonces the cipher expects
+    nonce_size: usize,
+    key: Zeroizing<Vec<u8>>,
+    clipboard_timeout: Duration,
+    /// Print secrets to stdout instead of copying them to the clipboard
+    print_secrets: bool,
+    /// List collections and records sorted by label
+    sort_by_label: bool,
+    /// List archived records along with the others
+    show_archived: bool,
+    /// Working copy of the vault favorites, written back from the root menu
+    favorites: Vec<Vec<String>>,
+    read_only: bool,
 }
 
-fn interact(mut swd: Swd) -> Swd {
+fn interact(
+    mut swd: Swd,
+    clipboard_timeout: Duration,
+    print_secrets: bool,
+    idle_timeout: Option<Duration>,
+) -> Swd {
+    set_idle_timeout(idle_timeout);
     authenticate(&mut swd);
+    if !swd.is_read_only() {
+        swd.set_last_opened(SystemTime::now())
+            .expect("BUG: this should never panic");
+    }
 
     let cipher_name = swd.header().key_cipher();
     let cipher_registry = CipherRegistry::default();
     let encrypt = cipher_registry.get_encryptor(cipher_name);
     let decrypt = cipher_registry.get_decryptor(cipher_name);
 
-    let key = swd.header().get_key().unwrap().clone();
+    if !swd.is_unlocked() {
+        fail("The vault is locked");
+    }
+    let key = Zeroizing::new(unlocked_key(&swd).to_vec());
 
     let mut state = CliState {
-        path: vec![swd.get_root().label().clone()],
+        path: Breadcrumb::from(vec![swd.get_root().label().clone()]),
+        location: vec![],
+        pending_move: None,
         key,
         cipher: (encrypt, decrypt),
+        key_cipher: cipher_registry.get(cipher_name),
+        key_cipher_name: cipher_name.to_owned(),
+        ciphers: &cipher_registry,
+        nonce_size: cipher_registry.get_nonce_size(cipher_name),
+        clipboard_timeout,
+        print_secrets,
+        sort_by_label: false,
+        show_archived: false,
+        favorites: swd.favorites(),
+        read_only: swd.is_read_only(),
     };
 
     loop {
         execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-        let menu = Select::new(swd.get_root().label(), ROOT_MENU.to_vec())
-            .prompt()
-            .expect("there was an error while selecting");
+        let title = format!(
+            "{} {}",
+            swd.description().unwrap_or(swd.get_root().label()),
+            count_summary(swd.get_root())
+        );
+        // Leaving the root menu with Esc or Ctrl-C saves and exits,
+        // while menus unwinding from an idle session lock the vault
+        let menu = match prompt_select(&title, menu_options(&ROOT_MENU, &state)) {
+            _ if idle_timed_out() => "Lock",
+            Some(menu) => menu,
+            None => "Exit",
+        };
 
         match menu {
             "Collections" => show_collections(swd.get_root_mut(), &mut state),
             "Records" => show_records(swd.get_root_mut(), &mut state),
+            "Search" => search_records(swd.get_root_mut(), &mut state),
+            "Filter by Tag" => filter_by_tag(swd.get_root_mut(), &mut state),
+            "Favorites" => show_favorites(swd.get_root_mut(), &mut state),
             "New Collection" => add_new_collection(swd.get_root_mut(), &mut state),
             "New Record" => add_new_record(swd.get_root_mut(), &mut state),
+            "Clean Up Empty Collections" => {
+                let removed = swd.get_root_mut().prune_empty();
+                execute!(
+                    stdout(),
+                    SetForegroundColor(Color::Green),
+                    Print(format!("Removed {}\n", plural(removed, "empty collection"))),
+                    ResetColor,
+                    Print("Press any key to continue..."),
+                );
+                pause();
+            }
+            "Edit Description" => {
+                let description = swd.description().unwrap_or_default().to_owned();
+                let Some(description) = answer(move || {
+                    Text::new("Description:")
+                        .with_initial_value(&description)
+                        .with_help_message("Leave blank to show the vault name instead")
+                        .prompt()
+                }) else {
+                    continue;
+                };
+                swd.set_description(&description)
+                    .expect("BUG: this should never panic");
+            }
+            "Vault Info" => show_vault_info(&swd),
+            "Change Master Key" => change_master_key(&mut swd, &mut state),
+            "Lock" => {
+                swd.lock();
+                state.key = Zeroizing::new(vec![]);
+                authenticate(&mut swd);
+                state.key = Zeroizing::new(unlocked_key(&swd).to_vec());
+            }
             "Exit" => {
                 return swd;
             }
             _ => unreachable!(),
         }
+
+        if let Some(address) = state.pending_move.take() {
+            move_record(swd.get_root_mut(), address, &mut state);
+        }
+        if !state.read_only {
+            sync_favorites(&mut swd, &state);
+        }
     }
 }
 
-fn interact_collection(collection: &mut Collection, state: &mut CliState) {
-    state.path.push(collection.label().to_owned());
-    let path = state.path.join("/");
+/// Prints the format version, algorithms and header extras of the vault
+fn show_vault_info(swd: &Swd) {
+    let header = swd.header();
+    execute!(
+        stdout(),
+        Clear(ClearType::All),
+        MoveTo(0, 0),
+        SetAttribute(Attribute::Bold),
+        Print("Vault info\n"),
+        SetAttribute(Attribute::Reset),
+        Print(format!("Version:                  {}\n", header.version())),
+        Print(format!(
+            "Cipher:                   {}\n",
+            header.key_cipher()
+        )),
+        Print(format!(
+            "Master key hash function: {}\n",
+            header.master_key_hash_fn()
+        )),
+        Print(format!(
+            "Key hash function:        {}\n",
+            header.key_hash_fn()
+        )),
+        Print(format!(
+            "Master key salt:          {} bytes\n",
+            header.master_key_salt().len()
+        )),
+        Print(format!(
+            "Key salt:                 {} bytes\n",
+            header.key_salt().len()
+        )),
+    );
+
+    let extras: Vec<(&String, &Value)> = header.extras_iter().collect();
+    if !extras.is_empty() {
+        execute!(stdout(), Print("Extras:\n"));
+    }
+    for (key, value) in extras {
+        execute!(
+            stdout(),
+            Print(format!("  {}: {}\n", key, value.describe()))
+        );
+    }
+
+    execute!(stdout(), Print("Press any key to continue..."));
+    pause();
+}
+
+/// Writes the favorites added or removed in the menus back to the vault
+fn sync_favorites(swd: &mut Swd, state: &CliState) {
+    for favorite in swd.favorites() {
+        if !state.favorites.contains(&favorite) {
+            swd.remove_favorite(&favorite)
+                .expect("BUG: this should never panic");
+        }
+    }
+    for favorite in state.favorites.iter() {
+        swd.add_favorite(favorite)
+            .expect("BUG: this should never panic");
+    }
+}
+
+/// Number of records below `collection` and of its direct
+/// child collections, e.g. "(12 records, 3 collections)"
+fn count_summary(collection: &Collection) -> String {
+    format!(
+        "({}, {})",
+        plural(collection.total_record_count(), "record"),
+        plural(collection.child_count(), "collection")
+    )
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+/// Menu options without the editing ones in read-only mode
+/// and without the reordering ones while sorted by label
+fn menu_options<'a>(menu: &[&'a str], state: &CliState) -> Vec<&'a str> {
+    menu.iter()
+        .copied()
+        .filter(|option| !state.read_only || !EDIT_OPTIONS.contains(option))
+        .filter(|option| !state.sort_by_label || !REORDER_OPTIONS.contains(option))
+        .collect()
+}
+
+/// Index one place up or down from `index`, past the
+/// ends for the first entry moving up or the last moving down
+fn reorder_target(index: usize, up: bool) -> usize {
+    if up {
+        index.wrapping_sub(1)
+    } else {
+        index + 1
+    }
+}
+
+enum CollectionAction {
+    Back,
+    MoveUp,
+    MoveDown,
+}
+
+fn interact_collection(collection: &mut Collection, state: &mut CliState) -> CollectionAction {
+    state.path.push(collection.label());
 
     loop {
         execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-        let menu = Select::new(&path, COLLECTION_MENU.to_vec())
-            .prompt()
-            .expect("there was an error while selecting");
+        let title = format!("{} {}", state.path, count_summary(collection));
+        let menu = prompt_select(&title, menu_options(&COLLECTION_MENU, state)).unwrap_or("Back");
 
         match menu {
             "Collections" => show_collections(collection, state),
             "Records" => show_records(collection, state),
             "New Collection" => add_new_collection(collection, state),
             "New Record" => add_new_record(collection, state),
+            "Move Up" => {
+                state.path.pop();
+                return CollectionAction::MoveUp;
+            }
+            "Move Down" => {
+                state.path.pop();
+                return CollectionAction::MoveDown;
+            }
             "Back" => {
                 state.path.pop();
-                return;
+                return CollectionAction::Back;
             }
             _ => unreachable!(),
         }
+
+        if state.pending_move.is_some() {
+            state.path.pop();
+            return CollectionAction::Back;
+        }
     }
 }
 
@@ -282,30 +1170,54 @@ fn show_collections(collection: &mut Collection, state: &mut CliState) {
     loop {
         execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-        let mut children: Vec<String> = collection
+        let labels: Vec<&String> = collection
             .children()
+            .iter()
+            .map(Collection::label)
+            .collect();
+        let order = display_order(&labels, state.sort_by_label);
+        let mut children: Vec<String> = order
             .iter()
             .enumerate()
-            .map(|(index, child)| format!("[{}] {}", index + 1, child.label()))
+            .map(|(position, &index)| format!("[{}] {}", position + 1, labels[index]))
             .collect();
+        children.push(sort_toggle_option(state.sort_by_label));
         children.push("[<] Back".to_owned());
 
-        let choice = Select::new("Collections", children.clone())
-            .prompt()
-            .expect("there was an error while selecting");
+        let Some(index) = prompt_select_index("Collections", children) else {
+            return;
+        };
 
-        if &choice == "[<] Back" {
+        if index == order.len() {
+            state.sort_by_label = !state.sort_by_label;
+            continue;
+        }
+        let Some(child) = order
+            .get(index)
+            .and_then(|&index| collection.get_child_mut(index))
+        else {
             return;
+        };
+        let index = order[index];
+
+        state.location.push(index);
+        let action = interact_collection(child, state);
+        state.location.pop();
+
+        // Moving past either end leaves the order unchanged
+        match action {
+            CollectionAction::Back => {}
+            CollectionAction::MoveUp => {
+                let _ = collection.move_child(index, reorder_target(index, true));
+            }
+            CollectionAction::MoveDown => {
+                let _ = collection.move_child(index, reorder_target(index, false));
+            }
         }
 
-        let index = children
-            .iter()
-            .position(|child| *child == choice)
-            .expect("BUG: this should never panic");
-
-        let child = collection.get_child_mut(index).unwrap();
-
-        interact_collection(child, state);
+        if state.pending_move.is_some() {
+            return;
+        }
     }
 }
 
@@ -313,97 +1225,830 @@ fn show_records(collection: &mut Collection, state: &mut CliState) {
     loop {
         execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
 
-        let mut records: Vec<String> = collection
-            .records()
+        let listed = collection.listed_record_indices(state.show_archived);
+        let has_archived = collection.records().iter().any(Record::is_archived);
+        let labels: Vec<&String> = listed
+            .iter()
+            .map(|&index| collection.records()[index].label())
+            .collect();
+        // Indices of the records in display order
+        let order: Vec<usize> = display_order(&labels, state.sort_by_label)
+            .into_iter()
+            .map(|position| listed[position])
+            .collect();
+        let mut records: Vec<String> = order
             .iter()
             .enumerate()
-            .map(|(index, child)| format!("[{}] {}", index + 1, child.label()))
+            .map(|(position, &index)| {
+                let record = &collection.records()[index];
+                let archived = if record.is_archived() {
+                    " (archived)"
+                } else {
+                    ""
+                };
+                format!("[{}] {}{}", position + 1, record.label(), archived)
+            })
             .collect();
+        records.push(sort_toggle_option(state.sort_by_label));
+        if has_archived {
+            records.push(archived_toggle_option(state.show_archived));
+        }
         records.push("[<] Back".to_owned());
 
-        let choice = Select::new("Records", records.clone())
+        let Some(index) = prompt_select_index("Records", records) else {
+            return;
+        };
+
+        if index == order.len() {
+            state.sort_by_label = !state.sort_by_label;
+            continue;
+        }
+        if has_archived && index == order.len() + 1 {
+            state.show_archived = !state.show_archived;
+            continue;
+        }
+        let Some(record) = order
+            .get(index)
+            .and_then(|&index| collection.get_record_mut(index))
+        else {
+            return;
+        };
+        let index = order[index];
+
+        match interact_record(record, state) {
+            RecordAction::Back => {}
+            RecordAction::Duplicate => duplicate_record(collection, index, state),
+            RecordAction::Move => {
+                state.pending_move = Some((state.location.clone(), index));
+                return;
+            }
+            // Moving past either end leaves the order unchanged
+            RecordAction::MoveUp => {
+                let _ = collection.move_record(index, reorder_target(index, true));
+            }
+            RecordAction::MoveDown => {
+                let _ = collection.move_record(index, reorder_target(index, false));
+            }
+        }
+    }
+}
+
+fn search_records(collection: &mut Collection, state: &mut CliState) {
+    execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+
+    let query = answer(|| {
+        Text::new("Search:")
+            .with_help_message("Leave blank to cancel")
             .prompt()
-            .expect("there was an error while selecting");
+    })
+    .unwrap_or_default();
+
+    if query.is_empty() {
+        return;
+    }
+
+    show_filtered_records(collection, &RecordFilter::Label(query), state);
+}
+
+fn filter_by_tag(collection: &mut Collection, state: &mut CliState) {
+    let mut tags: Vec<String> = vec![];
+    for (_, record) in collection.iter_all_records() {
+        for tag in record.tags() {
+            if !tags
+                .iter()
+                .any(|known| known.to_lowercase() == tag.to_lowercase())
+            {
+                tags.push(tag.to_owned());
+            }
+        }
+    }
+    tags.sort_by_key(|tag| tag.to_lowercase());
+
+    execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+    if tags.is_empty() {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Yellow),
+            Print("No records are tagged\n"),
+            ResetColor,
+            Print("Press any key to continue..."),
+        );
+        pause();
+        return;
+    }
+
+    let Some(tag) = prompt_select("Filter by tag", tags) else {
+        return;
+    };
+    show_filtered_records(collection, &RecordFilter::Tag(tag), state);
+}
+
+/// Records listed by `show_filtered_records`
+enum RecordFilter {
+    /// Records whose label contains the query
+    Label(String),
+    /// Records tagged with the tag
+    Tag(String),
+}
+
+impl RecordFilter {
+    fn hits<'a>(&self, collection: &'a Collection) -> Vec<(Vec<String>, &'a Record)> {
+        match self {
+            RecordFilter::Label(query) => collection.find_records(query),
+            RecordFilter::Tag(tag) => collection.find_by_tag(tag),
+        }
+    }
 
-        if &choice == "[<] Back" {
+    fn addresses(&self, collection: &Collection) -> Vec<(Vec<usize>, usize)> {
+        match self {
+            RecordFilter::Label(query) => collection.find_record_addresses(query),
+            RecordFilter::Tag(tag) => collection.find_record_addresses_by_tag(tag),
+        }
+    }
+}
+
+fn show_filtered_records(collection: &mut Collection, filter: &RecordFilter, state: &mut CliState) {
+    loop {
+        execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+
+        // Archived records are searchable while they are shown
+        let (hits, mut addresses): (Vec<_>, Vec<_>) = filter
+            .hits(collection)
+            .into_iter()
+            .zip(filter.addresses(collection))
+            .filter(|((_, record), _)| state.show_archived || !record.is_archived())
+            .unzip();
+        if hits.is_empty() {
+            let message = match filter {
+                RecordFilter::Label(query) => format!("No records matching \"{}\"\n", query),
+                RecordFilter::Tag(tag) => format!("No records tagged \"{}\"\n", tag),
+            };
+            execute!(
+                stdout(),
+                SetForegroundColor(Color::Yellow),
+                Print(message),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+            pause();
             return;
         }
 
-        let index = records
+        let mut results: Vec<String> = hits
             .iter()
-            .position(|child| *child == choice)
+            .enumerate()
+            .map(|(index, (path, record))| {
+                format!("[{}] {}/{}", index + 1, path.join("/"), record.label())
+            })
+            .collect();
+        results.push("[<] Back".to_owned());
+
+        let title = match filter {
+            RecordFilter::Label(_) => "Search results".to_owned(),
+            RecordFilter::Tag(tag) => format!("Records tagged {}", tag),
+        };
+        let Some(index) = prompt_select_index(&title, results) else {
+            return;
+        };
+
+        let Some((path, _)) = hits.get(index) else {
+            return;
+        };
+        let path = path.clone();
+        let (location, record_index) = addresses.swap_remove(index);
+        let record = collection
+            .get_descendant_mut(&location)
+            .and_then(|parent| parent.get_record_mut(record_index))
             .expect("BUG: this should never panic");
 
-        let record = collection.get_record_mut(index).unwrap();
+        let previous_path = std::mem::replace(&mut state.path, Breadcrumb::from(path));
+        let action = interact_record(record, state);
+        state.path = previous_path;
+
+        apply_record_action(collection, (location, record_index), action, state);
+    }
+}
+
+fn apply_record_action(
+    root: &mut Collection,
+    address: (Vec<usize>, usize),
+    action: RecordAction,
+    state: &mut CliState,
+) {
+    let (location, record_index) = address;
+    let parent = root
+        .get_descendant_mut(&location)
+        .expect("BUG: this should never panic");
+    match action {
+        RecordAction::Back => {}
+        RecordAction::Duplicate => duplicate_record(parent, record_index, state),
+        RecordAction::Move => move_record(root, (location, record_index), state),
+        // Moving past either end leaves the order unchanged
+        RecordAction::MoveUp => {
+            let _ = parent.move_record(record_index, reorder_target(record_index, true));
+        }
+        RecordAction::MoveDown => {
+            let _ = parent.move_record(record_index, reorder_target(record_index, false));
+        }
+    }
+}
+
+fn show_favorites(root: &mut Collection, state: &mut CliState) {
+    loop {
+        execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+
+        if state.favorites.is_empty() {
+            execute!(
+                stdout(),
+                SetForegroundColor(Color::Yellow),
+                Print("No favorites yet, add them from the record menu\n"),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+            pause();
+            return;
+        }
+
+        let addresses: Vec<Option<(Vec<usize>, usize)>> = state
+            .favorites
+            .iter()
+            .map(|favorite| {
+                let path: Vec<&str> = favorite.iter().map(String::as_str).collect();
+                root.record_address(&path)
+            })
+            .collect();
+        let mut options: Vec<String> = state
+            .favorites
+            .iter()
+            .zip(addresses.iter())
+            .enumerate()
+            .map(|(index, (favorite, address))| {
+                let missing = if address.is_some() { "" } else { " (missing)" };
+                format!("[{}] {}{}", index + 1, favorite.join("/"), missing)
+            })
+            .collect();
+        let can_clean_up = !state.read_only && addresses.iter().any(Option::is_none);
+        if can_clean_up {
+            options.push("[x] Remove Missing Favorites".to_owned());
+        }
+        options.push("[<] Back".to_owned());
+
+        let Some(index) = prompt_select_index("Favorites", options) else {
+            return;
+        };
+        if index >= addresses.len() {
+            if can_clean_up && index == addresses.len() {
+                state.favorites = std::mem::take(&mut state.favorites)
+                    .into_iter()
+                    .zip(addresses)
+                    .filter_map(|(favorite, address)| address.and(Some(favorite)))
+                    .collect();
+                continue;
+            }
+            return;
+        }
+
+        let Some(address) = addresses[index].clone() else {
+            execute!(
+                stdout(),
+                SetForegroundColor(Color::Yellow),
+                Print("The record no longer exists, it may have been moved or relabeled\n"),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+            pause();
+            continue;
+        };
+        let (location, record_index) = address.clone();
+        let record = root
+            .get_descendant_mut(&location)
+            .and_then(|parent| parent.get_record_mut(record_index))
+            .expect("BUG: this should never panic");
+
+        let favorite = &state.favorites[index];
+        let mut path = vec![state.path.labels()[0].clone()];
+        path.extend_from_slice(&favorite[..favorite.len() - 1]);
+        let previous_path = std::mem::replace(&mut state.path, Breadcrumb::from(path));
+        let action = interact_record(record, state);
+        state.path = previous_path;
+
+        apply_record_action(root, address, action, state);
+    }
+}
+
+/// Path of `record` as stored in the favorites, below the root
+fn favorite_path(record: &Record, state: &CliState) -> Vec<String> {
+    let mut path = state.path.labels()[1..].to_vec();
+    path.push(record.label().clone());
+    path
+}
+
+/// Indices of `labels` in display order, sorted by label ignoring case
+/// when `sorted` is set and in stored order otherwise
+fn display_order(labels: &[&String], sorted: bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..labels.len()).collect();
+    if sorted {
+        order.sort_by_cached_key(|&index| labels[index].to_lowercase());
+    }
+    order
+}
+
+fn sort_toggle_option(sorted: bool) -> String {
+    if sorted {
+        "[~] Show in Stored Order".to_owned()
+    } else {
+        "[~] Sort by Label".to_owned()
+    }
+}
 
-        interact_record(record, state);
+fn archived_toggle_option(shown: bool) -> String {
+    if shown {
+        "[a] Hide Archived".to_owned()
+    } else {
+        "[a] Show Archived".to_owned()
     }
 }
 
-fn interact_record(record: &mut Record, state: &mut CliState) {
-    let path = state.path.join("/") + record.label();
-    loop {
-        execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+/// Appends a copy of the record at `index` to the same collection
+fn duplicate_record(collection: &mut Collection, index: usize, state: &CliState) {
+    let record = collection
+        .get_record(index)
+        .expect("BUG: this should never panic");
+    let result = record_cipher(record, state).and_then(|bound| {
+        record.duplicate(
+            bound.encrypt_fn,
+            bound.decrypt_fn,
+            &bound.key,
+            bound.nonce_size,
+        )
+    });
+    let copy = match result {
+        Ok(copy) => copy,
+        Err(err) => {
+            execute!(
+                stdout(),
+                Clear(ClearType::All),
+                MoveTo(0, 0),
+                SetAttribute(Attribute::Bold),
+                SetForegroundColor(Color::Red),
+                Print(format!("The record could not be duplicated: {}\n", err)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+            pause();
+            return;
+        }
+    };
+    let label = copy.label().clone();
+    collection.add_record(copy);
+
+    execute!(
+        stdout(),
+        Clear(ClearType::All),
+        MoveTo(0, 0),
+        SetAttribute(Attribute::Bold),
+        SetForegroundColor(Color::Green),
+        Print(format!("Record duplicated as {}!\n", label)),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("Press any key to continue..."),
+    );
+    pause();
+}
+
+/// Lets the user pick a collection and moves the record at `address`,
+/// given relative to `root`, into it
+fn move_record(root: &mut Collection, address: (Vec<usize>, usize), state: &mut CliState) {
+    execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+
+    let mut choices = vec![];
+    collect_collection_choices(root, &mut vec![], &mut vec![], &mut choices);
+    let mut options: Vec<String> = choices
+        .iter()
+        .enumerate()
+        .map(|(index, (path, _))| format!("[{}] {}", index + 1, path))
+        .collect();
+    options.push("[<] Cancel".to_owned());
+
+    let Some(index) = prompt_select_index("Move to", options) else {
+        return;
+    };
+
+    let Some((path, target)) = choices.get(index) else {
+        return;
+    };
+    let (source, record_index) = address;
+
+    if *target == source {
+        execute!(
+            stdout(),
+            SetForegroundColor(Color::Yellow),
+            Print(format!("Record is already in {}\n", path)),
+            ResetColor,
+            Print("Press any key to continue..."),
+        );
+        pause();
+        return;
+    }
+
+    let record = root
+        .get_descendant_mut(&source)
+        .and_then(|parent| parent.take_record(record_index))
+        .expect("BUG: this should never panic");
+    root.get_descendant_mut(target)
+        .expect("BUG: this should never panic")
+        .add_record(record);
+
+    execute!(
+        stdout(),
+        SetAttribute(Attribute::Bold),
+        SetForegroundColor(Color::Green),
+        Print(format!("Record moved to {}!\n", path)),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("Press any key to continue..."),
+    );
+    pause();
+}
+
+fn collect_collection_choices(
+    collection: &Collection,
+    path: &mut Vec<String>,
+    location: &mut Vec<usize>,
+    choices: &mut Vec<(String, Vec<usize>)>,
+) {
+    path.push(collection.label().clone());
+    choices.push((path.join("/"), location.clone()));
+    for (index, child) in collection.children().iter().enumerate() {
+        location.push(index);
+        collect_collection_choices(child, path, location, choices);
+        location.pop();
+    }
+    path.pop();
+}
+
+enum RecordAction {
+    Back,
+    Duplicate,
+    Move,
+    MoveUp,
+    MoveDown,
+}
+
+fn interact_record(record: &mut Record, state: &mut CliState) -> RecordAction {
+    let path = format!("{}/{}", state.path, record.label());
+    loop {
+        execute!(
+            stdout(),
+            Clear(ClearType::All),
+            MoveTo(0, 0),
+            Print(format!(
+                "Created:  {}\nModified: {}\n",
+                format_timestamp(record.created_at()),
+                format_timestamp(record.modified_at())
+            )),
+        );
+
+        if record.is_archived() {
+            execute!(stdout(), Print("Archived\n"));
+        }
+        if let Some(cipher) = record.cipher() {
+            execute!(stdout(), Print(format!("Cipher:   {}\n", cipher)));
+        }
+        let tags = record.tags();
+        if !tags.is_empty() {
+            execute!(stdout(), Print(format!("Tags:     {}\n", tags.join(", "))));
+        }
+
+        if let Some(notes) = record.notes() {
+            let width = terminal::size().map(|(columns, _)| columns).unwrap_or(80);
+            execute!(stdout(), Print("Notes:\n"));
+            for line in wrap_text(notes, width.saturating_sub(2) as usize) {
+                execute!(stdout(), Print(format!("  {}\n", line)));
+            }
+        }
+
+        let mut options = menu_options(&RECORD_MENU, state);
+        let is_favorite = state.favorites.contains(&favorite_path(record, state));
+        if is_favorite {
+            for option in options.iter_mut() {
+                if *option == "Add to Favorites" {
+                    *option = "Remove from Favorites";
+                }
+            }
+        }
+        if record.is_archived() {
+            for option in options.iter_mut() {
+                if *option == "Archive" {
+                    *option = "Unarchive";
+                }
+            }
+        }
+        if state.print_secrets {
+            options.retain(|option| *option != "Copy Secret to Clipboard");
+        }
+        if record.is_binary() {
+            options.retain(|option| {
+                !["Copy Secret to Clipboard", "Print Secret", "Edit Secret"].contains(option)
+            });
+            options.insert(0, "Save Secret to File");
+        } else if record.is_totp() {
+            let totp_option = if state.print_secrets {
+                "Print TOTP Code"
+            } else {
+                "Copy TOTP Code"
+            };
+            let position = options
+                .iter()
+                .position(|option| *option == "Print Secret")
+                .expect("BUG: this should never panic");
+            options.insert(position + 1, totp_option);
+        }
 
-        let menu = Select::new(&format!("{}", path), RECORD_MENU.to_vec())
-            .prompt()
-            .expect("there was an error while selecting");
+        let menu = prompt_select(&path, options).unwrap_or("Back");
 
         match menu {
-            "Copy Secret to Clipboard" => {
-                let mut clipboard = Clipboard::new().unwrap();
-                let decrypt_fn = state.cipher.1;
-                record.reveal(decrypt_fn, &state.key);
-                let secret = record.revealed_secret().unwrap();
-                clipboard.set_text(secret);
-
+            "Copy Secret to Clipboard" | "Print Secret" => {
+                let secret = record_cipher(record, state).and_then(|bound| {
+                    let secret = record.reveal(bound.decrypt_fn, &bound.key)?;
+                    Ok(Zeroizing::new(secret.to_owned()))
+                });
+                let secret = match secret {
+                    Ok(secret) => secret,
+                    Err(err) => {
+                        execute!(
+                            stdout(),
+                            SetAttribute(Attribute::Bold),
+                            SetForegroundColor(Color::Red),
+                            Print(format!("The secret could not be decrypted: {}\n", err)),
+                            SetAttribute(Attribute::Reset),
+                            ResetColor,
+                            Print("Press any key to continue..."),
+                        );
+                        pause();
+                        continue;
+                    }
+                };
+                // Decrypt afresh every time rather than keeping the plaintext around
+                record.conceal();
+
+                if menu == "Print Secret" {
+                    print_secret(&secret);
+                    continue;
+                }
+                copy_to_clipboard(secret, "Secret", state);
+                return RecordAction::Back;
+            }
+            "Copy TOTP Code" | "Print TOTP Code" => {
+                let code = record_cipher(record, state)
+                    .ok()
+                    .and_then(|bound| record.current_totp(bound.decrypt_fn, &bound.key));
+                let Some(code) = code else {
+                    execute!(
+                        stdout(),
+                        SetAttribute(Attribute::Bold),
+                        SetForegroundColor(Color::Red),
+                        Print("The secret is not a valid base32 TOTP seed!\n"),
+                        SetAttribute(Attribute::Reset),
+                        ResetColor,
+                        Print("Press any key to continue..."),
+                    );
+                    pause();
+                    continue;
+                };
+                let code = Zeroizing::new(code);
+
+                if menu == "Print TOTP Code" {
+                    print_secret(&code);
+                    continue;
+                }
+                copy_to_clipboard(code, "TOTP code", state);
+                return RecordAction::Back;
+            }
+            "Save Secret to File" => {
+                let Some(file_path) = answer(|| Text::new("Save to:").prompt()) else {
+                    continue;
+                };
+                let result = if Path::new(&file_path).exists() {
+                    Err(format!("{} already exists!", file_path))
+                } else {
+                    record_cipher(record, state)
+                        .and_then(|bound| record.reveal_bytes(bound.decrypt_fn, &bound.key))
+                        .map_err(|err| format!("The secret could not be decrypted: {}", err))
+                        .and_then(|secret| {
+                            let secret = Zeroizing::new(secret);
+                            fs::write(&file_path, &*secret)
+                                .map_err(|err| format!("Failed to write {}: {}", file_path, err))
+                        })
+                };
+                let message = match result {
+                    Ok(()) => format!("Saved the secret to {}\n", file_path),
+                    Err(message) => format!("{}\n", message),
+                };
                 execute!(
                     stdout(),
                     SetAttribute(Attribute::Bold),
-                    SetForegroundColor(Color::Green),
-                    Print("Secret has been copied to clipboard!\n"),
+                    Print(message),
                     SetAttribute(Attribute::Reset),
-                    ResetColor,
                     Print("Press any key to continue..."),
                 );
-
                 pause();
-                state.path.pop();
-                return;
+            }
+            "Edit Secret" => edit_secret(record, state),
+            "Change Cipher" => change_cipher(record, state),
+            "Edit Notes" => {
+                let notes = record.notes().unwrap_or_default().to_owned();
+                let Some(notes) = answer(move || {
+                    Text::new("Notes:")
+                        .with_initial_value(&notes)
+                        .with_help_message("Leave blank to remove the notes")
+                        .prompt()
+                }) else {
+                    continue;
+                };
+                if notes != record.notes().unwrap_or_default() {
+                    record.set_notes(&notes);
+                }
+            }
+            "Edit Tags" => {
+                let tags = record.tags().join(", ");
+                let Some(tags) = answer(move || {
+                    Text::new("Tags:")
+                        .with_initial_value(&tags)
+                        .with_help_message("Comma separated, leave blank to remove the tags")
+                        .prompt()
+                }) else {
+                    continue;
+                };
+                let tags = parse_tags(&tags);
+                if tags != record.tags() {
+                    record.set_tags(&tags);
+                }
+            }
+            "Archive" => record.set_archived(true),
+            "Unarchive" => record.set_archived(false),
+            "Add to Favorites" => {
+                state.favorites.push(favorite_path(record, state));
+            }
+            "Remove from Favorites" => {
+                let path = favorite_path(record, state);
+                state.favorites.retain(|favorite| *favorite != path);
+            }
+            "Duplicate" => {
+                return RecordAction::Duplicate;
+            }
+            "Move" => {
+                return RecordAction::Move;
+            }
+            "Move Up" => {
+                return RecordAction::MoveUp;
+            }
+            "Move Down" => {
+                return RecordAction::MoveDown;
             }
             "Back" => {
-                state.path.pop();
-                return;
+                return RecordAction::Back;
             }
             _ => unreachable!(),
         }
     }
 }
 
-fn authenticate(swd: &mut Swd) -> String {
+fn authenticate(swd: &mut Swd) -> Zeroizing<String> {
+    wait_for_abandoned_prompt();
     execute!(stdout(), Clear(ClearType::All), MoveTo(0, 0));
+    stop_idle_timer();
+    let master_key = prompt_master_key(swd);
+    if !swd.self_test() {
+        execute!(
+            stderr(),
+            SetAttribute(Attribute::Bold),
+            SetForegroundColor(Color::Yellow),
+            Print(
+                "Warning: the master key is correct but the secrets do not decrypt, \
+                the vault header may not match its key!\n"
+            ),
+            SetAttribute(Attribute::Reset),
+            ResetColor,
+            Print("Press any key to continue..."),
+        );
+        pause();
+    }
+    start_idle_timer();
+    master_key
+}
 
+/// Prompts for the master key until it unlocks `swd`, writing
+/// only to stderr so that stdout can be piped
+fn prompt_master_key(swd: &mut Swd) -> Zeroizing<String> {
     loop {
-        let master_key = Password::new("Master key:")
-            .with_display_mode(PasswordDisplayMode::Masked)
-            .without_confirmation()
-            .prompt()
-            .expect("there was an error on password input");
+        let Some(master_key) = answer(|| {
+            Password::new("Master key:")
+                .with_display_mode(PasswordDisplayMode::Masked)
+                .without_confirmation()
+                .prompt()
+        })
+        .map(Zeroizing::new) else {
+            fail(CANCELLED);
+        };
 
         let unlocked = swd.unlock(master_key.as_bytes());
         if unlocked {
+            if let Err(err) = swd.verify_integrity() {
+                execute!(
+                    stderr(),
+                    SetAttribute(Attribute::Bold),
+                    SetForegroundColor(Color::Yellow),
+                    Print(format!(
+                        "Warning: {}, the file may have been corrupted or tampered with!\n",
+                        err
+                    )),
+                    SetAttribute(Attribute::Reset),
+                    ResetColor,
+                    Print("Press any key to continue..."),
+                );
+                pause();
+            }
             return master_key;
         }
 
         execute!(
-            stdout(),
+            stderr(),
             SetAttribute(Attribute::Bold),
             SetForegroundColor(Color::Red),
             Print("Wrong master key!\n"),
             SetAttribute(Attribute::Reset),
             ResetColor,
         );
+        thread::sleep(unlock_backoff(swd.failed_attempts()));
+    }
+}
+
+/// Delay after the first wrong master key, doubled for each following one
+const UNLOCK_BACKOFF_BASE: Duration = Duration::from_millis(250);
+const UNLOCK_BACKOFF_CAP: Duration = Duration::from_secs(30);
+
+/// How long to wait before prompting again after `failed_attempts`
+/// wrong master keys in a row, to slow down guessing
+fn unlock_backoff(failed_attempts: u32) -> Duration {
+    let exponent = failed_attempts.saturating_sub(1).min(16);
+    UNLOCK_BACKOFF_BASE
+        .saturating_mul(1 << exponent)
+        .min(UNLOCK_BACKOFF_CAP)
+}
+
+fn change_master_key(swd: &mut Swd, state: &mut CliState) {
+    execute!(
+        stdout(),
+        Clear(ClearType::All),
+        MoveTo(0, 0),
+        SetAttribute(Attribute::Bold),
+        SetForegroundColor(Color::Cyan),
+        Print("Changing the master key\n"),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+    );
+
+    let Some(old_master_key) = answer(|| {
+        Password::new("Current master key:")
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .without_confirmation()
+            .prompt()
+    })
+    .map(Zeroizing::new) else {
+        return;
+    };
+    let Some(new_master_key) = prompt_new_master_key("New master key:", &PasswordPolicy::default())
+    else {
+        return;
+    };
+
+    let changed = swd.change_master_key(old_master_key.as_bytes(), new_master_key.as_bytes());
+    if changed {
+        state.key = Zeroizing::new(unlocked_key(swd).to_vec());
+        execute!(
+            stdout(),
+            SetAttribute(Attribute::Bold),
+            SetForegroundColor(Color::Green),
+            Print("Master key changed!\n"),
+        );
+    } else {
+        execute!(
+            stdout(),
+            SetAttribute(Attribute::Bold),
+            SetForegroundColor(Color::Red),
+            Print("Wrong master key!\n"),
+        );
     }
+
+    execute!(
+        stdout(),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("Press any key to continue..."),
+    );
+    pause();
 }
 
 fn add_new_record(collection: &mut Collection, state: &mut CliState) {
@@ -412,27 +2057,45 @@ fn add_new_record(collection: &mut Collection, state: &mut CliState) {
         Clear(ClearType::All),
         SetAttribute(Attribute::Bold),
         SetForegroundColor(Color::Cyan),
-        Print(format!(
-            "Creating a new record on {}\n",
-            state.path.join("/")
-        )),
+        Print(format!("Creating a new record on {}\n", state.path)),
         SetAttribute(Attribute::Reset)
     );
 
-    let label = Text::new("Label:")
-        .with_help_message("Leave blank to cancel")
-        .prompt()
-        .expect("there was an error");
+    let label = answer(|| {
+        Text::new("Label:")
+            .with_help_message("Leave blank to cancel")
+            .prompt()
+    })
+    .unwrap_or_default();
 
-    if label.len() == 0 {
+    if label.is_empty() {
         return;
     }
 
-    let secret = Password::new("Secret:")
-        .with_help_message("Secret to store in the record")
-        .with_display_mode(PasswordDisplayMode::Masked)
-        .prompt()
-        .expect("there was an error");
+    let Some(label) =
+        resolve_label_collision(label, "record", |label| collection.has_record_label(label))
+    else {
+        return;
+    };
+
+    let Some(secret) = answer(|| {
+        Password::new("Secret:")
+            .with_help_message("Secret to store in the record")
+            .with_display_mode(PasswordDisplayMode::Masked)
+            .prompt()
+    })
+    .map(Zeroizing::new) else {
+        return;
+    };
+
+    let Some(is_totp) = answer(|| {
+        Confirm::new("Is the secret a TOTP seed?")
+            .with_default(false)
+            .with_help_message("Base32 seed of a two-factor authenticator")
+            .prompt()
+    }) else {
+        return;
+    };
 
     execute!(
         stdout(),
@@ -443,19 +2106,12 @@ fn add_new_record(collection: &mut Collection, state: &mut CliState) {
         ResetColor,
     );
 
-    let encrypt = state.cipher.0;
-
-    // FIXME: refactor this so that it is not hardcoded
-    let mut rng = rand::thread_rng();
-    let mut nonce = [0; 12];
-    rng.fill_bytes(&mut nonce);
-    let mut extras = HashMap::new();
-    extras.insert("nonce".to_owned(), &nonce[..]);
-
-    let encrypted_secret =
-        encrypt(secret.as_bytes(), &state.key, extras).expect("error while encrypting secret");
-    let mut record = Record::new(label, encrypted_secret.into_boxed_slice());
-    record.add_extra("nonce", &nonce, false);
+    let record = RecordBuilder::new()
+        .label(&label)
+        .secret_plaintext(secret.as_bytes())
+        .totp(is_totp)
+        .encrypt_with(state.key_cipher, &state.key)
+        .expect("error while encrypting secret");
     collection.add_record(record);
 
     execute!(
@@ -479,22 +2135,27 @@ fn add_new_collection(collection: &mut Collection, state: &mut CliState) {
         Clear(ClearType::All),
         SetAttribute(Attribute::Bold),
         SetForegroundColor(Color::Cyan),
-        Print(format!(
-            "Creating a new collection on {}\n",
-            state.path.join("/")
-        )),
+        Print(format!("Creating a new collection on {}\n", state.path)),
         SetAttribute(Attribute::Reset)
     );
 
-    let label = Text::new("Label:")
-        .with_help_message("Leave blank to cancel")
-        .prompt()
-        .expect("there was an error");
+    let label = answer(|| {
+        Text::new("Label:")
+            .with_help_message("Leave blank to cancel")
+            .prompt()
+    })
+    .unwrap_or_default();
 
-    if label.len() == 0 {
+    if label.is_empty() {
         return;
     }
 
+    let Some(label) = resolve_label_collision(label, "collection", |label| {
+        collection.has_child_label(label)
+    }) else {
+        return;
+    };
+
     execute!(
         stdout(),
         SetForegroundColor(Color::Yellow),
@@ -522,23 +2183,411 @@ fn add_new_collection(collection: &mut Collection, state: &mut CliState) {
     pause();
 }
 
+/// Offers a suffixed label when a sibling `kind` already uses `label`,
+/// returning `None` if the user declines
+fn resolve_label_collision(
+    label: String,
+    kind: &str,
+    is_taken: impl Fn(&str) -> bool,
+) -> Option<String> {
+    if !is_taken(&label) {
+        return Some(label);
+    }
+
+    let suffixed = (2..)
+        .map(|n| format!("{} ({})", label, n))
+        .find(|suffixed| !is_taken(suffixed))
+        .expect("BUG: this should never panic");
+
+    let question = format!(
+        "A {} labeled \"{}\" already exists here. Use \"{}\" instead?",
+        kind, label, suffixed
+    );
+    let accepted = answer(move || Confirm::new(&question).with_default(true).prompt())?;
+
+    accepted.then_some(suffixed)
+}
+
+/// Copies `secret` to the clipboard and schedules its clearing,
+/// reporting instead of panicking when no clipboard is available
+/// such as on headless machines or when the write itself fails
+fn copy_to_clipboard(secret: Zeroizing<String>, name: &str, state: &CliState) {
+    let copied = Clipboard::new()
+        .map_err(|err| format!("Clipboard is not available: {}", err))
+        .and_then(|mut clipboard| {
+            clipboard
+                .set_text(secret.as_str())
+                .map_err(|err| format!("{} could not be copied to clipboard: {}", name, err))
+        });
+
+    match copied {
+        Ok(()) => {
+            clear_clipboard_after(secret, state.clipboard_timeout);
+
+            execute!(
+                stdout(),
+                SetAttribute(Attribute::Bold),
+                SetForegroundColor(Color::Green),
+                Print(format!("{} has been copied to clipboard!\n", name)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+        }
+        Err(message) => {
+            execute!(
+                stdout(),
+                SetAttribute(Attribute::Bold),
+                SetForegroundColor(Color::Red),
+                Print(format!("{}\n", message)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+                Print("Use Print Secret or the --print flag instead.\n"),
+                Print("Press any key to continue..."),
+            );
+        }
+    }
+
+    pause();
+}
+
+/// Opens the secret of `record` in the user's editor and stores
+/// what is saved there, unless the editor fails or nothing changed
+fn edit_secret(record: &mut Record, state: &CliState) {
+    let decrypted = record_cipher(record, state).and_then(|bound| {
+        let secret = record.decrypt_secret(bound.decrypt_fn, &bound.key)?;
+        Ok((secret, bound))
+    });
+    let (secret, bound) = match decrypted {
+        Ok(decrypted) => decrypted,
+        Err(err) => {
+            execute!(
+                stdout(),
+                SetAttribute(Attribute::Bold),
+                SetForegroundColor(Color::Red),
+                Print(format!("The secret could not be decrypted: {}\n", err)),
+                SetAttribute(Attribute::Reset),
+                ResetColor,
+                Print("Press any key to continue..."),
+            );
+            pause();
+            return;
+        }
+    };
+
+    // Time spent in the editor does not count as idle
+    stop_idle_timer();
+    let result = editor::edit_with(&editor::editor_command(), secret.as_bytes());
+    start_idle_timer();
+
+    let message = match result {
+        Ok(Some(edited)) => match std::str::from_utf8(&edited) {
+            Ok(edited) if strip_trailing_newline(edited) == secret.as_str() => return,
+            Ok(edited) => {
+                let edited = strip_trailing_newline(edited);
+                match record.replace_secret(
+                    edited.as_bytes(),
+                    bound.encrypt_fn,
+                    &bound.key,
+                    bound.nonce_size,
+                ) {
+                    Ok(()) => return,
+                    Err(err) => format!("Failed to encrypt the secret: {}", err),
+                }
+            }
+            Err(_) => "The edited secret is not valid UTF-8, it was not changed".to_owned(),
+        },
+        Ok(None) => "The editor exited with an error, the secret was not changed".to_owned(),
+        Err(err) => format!("Failed to run the editor: {}", err),
+    };
+    execute!(
+        stdout(),
+        SetAttribute(Attribute::Bold),
+        SetForegroundColor(Color::Red),
+        Print(format!("{}\n", message)),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("Press any key to continue..."),
+    );
+    pause();
+}
+
+/// Cipher the secrets of `record` are encrypted with, its own or the key
+/// cipher, bound to its key
+fn record_cipher<'a>(record: &Record, state: &CliState<'a>) -> CipherResult<BoundCipher<'a>> {
+    let key_cipher = &state.key_cipher_name;
+    state.ciphers.bind(
+        record.cipher().unwrap_or(key_cipher),
+        key_cipher,
+        &state.key,
+    )
+}
+
+/// Prompts for another cipher and re-encrypts the secrets of `record` with it
+fn change_cipher(record: &mut Record, state: &CliState) {
+    let current = record.cipher().unwrap_or(&state.key_cipher_name).to_owned();
+    let mut names: Vec<&String> = state
+        .ciphers
+        .get_names()
+        .into_iter()
+        .filter(|name| **name != current)
+        .collect();
+    names.sort();
+    if names.is_empty() {
+        return;
+    }
+    let title = format!("Re-encrypt with (currently {})", current);
+    let Some(name) = prompt_select(&title, names) else {
+        return;
+    };
+
+    let result = record_cipher(record, state).and_then(|old| {
+        let new = state
+            .ciphers
+            .bind(name, &state.key_cipher_name, &state.key)?;
+        record.reencrypt_with(
+            old.decrypt_fn,
+            &old.key,
+            new.encrypt_fn,
+            &new.key,
+            new.nonce_size,
+            name,
+        )
+    });
+    let (color, message) = match result {
+        Ok(()) => (
+            Color::Green,
+            format!("The secret is now encrypted with {}\n", name),
+        ),
+        Err(err) => (
+            Color::Red,
+            format!("The secret could not be re-encrypted: {}\n", err),
+        ),
+    };
+    execute!(
+        stdout(),
+        SetAttribute(Attribute::Bold),
+        SetForegroundColor(color),
+        Print(message),
+        SetAttribute(Attribute::Reset),
+        ResetColor,
+        Print("Press any key to continue..."),
+    );
+    pause();
+}
+
+/// Shows `secret` until a key is pressed, then wipes it from the screen
+fn print_secret(secret: &str) {
+    execute!(
+        stdout(),
+        SetAttribute(Attribute::Bold),
+        Print(format!("{}\n", secret)),
+        SetAttribute(Attribute::Reset),
+        Print("Press any key to hide it..."),
+    );
+    pause();
+    execute!(stdout(), Clear(ClearType::All), Clear(ClearType::Purge));
+}
+
+/// Clears the clipboard once `timeout` has elapsed, unless its content
+/// no longer matches `secret` (i.e. the user has copied something else).
+fn clear_clipboard_after(secret: Zeroizing<String>, timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        let Ok(mut clipboard) = Clipboard::new() else {
+            return;
+        };
+        let current = clipboard.get_text().map(Zeroizing::new);
+        if matches!(current, Ok(current) if current == secret) {
+            clipboard.clear();
+        }
+    });
+}
+
+/// Reported when a prompt outside the menus is canceled
+const CANCELLED: &str = "Cancelled";
+
+/// Runs `prompt` and unwraps its answer, giving `None` when the user
+/// cancels it with Esc or interrupts it with Ctrl-C, or when the
+/// session went idle before it was answered.
+///
+/// The prompt runs on its own thread, so that an idle session gives
+/// up waiting on it and unwinds to the root menu, which locks the
+/// vault, even while the prompt is still open.
+fn answer<T: Send + 'static>(
+    prompt: impl FnOnce() -> InquireResult<T> + Send + 'static,
+) -> Option<T> {
+    if idle_timed_out() {
+        return None;
+    }
+    wait_for_abandoned_prompt();
+
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        let _ = sender.send(prompt());
+    });
+    let result = loop {
+        let received = match remaining_idle_time() {
+            Some(remaining) => receiver.recv_timeout(remaining),
+            None => receiver.recv().map_err(RecvTimeoutError::from),
+        };
+        match received {
+            Ok(result) => break result,
+            Err(RecvTimeoutError::Timeout) if idle_timed_out() => {
+                *abandoned_prompt() = Some(handle);
+                return None;
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => panic!("the prompt thread panicked"),
+        }
+    };
+    let _ = handle.join();
+
+    let answer = match result {
+        Ok(answer) => Some(answer),
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => None,
+        Err(err) => panic!("there was an error while prompting: {}", err),
+    };
+    if !record_activity() {
+        return None;
+    }
+    answer
+}
+
+/// Lets the user pick one of `options`, giving `None` when
+/// the menu is canceled, which menus treat as going back.
+/// Menus are skipped once the session is idle, so that
+/// they unwind to the root menu, which locks the vault.
+fn prompt_select<T: Display>(message: &str, mut options: Vec<T>) -> Option<T> {
+    let index = prompt_select_index(message, options.iter().collect())?;
+    Some(options.swap_remove(index))
+}
+
+/// Same as `prompt_select`, giving the index of the picked option
+fn prompt_select_index<T: Display>(message: &str, options: Vec<T>) -> Option<usize> {
+    let message = message.to_owned();
+    let options: Vec<String> = options.iter().map(ToString::to_string).collect();
+    answer(move || Select::new(&message, options).raw_prompt()).map(|option| option.index)
+}
+
+/// Prompt still open when the session went idle, left running
+/// in the background until the user answers or cancels it
+static ABANDONED_PROMPT: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+
+fn abandoned_prompt() -> MutexGuard<'static, Option<JoinHandle<()>>> {
+    ABANDONED_PROMPT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Waits for the user to dismiss the prompt left open when the
+/// session went idle, if any, whose answer is then discarded, so
+/// that it no longer competes with the next prompt for the terminal
+fn wait_for_abandoned_prompt() {
+    let handle = abandoned_prompt().take();
+    if let Some(handle) = handle {
+        let _ = handle.join();
+    }
+}
+
+/// Waits for a key press, or until the session is idle
 fn pause() {
     loop {
+        if idle_timed_out() {
+            return;
+        }
+        if let Some(remaining) = remaining_idle_time() {
+            if !event::poll(remaining).unwrap_or(false) {
+                continue;
+            }
+        }
         if let Ok(Event::Key(event)) = event::read() {
             if event.kind == KeyEventKind::Press {
+                record_activity();
                 break;
             }
         }
     }
 }
 
+/// Idle time after which an interactive session locks the vault, if any
+struct IdleTimer {
+    timeout: Option<Duration>,
+    /// Time of the last answer or key press, `None` while stopped
+    last_activity: Option<Instant>,
+    timed_out: bool,
+}
+
+/// Shared by the prompt helpers, which have no access to the session
+static IDLE_TIMER: Mutex<IdleTimer> = Mutex::new(IdleTimer {
+    timeout: None,
+    last_activity: None,
+    timed_out: false,
+});
+
+fn idle_timer() -> MutexGuard<'static, IdleTimer> {
+    IDLE_TIMER.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn set_idle_timeout(timeout: Option<Duration>) {
+    idle_timer().timeout = timeout;
+}
+
+fn start_idle_timer() {
+    let mut timer = idle_timer();
+    timer.last_activity = Some(Instant::now());
+    timer.timed_out = false;
+}
+
+/// Stops counting idle time, such as while waiting for the master key
+fn stop_idle_timer() {
+    let mut timer = idle_timer();
+    timer.last_activity = None;
+    timer.timed_out = false;
+}
+
+/// Time left before the session is idle, `None` if it never will be
+fn remaining_idle_time() -> Option<Duration> {
+    let timer = idle_timer();
+    let (timeout, last_activity) = (timer.timeout?, timer.last_activity?);
+    Some(timeout.saturating_sub(last_activity.elapsed()))
+}
+
+/// Whether the session has gone idle, staying so until the timer restarts
+fn idle_timed_out() -> bool {
+    let remaining = remaining_idle_time();
+    let mut timer = idle_timer();
+    timer.timed_out |= remaining == Some(Duration::ZERO);
+    timer.timed_out
+}
+
+/// Restarts the idle time count, unless the session has gone idle
+/// already, returning whether it was still active
+fn record_activity() -> bool {
+    if idle_timed_out() {
+        return false;
+    }
+    let mut timer = idle_timer();
+    if timer.last_activity.is_some() {
+        timer.last_activity = Some(Instant::now());
+    }
+    true
+}
+
 fn file_exists(path: &str) -> bool {
     let path = Path::new(path);
     path.exists() && path.is_file()
 }
 
 #[derive(CliParser)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    after_help = "Vaults given by a bare name live in SWORDS_HOME, ~/.swords by default. \
+        Anything else is taken as a path to the vault file."
+)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
@@ -548,14 +2597,205 @@ struct Cli {
 enum Commands {
     New(NewArgs),
     Open(OpenArgs),
+    /// Export a vault or one of its collections with the secrets decrypted to stdout
+    Export(ExportArgs),
+    /// Create a vault from a JSON or CSV file
+    Import(ImportArgs),
+    /// Check that every record secret in a vault decrypts
+    Verify(VerifyArgs),
+    /// Print the secret of a single record to stdout
+    ///
+    /// The master key is read from the SWORDS_MASTER_KEY environment
+    /// variable when it is set, and prompted for otherwise.
+    ///
+    /// WARNING: environment variables may be visible to other processes of
+    /// the same user and can leak into shell history or logs. Only use
+    /// SWORDS_MASTER_KEY for automation on machines you trust.
+    Get(GetArgs),
+    /// Add a record with the secret read from stdin
+    ///
+    /// Collections missing along the path are created. A single trailing
+    /// newline is stripped from the secret. Reads the master key from
+    /// SWORDS_MASTER_KEY like get does.
+    Add(AddArgs),
+    /// Remove a record, or a collection with everything in it
+    ///
+    /// Asks for confirmation unless --force is given. Reads the master
+    /// key from SWORDS_MASTER_KEY like get does.
+    Rm(RmArgs),
+    /// Print the collections and record labels of a vault as a tree
+    ///
+    /// Reads the master key from SWORDS_MASTER_KEY like get does.
+    Tree(TreeArgs),
+    /// Show which records were added, removed or changed between two vaults
+    ///
+    /// Secrets are compared but never printed.
+    Diff(DiffArgs),
+    /// List the vaults in SWORDS_HOME
+    List,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    old_file_path: String,
+    new_file_path: String,
+}
+
+#[derive(Args)]
+struct TreeArgs {
+    file_path: String,
+    /// Show at most this many levels below the root collection
+    #[arg(long)]
+    depth: Option<usize>,
+}
+
+#[derive(Args)]
+struct GetArgs {
+    file_path: String,
+    /// Record path below the root collection, e.g. Work/Email/Gmail
+    path: String,
+}
+
+#[derive(Args)]
+struct AddArgs {
+    file_path: String,
+    /// Collection path below the root collection, e.g. Work/Email
+    path: String,
+    label: String,
+    /// Store stdin as is, as a binary record such as a file
+    #[arg(long)]
+    binary: bool,
+    /// Do not keep the previous vault file as <vault>.swd.bak
+    #[arg(long)]
+    no_backup: bool,
+}
+
+#[derive(Args)]
+struct RmArgs {
+    file_path: String,
+    /// Path of the record or collection below the root collection,
+    /// e.g. Work/Email/Gmail. A record is taken over a collection
+    /// labelled the same.
+    path: String,
+    /// Remove without asking for confirmation
+    #[arg(long)]
+    force: bool,
+    /// Do not keep the previous vault file as <vault>.swd.bak
+    #[arg(long)]
+    no_backup: bool,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    file_path: String,
 }
 
 #[derive(Args)]
 struct NewArgs {
     file_path: String,
+    /// Reuse the cipher and hash functions of this existing vault
+    #[arg(long, value_name = "VAULT")]
+    like: Option<String>,
+    /// Encrypt record and collection labels too, hiding them until unlocked
+    #[arg(long)]
+    encrypt_labels: bool,
+    #[command(flatten)]
+    algorithms: AlgorithmArgs,
+}
+
+/// Vault settings chosen up front instead of prompted for
+#[derive(Args, Default)]
+struct AlgorithmArgs {
+    /// Cipher encrypting the secrets
+    #[arg(long, value_name = "NAME", conflicts_with = "like")]
+    cipher: Option<String>,
+    /// Hash function hashing the master key
+    #[arg(long, value_name = "NAME", conflicts_with = "like")]
+    master_kdf: Option<String>,
+    /// Hash function deriving the encryption key from the master key
+    #[arg(long, value_name = "NAME", conflicts_with = "like")]
+    key_kdf: Option<String>,
+}
+
+impl AlgorithmArgs {
+    /// Checks every given name against the registries
+    fn validate(&self) -> Result<(), String> {
+        let cipher_registry = CipherRegistry::default();
+        let hash_registry = HashFunctionRegistry::default();
+        if let Some(cipher) = &self.cipher {
+            cipher_registry
+                .validate_name(cipher)
+                .map_err(|err| format!("Invalid --cipher: {}", err))?;
+        }
+        for (flag, name) in [
+            ("--master-kdf", &self.master_kdf),
+            ("--key-kdf", &self.key_kdf),
+        ] {
+            if let Some(name) = name {
+                hash_registry
+                    .validate_name(name)
+                    .map_err(|err| format!("Invalid {}: {}", flag, err))?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Args)]
 struct OpenArgs {
+    /// Vault to open, or - to read it from stdin
+    file_path: String,
+    /// Seconds after which a copied secret is cleared from the clipboard
+    #[arg(long, default_value_t = 30)]
+    clear_clipboard_after: u64,
+    /// Print secrets to stdout instead of copying them to the clipboard
+    #[arg(long)]
+    print: bool,
+    /// Browse the vault without allowing edits or saving it
+    #[arg(long)]
+    read_only: bool,
+    /// Seconds without input after which the vault is locked, 0 to never
+    /// lock [default: SWORDS_IDLE_TIMEOUT or 300]
+    #[arg(long, value_name = "SECONDS")]
+    lock_after: Option<u64>,
+    /// Do not keep the previous vault file as <vault>.swd.bak
+    #[arg(long)]
+    no_backup: bool,
+    /// Save the vault to this path instead, required to save
+    /// a vault read from stdin
+    #[arg(long, value_name = "PATH")]
+    out: Option<String>,
+    /// Re-encrypt every secret under a fresh nonce before saving
+    #[arg(long)]
+    rotate_nonces: bool,
+}
+
+#[derive(Args)]
+struct ExportArgs {
     file_path: String,
+    /// Collection path below the root collection to export only
+    /// that subtree, e.g. Work/Email
+    path: Option<String>,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    format: ExportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Json,
+}
+
+#[derive(Args)]
+struct ImportArgs {
+    /// JSON tree as written by export, or CSV with path, label and secret columns
+    source_path: String,
+    file_path: String,
+    #[arg(long, value_enum, default_value_t = ImportFormat::Json)]
+    format: ImportFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImportFormat {
+    Json,
+    Csv,
 }
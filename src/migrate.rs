@@ -0,0 +1,116 @@
+use crate::entity::{Swd, VERSION};
+
+/// Upgrades a vault parsed from `from_version` of the format
+/// to `from_version + 1`
+///
+/// The parser already reads every older layout into the same entities,
+/// so a migration only has to rewrite what a format change means for
+/// the entities themselves, such as renaming or converting fields.
+pub trait Migration {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_version(&self) -> u32;
+    fn apply(&self, swd: &mut Swd);
+}
+
+pub struct MigrationRegistry {
+    migrations: Vec<Box<dyn Migration>>,
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        Self { migrations: vec![] }
+    }
+
+    /// Registers a migration, keeping migrations ordered by the version
+    /// they upgrade from and in registration order for the same version
+    pub fn register(&mut self, migration: Box<dyn Migration>) {
+        self.migrations.push(migration);
+        self.migrations
+            .sort_by_key(|migration| migration.from_version());
+    }
+
+    /// Applies every migration from `from_version` up to the current
+    /// format version in order
+    pub fn apply(&self, swd: &mut Swd, from_version: u32) {
+        self.migrations
+            .iter()
+            .filter(|migration| (from_version..VERSION).contains(&migration.from_version()))
+            .for_each(|migration| migration.apply(swd));
+    }
+}
+
+impl Default for MigrationRegistry {
+    fn default() -> Self {
+        let mut registry = MigrationRegistry::new();
+        registry.register(Box::new(V1Migration));
+        registry.register(Box::new(V4Migration));
+        registry
+    }
+}
+
+/// Version 2 added collection lengths, which only change the layout,
+/// so there is nothing to migrate. Serves as a template for later ones.
+pub struct V1Migration;
+
+impl Migration for V1Migration {
+    fn from_version(&self) -> u32 {
+        1
+    }
+
+    fn apply(&self, swd: &mut Swd) {}
+}
+
+/// Version 5 added large values, written only for values too long for a
+/// 2 byte length. Older files have none, so there is nothing to migrate.
+pub struct V4Migration;
+
+impl Migration for V4Migration {
+    fn from_version(&self) -> u32 {
+        4
+    }
+
+    fn apply(&self, swd: &mut Swd) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::{tests::dummy_swd, VERSION};
+
+    use super::{Migration, MigrationRegistry};
+
+    struct LabelMigration(u32);
+
+    impl Migration for LabelMigration {
+        fn from_version(&self) -> u32 {
+            self.0
+        }
+
+        fn apply(&self, swd: &mut crate::entity::Swd) {
+            let label = format!("{}-{}", swd.get_root().label(), self.0);
+            swd.get_root_mut().set_label(&label);
+        }
+    }
+
+    #[test]
+    fn applies_pending_migrations_in_order() {
+        let mut registry = MigrationRegistry::new();
+        registry.register(Box::new(LabelMigration(2)));
+        registry.register(Box::new(LabelMigration(VERSION)));
+        registry.register(Box::new(LabelMigration(1)));
+        registry.register(Box::new(LabelMigration(3)));
+
+        let mut swd = dummy_swd(b"master key");
+        registry.apply(&mut swd, 2);
+        assert_eq!(swd.get_root().label(), "root-2-3");
+    }
+
+    #[test]
+    fn nothing_to_apply_for_current_version() {
+        let mut registry = MigrationRegistry::new();
+        registry.register(Box::new(LabelMigration(1)));
+
+        let mut swd = dummy_swd(b"master key");
+        registry.apply(&mut swd, VERSION);
+        assert_eq!(swd.get_root().label(), "root");
+    }
+}
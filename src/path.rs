@@ -0,0 +1,113 @@
+use std::fmt::Display;
+
+/// Separator between the labels of a path
+pub const SEPARATOR: &str = "/";
+
+/// Labels leading to a collection, kept joined with `/` as they are
+/// pushed and popped so that displaying the path needs no allocation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breadcrumb {
+    labels: Vec<String>,
+    joined: String,
+}
+
+impl Breadcrumb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, label: &str) {
+        if !self.labels.is_empty() {
+            self.joined.push_str(SEPARATOR);
+        }
+        self.joined.push_str(label);
+        self.labels.push(label.to_owned());
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        let label = self.labels.pop()?;
+        let mut length = self.joined.len() - label.len();
+        if !self.labels.is_empty() {
+            length -= SEPARATOR.len();
+        }
+        self.joined.truncate(length);
+        Some(label)
+    }
+
+    /// Labels joined with `/`, without a leading or trailing one
+    pub fn as_display(&self) -> &str {
+        &self.joined
+    }
+
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+impl From<Vec<String>> for Breadcrumb {
+    fn from(labels: Vec<String>) -> Self {
+        let joined = labels.join(SEPARATOR);
+        Self { labels, joined }
+    }
+}
+
+impl Display for Breadcrumb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.joined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Breadcrumb;
+
+    #[test]
+    fn push_and_pop_keep_the_joined_path() {
+        let mut path = Breadcrumb::new();
+        assert_eq!(path.as_display(), "");
+
+        path.push("root");
+        assert_eq!(path.as_display(), "root");
+        path.push("Work");
+        path.push("Legacy");
+        assert_eq!(path.as_display(), "root/Work/Legacy");
+        assert_eq!(path.labels(), ["root", "Work", "Legacy"]);
+
+        assert_eq!(path.pop().as_deref(), Some("Legacy"));
+        assert_eq!(path.as_display(), "root/Work");
+        assert_eq!(path.pop().as_deref(), Some("Work"));
+        assert_eq!(path.as_display(), "root");
+        assert_eq!(path.pop().as_deref(), Some("root"));
+        assert_eq!(path.as_display(), "");
+        assert_eq!(path.pop(), None);
+        assert!(path.is_empty());
+    }
+
+    #[test]
+    fn empty_labels_keep_their_separator() {
+        let mut path = Breadcrumb::new();
+        path.push("");
+        path.push("Work");
+        assert_eq!(path.as_display(), "/Work");
+        path.pop();
+        assert_eq!(path.as_display(), "");
+
+        path.pop();
+        path.push("Work");
+        assert_eq!(path.as_display(), "Work");
+    }
+
+    #[test]
+    fn from_labels_matches_pushing() {
+        let mut pushed = Breadcrumb::new();
+        pushed.push("root");
+        pushed.push("Work");
+        let collected = Breadcrumb::from(vec!["root".to_owned(), "Work".to_owned()]);
+        assert_eq!(collected, pushed);
+        assert_eq!(collected.to_string(), "root/Work");
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{error::PolicyViolation, hash::HashFunctionRegistry};
+
+/// Minimum master key length of the default policy
+pub const DEFAULT_MIN_LENGTH: usize = 8;
+
+/// Requirements a new master key has to meet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// Minimum length in characters
+    pub min_len: usize,
+    pub require_mixed_case: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+impl PasswordPolicy {
+    /// Checks `password` against every requirement,
+    /// listing all the ones it violates
+    pub fn validate(&self, password: &str) -> Result<(), Vec<PolicyViolation>> {
+        let mut violations = vec![];
+
+        if password.chars().count() < self.min_len {
+            violations.push(PolicyViolation::TooShort(self.min_len));
+        }
+        if self.require_mixed_case
+            && !(password.chars().any(char::is_lowercase)
+                && password.chars().any(char::is_uppercase))
+        {
+            violations.push(PolicyViolation::MissingMixedCase);
+        }
+        if self.require_digit && !password.chars().any(|ch| ch.is_ascii_digit()) {
+            violations.push(PolicyViolation::MissingDigit);
+        }
+        if self.require_symbol
+            && !password
+                .chars()
+                .any(|ch| !ch.is_alphanumeric() && !ch.is_whitespace())
+        {
+            violations.push(PolicyViolation::MissingSymbol);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Describes the requirements in a single sentence
+    pub fn describe(&self) -> String {
+        let mut requirements = vec![format!("at least {} characters", self.min_len)];
+        if self.require_mixed_case {
+            requirements.push("lowercase and uppercase letters".to_owned());
+        }
+        if self.require_digit {
+            requirements.push("a digit".to_owned());
+        }
+        if self.require_symbol {
+            requirements.push("a symbol".to_owned());
+        }
+        format!("Must contain {}", requirements.join(", "))
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_len: DEFAULT_MIN_LENGTH,
+            require_mixed_case: false,
+            require_digit: false,
+            require_symbol: false,
+        }
+    }
+}
+
+/// How well a hash function resists guessing the key it derives from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KdfStrength {
+    /// A key derivation function, slow by design
+    KeyDerivation,
+    /// A plain hash function, cheap to evaluate for every guess
+    FastHash,
+    /// Not a registered function
+    Unknown,
+}
+
+/// Classifies the hash function registered as `name` for deriving keys
+/// from the master key. Plain hash functions work but make brute forcing
+/// the master key cheap, so a key derivation function such as PBKDF2 or
+/// Argon2 should be preferred.
+pub fn validate_kdf_choice(name: &str) -> KdfStrength {
+    let registry = HashFunctionRegistry::default();
+    if registry.is_key_derivation(name) {
+        KdfStrength::KeyDerivation
+    } else if registry.validate_name(name).is_ok() {
+        KdfStrength::FastHash
+    } else {
+        KdfStrength::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::PolicyViolation;
+
+    use super::{validate_kdf_choice, KdfStrength, PasswordPolicy};
+
+    fn strict_policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_len: 10,
+            require_mixed_case: true,
+            require_digit: true,
+            require_symbol: true,
+        }
+    }
+
+    #[test]
+    fn default_policy_only_checks_length() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.validate("abcdefgh"), Ok(()));
+        assert_eq!(
+            policy.validate("abcdefg"),
+            Err(vec![PolicyViolation::TooShort(8)])
+        );
+    }
+
+    #[test]
+    fn length_counts_characters() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(
+            policy.validate("ééééééé"),
+            Err(vec![PolicyViolation::TooShort(8)])
+        );
+        assert_eq!(policy.validate("éééééééé"), Ok(()));
+    }
+
+    #[test]
+    fn strict_policy_accepts_strong_password() {
+        assert_eq!(strict_policy().validate("Abcdefgh1!"), Ok(()));
+    }
+
+    #[test]
+    fn missing_mixed_case() {
+        assert_eq!(
+            strict_policy().validate("abcdefgh1!"),
+            Err(vec![PolicyViolation::MissingMixedCase])
+        );
+        assert_eq!(
+            strict_policy().validate("ABCDEFGH1!"),
+            Err(vec![PolicyViolation::MissingMixedCase])
+        );
+    }
+
+    #[test]
+    fn missing_digit() {
+        assert_eq!(
+            strict_policy().validate("Abcdefghi!"),
+            Err(vec![PolicyViolation::MissingDigit])
+        );
+    }
+
+    #[test]
+    fn missing_symbol() {
+        assert_eq!(
+            strict_policy().validate("Abcdefghi1"),
+            Err(vec![PolicyViolation::MissingSymbol])
+        );
+        assert_eq!(
+            strict_policy().validate("Abcdefgh 1"),
+            Err(vec![PolicyViolation::MissingSymbol])
+        );
+    }
+
+    #[test]
+    fn classifies_kdf_choices() {
+        assert_eq!(
+            validate_kdf_choice("pbkdf2-sha256"),
+            KdfStrength::KeyDerivation
+        );
+        for name in ["sha3-256", "sha2-256", "sha2-512", "blake3"] {
+            assert_eq!(validate_kdf_choice(name), KdfStrength::FastHash, "{}", name);
+        }
+        assert_eq!(validate_kdf_choice("md5"), KdfStrength::Unknown);
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        assert_eq!(
+            strict_policy().validate("abc"),
+            Err(vec![
+                PolicyViolation::TooShort(10),
+                PolicyViolation::MissingMixedCase,
+                PolicyViolation::MissingDigit,
+                PolicyViolation::MissingSymbol,
+            ])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use crate::entity::{
+    collection::REQUIRED_COLLECTION_FIELDS,
+    label::LABEL_NONCE_FIELD,
+    record::{
+        ARCHIVED_FIELD, BINDING_FIELD, CHECKSUM_FIELD, CIPHER_FIELD, CREATED_AT_FIELD,
+        FIELD_NONCE_SUFFIX, FIELD_TIME_SUFFIX, HISTORY_FIELD_PREFIX, KIND_FIELD, MODIFIED_AT_FIELD,
+        NOTES_FIELD, REQUIRED_RECORD_FIELDS, REQUIRED_RECORD_SECRET_FIELDS, TAGS_FIELD, TOTP_FIELD,
+    },
+    DESCRIPTION_FIELD, FAVORITES_FIELD, FAVORITES_NONCE_FIELD, KDF_ITERATIONS_FIELD,
+    LABELS_ENCRYPTED_FIELD, LAST_OPENED_FIELD, REQUIRED_HEADER_FIELDS,
+};
+
+/// Record extras swords manages itself, besides the required fields
+pub const RESERVED_RECORD_KEYS: [&str; 11] = [
+    CREATED_AT_FIELD,
+    MODIFIED_AT_FIELD,
+    "nonce",
+    BINDING_FIELD,
+    CHECKSUM_FIELD,
+    TOTP_FIELD,
+    KIND_FIELD,
+    NOTES_FIELD,
+    TAGS_FIELD,
+    ARCHIVED_FIELD,
+    CIPHER_FIELD,
+];
+
+/// Suffixes of the record extras belonging to a secret field
+pub const RESERVED_RECORD_SUFFIXES: [&str; 2] = [FIELD_NONCE_SUFFIX, FIELD_TIME_SUFFIX];
+
+/// Header extras swords manages itself, besides the required fields
+pub const RESERVED_HEADER_KEYS: [&str; 6] = [
+    KDF_ITERATIONS_FIELD,
+    DESCRIPTION_FIELD,
+    LAST_OPENED_FIELD,
+    FAVORITES_FIELD,
+    FAVORITES_NONCE_FIELD,
+    LABELS_ENCRYPTED_FIELD,
+];
+
+// Two features claiming the same key fail to compile
+const _: () = assert!(all_distinct(&RESERVED_RECORD_KEYS));
+const _: () = assert!(all_distinct(&RESERVED_HEADER_KEYS));
+
+/// Whether users may not set the record extra `key`, which would
+/// clobber a field swords relies on, such as a nonce
+pub fn is_reserved_record_key(key: &str) -> bool {
+    REQUIRED_RECORD_FIELDS.contains(&key)
+        || REQUIRED_RECORD_SECRET_FIELDS.contains(&key)
+        || RESERVED_RECORD_KEYS.contains(&key)
+        || RESERVED_RECORD_SUFFIXES
+            .iter()
+            .any(|suffix| key.ends_with(suffix))
+        || is_history_field(key)
+}
+
+pub fn is_reserved_collection_key(key: &str) -> bool {
+    REQUIRED_COLLECTION_FIELDS.contains(&key) || key == LABEL_NONCE_FIELD
+}
+
+pub fn is_reserved_header_key(key: &str) -> bool {
+    REQUIRED_HEADER_FIELDS.contains(&key) || RESERVED_HEADER_KEYS.contains(&key)
+}
+
+/// Whether `key` is one of `hist0`, `hist1`, ... holding previous secrets
+fn is_history_field(key: &str) -> bool {
+    key.strip_prefix(HISTORY_FIELD_PREFIX)
+        .is_some_and(|index| !index.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+const fn all_distinct(keys: &[&str]) -> bool {
+    let mut i = 0;
+    while i < keys.len() {
+        let mut j = i + 1;
+        while j < keys.len() {
+            if str_eq(keys[i], keys[j]) {
+                return false;
+            }
+            j += 1;
+        }
+        i += 1;
+    }
+    true
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        all_distinct, is_reserved_collection_key, is_reserved_header_key, is_reserved_record_key,
+    };
+
+    #[test]
+    fn reserved_record_keys() {
+        for key in [
+            "label", "secret", "nonce", "cksum", "cipher", "tags", "totp", "hist0", "hist12",
+        ] {
+            assert!(is_reserved_record_key(key), "{}", key);
+        }
+        for key in ["pin.nonce", "hist0.time"] {
+            assert!(is_reserved_record_key(key), "{}", key);
+        }
+        for key in ["icon", "url", "history", "hist", "histx", "username"] {
+            assert!(!is_reserved_record_key(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn reserved_collection_and_header_keys() {
+        assert!(is_reserved_collection_key("label"));
+        assert!(is_reserved_collection_key("label.nonce"));
+        assert!(!is_reserved_collection_key("icon"));
+        assert!(is_reserved_header_key("kc"));
+        assert!(is_reserved_header_key("favorites"));
+        assert!(!is_reserved_header_key("icon"));
+    }
+
+    #[test]
+    fn all_distinct_finds_duplicates() {
+        assert!(all_distinct(&["a", "ab", "b"]));
+        assert!(!all_distinct(&["a", "ab", "a"]));
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Environment variable overriding the directory vaults live in
+pub const HOME_ENV: &str = "SWORDS_HOME";
+pub const VAULT_EXTENSION: &str = "swd";
+
+/// Directory bare vault names resolve under, `SWORDS_HOME`
+/// when it is set and `~/.swords` otherwise
+pub fn home_dir() -> PathBuf {
+    if let Some(home) = env::var_os(HOME_ENV) {
+        return PathBuf::from(home);
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".swords")
+}
+
+/// Resolves `name` to the file of a vault, see `resolve_vault_path_in`
+pub fn resolve_vault_path(name: &str) -> PathBuf {
+    resolve_vault_path_in(&home_dir(), name)
+}
+
+/// Resolves a bare vault name, one without any directory, under `home`
+/// and takes anything else as a path. The `.swd` extension is appended
+/// when missing.
+pub fn resolve_vault_path_in(home: &Path, name: &str) -> PathBuf {
+    let path = Path::new(name);
+    let mut components = path.components();
+    let is_bare = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    let path = if is_bare {
+        home.join(path)
+    } else {
+        path.to_path_buf()
+    };
+
+    if path.extension() == Some(OsStr::new(VAULT_EXTENSION)) {
+        return path;
+    }
+    let mut path = OsString::from(path);
+    path.push(".");
+    path.push(VAULT_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Names of the vaults in `dir`, sorted and without their extension
+pub fn list_vaults(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || path.extension() != Some(OsStr::new(VAULT_EXTENSION)) {
+            continue;
+        }
+        if let Some(name) = path.file_stem() {
+            names.push(name.to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use super::{list_vaults, resolve_vault_path_in};
+
+    #[test]
+    fn bare_names_resolve_under_home() {
+        let home = Path::new("/vaults");
+        assert_eq!(
+            resolve_vault_path_in(home, "work"),
+            Path::new("/vaults/work.swd")
+        );
+        assert_eq!(
+            resolve_vault_path_in(home, "work.swd"),
+            Path::new("/vaults/work.swd")
+        );
+        assert_eq!(
+            resolve_vault_path_in(home, "work.old"),
+            Path::new("/vaults/work.old.swd")
+        );
+    }
+
+    #[test]
+    fn paths_are_kept() {
+        let home = Path::new("/vaults");
+        assert_eq!(
+            resolve_vault_path_in(home, "./work"),
+            Path::new("./work.swd")
+        );
+        assert_eq!(
+            resolve_vault_path_in(home, "backups/work.swd"),
+            Path::new("backups/work.swd")
+        );
+        assert_eq!(
+            resolve_vault_path_in(home, "/tmp/work"),
+            Path::new("/tmp/work.swd")
+        );
+    }
+
+    #[test]
+    fn list_vaults_finds_swd_files() {
+        let dir = env::temp_dir().join(format!("swords-list-{}", process::id()));
+        fs::create_dir_all(dir.join("nested.swd")).unwrap();
+        for file in ["work.swd", "home.swd", "notes.txt"] {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+
+        let names = list_vaults(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names.unwrap(), vec!["home", "work"]);
+    }
+}
@@ -0,0 +1,83 @@
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+pub const TOTP_DIGITS: u32 = 6;
+pub const TOTP_PERIOD: u64 = 30;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Computes the RFC 6238 code of `seed` at `timestamp`
+/// using HMAC-SHA1 and the default 30 second period
+pub fn totp(seed: &[u8], timestamp: u64) -> String {
+    let counter = timestamp / TOTP_PERIOD;
+    let mut mac = Hmac::<Sha1>::new_from_slice(seed).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(digest[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    let code = binary % 10u32.pow(TOTP_DIGITS);
+
+    format!("{:0width$}", code, width = TOTP_DIGITS as usize)
+}
+
+/// Decodes an RFC 4648 base32 seed, ignoring case,
+/// whitespace and padding as authenticator apps do
+pub fn decode_base32(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for ch in encoded.chars() {
+        if ch.is_whitespace() || ch == '=' {
+            continue;
+        }
+        let ch = ch.to_ascii_uppercase();
+        let index = BASE32_ALPHABET.iter().position(|&c| c as char == ch)?;
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if bytes.is_empty() {
+        return None;
+    }
+
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_base32, totp};
+
+    const RFC_SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn totp_rfc6238_vectors() {
+        assert_eq!(totp(RFC_SEED, 59), "287082");
+        assert_eq!(totp(RFC_SEED, 1111111109), "081804");
+        assert_eq!(totp(RFC_SEED, 1111111111), "050471");
+        assert_eq!(totp(RFC_SEED, 1234567890), "005924");
+        assert_eq!(totp(RFC_SEED, 2000000000), "279037");
+    }
+
+    #[test]
+    fn decode_base32_seed() {
+        let decoded = decode_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(decoded.as_deref(), Some(RFC_SEED));
+        let decoded = decode_base32("gezd gnbv gy3t qojq gezd gnbv gy3t qojq");
+        assert_eq!(decoded.as_deref(), Some(RFC_SEED));
+        assert_eq!(decode_base32("MZXW6==="), Some(b"foo".to_vec()));
+    }
+
+    #[test]
+    fn decode_base32_invalid() {
+        assert!(decode_base32("not base32!").is_none());
+        assert!(decode_base32("ABC1").is_none());
+        assert!(decode_base32("").is_none());
+    }
+}
@@ -1,2 +1,134 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 /// 8 byte magic number representing swordswd
 pub const MAGIC_NUMBER: [u8; 8] = [0x73, 0x77, 0x6f, 0x72, 0x64, 0x73, 0x77, 0x64];
+
+/// Seconds elapsed since the Unix epoch
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM:SS UTC`,
+/// or `unknown` for the zero timestamp
+pub fn format_timestamp(timestamp: u64) -> String {
+    if timestamp == 0 {
+        return "unknown".to_owned();
+    }
+
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // Converts days since the epoch into a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Wraps `text` into lines of at most `width` characters, breaking
+/// at spaces where possible and keeping explicit line breaks
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = vec![];
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+            while word.len() > width {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+
+            let line_length = line.chars().count();
+            if line_length > 0 && line_length + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.extend(word);
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Strips a single trailing line break, `\n` or `\r\n`, leaving
+/// any other whitespace in place
+pub fn strip_trailing_newline(text: &str) -> &str {
+    text.strip_suffix('\n')
+        .map(|text| text.strip_suffix('\r').unwrap_or(text))
+        .unwrap_or(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, strip_trailing_newline, wrap_text};
+
+    #[test]
+    fn format_timestamp_zero() {
+        assert_eq!(format_timestamp(0), "unknown");
+    }
+
+    #[test]
+    fn format_timestamp_date() {
+        assert_eq!(format_timestamp(1686787200), "2023-06-15 00:00:00 UTC");
+        assert_eq!(format_timestamp(951825599), "2000-02-29 11:59:59 UTC");
+    }
+
+    #[test]
+    fn wrap_text_at_spaces() {
+        assert_eq!(
+            wrap_text("work account, recovery email X", 12),
+            vec!["work", "account,", "recovery", "email X"]
+        );
+        assert_eq!(wrap_text("short", 12), vec!["short"]);
+    }
+
+    #[test]
+    fn wrap_text_long_words_and_breaks() {
+        assert_eq!(
+            wrap_text("abcdefghij kl\n\nmn", 4),
+            vec!["abcd", "efgh", "ij", "kl", "", "mn"]
+        );
+    }
+
+    #[test]
+    fn strip_single_trailing_newline() {
+        assert_eq!(strip_trailing_newline("hunter2\n"), "hunter2");
+        assert_eq!(strip_trailing_newline("hunter2\r\n"), "hunter2");
+        assert_eq!(strip_trailing_newline("hunter2\n\n"), "hunter2\n");
+        assert_eq!(strip_trailing_newline(" two  words \n"), " two  words ");
+        assert_eq!(strip_trailing_newline("hunter2"), "hunter2");
+    }
+}